@@ -1,3 +1,6 @@
+//! Benchmarks for the tokenizer.
+#![allow(clippy::unwrap_used)]
+
 use criterion::{criterion_group, criterion_main, Criterion};
 
 use std::fs::File;
@@ -5,7 +8,7 @@ use syns::options::Options;
 use syns::tokenizer::*;
 
 fn bench_tokenizer_dict(c: &mut Criterion) {
-    let options = Options::new("txt".as_ref(), &["syns", "foo", "-"]);
+    let options = Options::builder().extension("txt").build().unwrap();
     let filename = "/usr/share/dict/words";
     let mut group = c.benchmark_group("tokenizer dict");
     group.bench_function("tokenizer dict", |b| {
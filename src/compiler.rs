@@ -3,11 +3,12 @@
 use itertools::Itertools;
 use lazy_static::lazy_static;
 use log::debug;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+use crate::error::SynsError;
 use crate::parser::ParsedAstMatcher;
-use crate::tokenizer::StandardTokenType;
+use crate::tokenizer::{NumberBound, StandardTokenType, TokenClass};
 use crate::wrappers::RegexEq;
 
 /// Token matchers.
@@ -28,8 +29,29 @@ pub enum Matcher {
     Any,
     /// Match end of group
     End,
-    /// Match a string literal with a regex.
-    Regex(RegexEq),
+    /// Match a string literal with a regex, and with the prefix if one is given.
+    Regex(Option<String>, RegexEq),
+    /// Match a comment with a regex.
+    Comment(RegexEq),
+    /// Match an identifier or a symbol, and with a regex if one is given.
+    Class(TokenClass, Option<RegexEq>),
+    /// Match an integer or a float within all of the bounds.
+    Number(Vec<NumberBound>),
+    /// Match any token or tree, binding it to a named metavariable. Later uses of the same name
+    /// only match structurally identical trees.
+    Capture(String),
+    /// Match a paren-delimited block containing a match for the nested NFA at any depth.
+    Contains {
+        /// Opening paren of the block, or None for any block.
+        op: Option<StandardTokenType>,
+        /// Starting state index of the nested NFA.
+        start: usize,
+    },
+    /// Match without consuming anything if the nested NFA doesn't match here.
+    Not {
+        /// Starting state index of the nested NFA.
+        start: usize,
+    },
     /// Match anything without consuming the next token.
     Epsilon,
     /// Accept the input.
@@ -52,6 +74,8 @@ pub struct Machine {
     pub initial: usize,
     /// All of the states inside this machine.
     pub states: HashMap<usize, State>,
+    /// Compare identifiers regardless of case. Printed text is left as it was in the source.
+    pub ignore_case: bool,
 }
 
 static INDEX: AtomicUsize = AtomicUsize::new(0);
@@ -88,6 +112,7 @@ impl Machine {
         Machine {
             initial: 0,
             states: collection!((ACCEPT.id, ACCEPT.clone())),
+            ignore_case: false,
         }
     }
 
@@ -165,10 +190,34 @@ impl Machine {
                 start.add_transition(end, Matcher::End);
                 (start.id, end)
             }
-            ParsedAstMatcher::Regex(regex) => {
+            ParsedAstMatcher::Regex(prefix, regex) => {
+                let end = self.state().id;
+                let start = self.state();
+                start.add_transition(end, Matcher::Regex(prefix.clone(), regex.clone()));
+                (start.id, end)
+            }
+            ParsedAstMatcher::Comment(regex) => {
+                let end = self.state().id;
+                let start = self.state();
+                start.add_transition(end, Matcher::Comment(regex.clone()));
+                (start.id, end)
+            }
+            ParsedAstMatcher::Class(class, regex) => {
+                let end = self.state().id;
+                let start = self.state();
+                start.add_transition(end, Matcher::Class(*class, regex.clone()));
+                (start.id, end)
+            }
+            ParsedAstMatcher::Number(bounds) => {
+                let end = self.state().id;
+                let start = self.state();
+                start.add_transition(end, Matcher::Number(bounds.clone()));
+                (start.id, end)
+            }
+            ParsedAstMatcher::Capture(name) => {
                 let end = self.state().id;
                 let start = self.state();
-                start.add_transition(end, Matcher::Regex(regex.clone()));
+                start.add_transition(end, Matcher::Capture(name.clone()));
                 (start.id, end)
             }
             ParsedAstMatcher::Delimited { op, cp, content } => {
@@ -194,6 +243,35 @@ impl Machine {
                 );
                 (delim.id, end)
             }
+            ParsedAstMatcher::Not(matcher) => {
+                let (inner_start, inner_end) = self.compile_state(matcher);
+                self.add_transition(inner_end, ACCEPT.id, Matcher::Epsilon);
+                let end = self.state().id;
+                let start = self.state();
+                start.add_transition(end, Matcher::Not { start: inner_start });
+                (start.id, end)
+            }
+            ParsedAstMatcher::Contains { op, content } => {
+                let inner_start = {
+                    if let Some((first, rest)) = content.split_first() {
+                        let (start, end) = self.link_list(first, rest);
+                        self.add_transition(end, ACCEPT.id, Matcher::Epsilon);
+                        start
+                    } else {
+                        ACCEPT.id
+                    }
+                };
+                let end = self.state().id;
+                let start = self.state();
+                start.add_transition(
+                    end,
+                    Matcher::Contains {
+                        op: op.as_ref().map(|t| t.ty.clone()),
+                        start: inner_start,
+                    },
+                );
+                (start.id, end)
+            }
             ParsedAstMatcher::Nested(content) => {
                 if let Some((first, rest)) = content.split_first() {
                     self.link_list(first, rest)
@@ -213,6 +291,66 @@ impl Machine {
             (state, state)
         }
     }
+
+    /// Identifiers and symbols which appear in the source text of every match.
+    pub fn required_literals(&self) -> BTreeSet<String> {
+        // None stands for "every literal", which is what unexplored states start from
+        fn union(
+            a: Option<BTreeSet<String>>,
+            b: &Option<BTreeSet<String>>,
+        ) -> Option<BTreeSet<String>> {
+            match (a, b) {
+                (Some(mut a), Some(b)) => {
+                    a.extend(b.iter().cloned());
+                    Some(a)
+                }
+                _ => None,
+            }
+        }
+        fn intersect(
+            a: Option<BTreeSet<String>>,
+            b: Option<BTreeSet<String>>,
+        ) -> Option<BTreeSet<String>> {
+            match (a, b) {
+                (Some(a), Some(b)) => Some(a.intersection(&b).cloned().collect()),
+                (None, x) | (x, None) => x,
+            }
+        }
+
+        let ids = self.states.keys().copied().sorted().collect::<Vec<_>>();
+        let mut required: HashMap<usize, Option<BTreeSet<String>>> =
+            ids.iter().map(|id| (*id, None)).collect();
+        loop {
+            let mut changed = false;
+            for id in &ids {
+                let mut res = None;
+                for (matcher, target) in &self.states[id].transitions {
+                    let path = match matcher {
+                        Matcher::Accept => Some(BTreeSet::new()),
+                        Matcher::Token(StandardTokenType::Identifier(s)) if !self.ignore_case => {
+                            union(Some(collection!(s.clone())), &required[target])
+                        }
+                        Matcher::Token(StandardTokenType::Symbol(s)) => {
+                            union(Some(collection!(s.clone())), &required[target])
+                        }
+                        Matcher::Delimited { start, .. } | Matcher::Contains { start, .. } => {
+                            union(required[start].clone(), &required[target])
+                        }
+                        _ => required[target].clone(),
+                    };
+                    res = intersect(res, path);
+                }
+                if required[id] != res {
+                    required.insert(*id, res);
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        required.remove(&self.initial).flatten().unwrap_or_default()
+    }
 }
 
 /// Optimize the state machine by removing unnecessary states and edges.
@@ -293,7 +431,10 @@ pub fn optimize(machine: &mut Machine) {
 
             for (matcher, target_id) in &machine.states[&id].transitions {
                 queue.push(*target_id);
-                if let Matcher::Delimited { start, .. } = matcher {
+                if let Matcher::Delimited { start, .. }
+                | Matcher::Contains { start, .. }
+                | Matcher::Not { start } = matcher
+                {
                     queue.push(*start);
                 }
             }
@@ -339,7 +480,10 @@ pub fn optimize(machine: &mut Machine) {
                 if let Some(&new_target) = remap.get(target) {
                     *target = new_target;
                 }
-                if let Matcher::Delimited { start, .. } = matcher {
+                if let Matcher::Delimited { start, .. }
+                | Matcher::Contains { start, .. }
+                | Matcher::Not { start } = matcher
+                {
                     if let Some(&new_start) = remap.get(start) {
                         *start = new_start;
                     }
@@ -371,14 +515,20 @@ fn normalize(machine: &Machine) -> Machine {
                 .transitions
                 .iter()
                 .map(|(matcher, target)| {
-                    let new_matcher = if let Matcher::Delimited { op, cp, start } = matcher {
-                        Matcher::Delimited {
+                    let new_matcher = match matcher {
+                        Matcher::Delimited { op, cp, start } => Matcher::Delimited {
                             op: op.clone(),
                             cp: cp.clone(),
                             start: id_map[start],
-                        }
-                    } else {
-                        matcher.clone()
+                        },
+                        Matcher::Contains { op, start } => Matcher::Contains {
+                            op: op.clone(),
+                            start: id_map[start],
+                        },
+                        Matcher::Not { start } => Matcher::Not {
+                            start: id_map[start],
+                        },
+                        _ => matcher.clone(),
                     };
                     (new_matcher, id_map[target])
                 })
@@ -396,11 +546,12 @@ fn normalize(machine: &Machine) -> Machine {
     Machine {
         initial: id_map[&machine.initial],
         states,
+        ignore_case: machine.ignore_case,
     }
 }
 
 /// Compile a parsed query into a NFA.
-pub fn compile_query(query: Vec<ParsedAstMatcher>) -> Machine {
+pub fn compile_query(query: Vec<ParsedAstMatcher>) -> Result<Machine, SynsError> {
     debug!("Compiling query");
     let mut machine = Machine::new();
     let (start, end) = machine.parse_query_ast(&query);
@@ -408,7 +559,7 @@ pub fn compile_query(query: Vec<ParsedAstMatcher>) -> Machine {
     machine
         .states
         .get_mut(&end)
-        .expect("Internal error when compiling query")
+        .ok_or_else(|| SynsError::Internal(format!("missing final state {}", end)))?
         .add_transition(ACCEPT.id, Matcher::Epsilon);
 
     // TODO: this is a bit dumb
@@ -416,7 +567,7 @@ pub fn compile_query(query: Vec<ParsedAstMatcher>) -> Machine {
         optimize(&mut machine);
     }
 
-    normalize(&machine)
+    Ok(normalize(&machine))
 }
 
 #[cfg(test)]
@@ -426,9 +577,30 @@ mod tests {
     use crate::parser::parse_query;
 
     fn compile(query: &str) -> Machine {
-        let options = Options::new("js".as_ref(), &["syns", query, "-"]);
-        let (parsed, _) = parse_query(query.as_bytes(), &options);
-        compile_query(parsed)
+        let options = Options::new("js".as_ref(), &["syns", query, "-"]).expect("options");
+        let (parsed, _) = parse_query(query.as_bytes(), &options).expect("query");
+        compile_query(parsed).expect("query")
+    }
+
+    #[test]
+    fn required_literals() {
+        let literals = |query: &str| {
+            compile(query)
+                .required_literals()
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(literals("sprintf()"), vec!["sprintf"]);
+        assert_eq!(literals("a == b"), vec!["==", "a", "b"]);
+        assert_eq!(literals(r"a \| b"), Vec::<String>::new());
+        assert_eq!(literals(r"foo \(a \| a b\)"), vec!["a", "foo"]);
+        assert_eq!(literals(r"x\* y\+ z\?"), vec!["y"]);
+        assert_eq!(literals(r#"\!foo bar 1 "s""#), vec!["bar"]);
+        assert_eq!(literals(r"f(g(\:x)) \has{h}"), vec!["f", "g", "h"]);
+        assert_eq!(literals(r"\."), Vec::<String>::new());
+        let mut machine = compile("a == b");
+        machine.ignore_case = true;
+        assert_eq!(machine.required_literals().into_iter().collect::<Vec<_>>(), vec!["=="]);
     }
 
     #[test]
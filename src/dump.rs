@@ -0,0 +1,234 @@
+//! Printing how a file was tokenized and parsed, to find out why a query doesn't match.
+
+use std::ops::RangeInclusive;
+
+use crate::parser::Ast;
+use crate::psi::{PeekableStringIterator, Span};
+use crate::tokenizer::{StandardToken, StandardTokenType};
+
+/// Parse a line range such as `12` or `12-20`.
+/// ```
+/// use syns::dump::parse_line_range;
+/// assert_eq!(parse_line_range("12"), Some(12..=12));
+/// assert_eq!(parse_line_range("12-20"), Some(12..=20));
+/// assert_eq!(parse_line_range("20-12"), None);
+/// ```
+pub fn parse_line_range(s: &str) -> Option<RangeInclusive<usize>> {
+    let (start, end) = s.split_once('-').unwrap_or((s, s));
+    let start = start.trim().parse().ok()?;
+    let end = end.trim().parse().ok()?;
+    (start <= end).then_some(start..=end)
+}
+
+/// Type and text of a token, eg. `Identifier printf` or `String f"{x}"`. Strings, regexes and
+/// comments are shown as written in the source, without adding any escapes.
+fn describe_token(token: &StandardToken, iter: &PeekableStringIterator) -> String {
+    let source = || iter.get_content_between(token.span);
+    match &token.ty {
+        StandardTokenType::Identifier(s) => format!("Identifier {}", s),
+        StandardTokenType::Symbol(s) => format!("Symbol {}", s),
+        StandardTokenType::Integer(i) => format!("Integer {}", i),
+        StandardTokenType::Float(f) => format!("Float {}", **f),
+        StandardTokenType::StringLiteral { .. } => format!("String {}", source()),
+        StandardTokenType::Regex(_) => format!("Regex {}", source()),
+        StandardTokenType::Comment(_) => format!("Comment {}", source()),
+        StandardTokenType::Indent => "Indent".to_string(),
+        StandardTokenType::Dedent => "Dedent".to_string(),
+    }
+}
+
+/// Position of `span` as `line:column-line:column [bytes]`.
+fn describe_span(span: Span, iter: &PeekableStringIterator) -> String {
+    let (start_line, end_line) = iter.get_line_information(span);
+    let (start_column, end_column) = iter.get_column_information(span);
+    format!(
+        "{}:{}-{}:{} [{}..{}]",
+        start_line,
+        start_column,
+        end_line,
+        end_column,
+        span.lo,
+        iter.get_span_end(span)
+    )
+}
+
+/// Is any part of `span` on one of `lines`?
+fn on_lines(
+    span: Span,
+    iter: &PeekableStringIterator,
+    lines: &Option<RangeInclusive<usize>>,
+) -> bool {
+    match lines {
+        Some(lines) => {
+            let (start, end) = iter.get_line_information(span);
+            start <= *lines.end() && end >= *lines.start()
+        }
+        None => true,
+    }
+}
+
+/// List `tokens` as produced by the tokenizer, before the parser splits any of them (eg. `>>`
+/// closing two type parameter lists), one per line, keeping only those on `lines` if given.
+pub fn tokens(
+    tokens: &[StandardToken],
+    iter: &PeekableStringIterator,
+    lines: Option<RangeInclusive<usize>>,
+) -> String {
+    tokens
+        .iter()
+        .filter(|token| on_lines(token.span, iter, &lines))
+        .map(|token| {
+            format!(
+                "{} {}\n",
+                describe_span(token.span, iter),
+                describe_token(token, iter)
+            )
+        })
+        .collect()
+}
+
+fn write_tree(
+    ast: &[Ast],
+    iter: &PeekableStringIterator,
+    lines: &Option<RangeInclusive<usize>>,
+    depth: usize,
+    res: &mut String,
+) {
+    for node in ast.iter().filter(|node| on_lines(node.span(), iter, lines)) {
+        let indent = "  ".repeat(depth);
+        let position = describe_span(node.span(), iter);
+        match node {
+            Ast::Token(token) => {
+                res.push_str(&format!(
+                    "{}{} {}\n",
+                    indent,
+                    position,
+                    describe_token(token, iter)
+                ));
+            }
+            Ast::Delimited { op, cp, content } => {
+                let close = match cp {
+                    Some(cp) => describe_token(cp, iter),
+                    None => "(unclosed)".to_string(),
+                };
+                res.push_str(&format!(
+                    "{}{} Block {} ... {}\n",
+                    indent,
+                    position,
+                    describe_token(op, iter),
+                    close
+                ));
+                write_tree(content, iter, lines, depth + 1, res);
+            }
+        }
+    }
+}
+
+/// Show `ast` as an indented tree, one node per line with the contents of each block indented
+/// below it, keeping only the nodes on `lines` if given.
+pub fn tree(
+    ast: &[Ast],
+    iter: &PeekableStringIterator,
+    lines: Option<RangeInclusive<usize>>,
+) -> String {
+    let mut res = String::new();
+    write_tree(ast, iter, &lines, 0, &mut res);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::Options;
+    use crate::parser::parse_file;
+    use crate::tokenizer::tokenize;
+
+    fn options(extension: &str) -> Options {
+        Options::new(extension.as_ref(), &["syns", "query", "-"]).expect("options")
+    }
+
+    fn parse(extension: &str, file: &str) -> (Vec<Ast>, PeekableStringIterator) {
+        parse_file(file.as_bytes(), &options(extension))
+    }
+
+    fn tokenize_str(extension: &str, file: &str) -> (Vec<StandardToken>, PeekableStringIterator) {
+        tokenize("test", file.as_bytes(), &options(extension))
+    }
+
+    #[test]
+    fn dump_tokens() {
+        let (all, iter) = tokenize_str("c", "f(1, \"a\");\nx = 1.5; // c");
+        assert_eq!(
+            tokens(&all, &iter, None),
+            "1:1-1:2 [0..1] Identifier f\n\
+             1:2-1:3 [1..2] Symbol (\n\
+             1:3-1:4 [2..3] Integer 1\n\
+             1:4-1:5 [3..4] Symbol ,\n\
+             1:6-1:9 [5..8] String \"a\"\n\
+             1:9-1:10 [8..9] Symbol )\n\
+             1:10-1:11 [9..10] Symbol ;\n\
+             2:1-2:2 [11..12] Identifier x\n\
+             2:3-2:4 [13..14] Symbol =\n\
+             2:5-2:8 [15..18] Float 1.5\n\
+             2:8-2:9 [18..19] Symbol ;\n\
+             2:10-2:14 [20..24] Comment // c\n"
+        );
+        assert_eq!(
+            tokens(&all, &iter, Some(2..=2)).lines().next(),
+            Some("2:1-2:2 [11..12] Identifier x")
+        );
+
+        // Shown as written, not with Rust escapes
+        let (all, iter) = tokenize_str("py", "s = r'a\\n' + \"\\\"\"  # \\");
+        assert_eq!(
+            tokens(&all, &iter, None),
+            "1:1-1:2 [0..1] Identifier s\n\
+             1:3-1:4 [2..3] Symbol =\n\
+             1:5-1:11 [4..10] String r'a\\n'\n\
+             1:12-1:13 [11..12] Symbol +\n\
+             1:14-1:18 [13..17] String \"\\\"\"\n\
+             1:20-1:23 [19..22] Comment # \\\n"
+        );
+    }
+
+    #[test]
+    fn dump_tokens_before_parsing() {
+        // The tree splits `>>` to close both type parameter lists
+        let file = "let v: Vec<Vec<u8>> = x;";
+        let (all, iter) = tokenize_str("ts", file);
+        let dumped = tokens(&all, &iter, None);
+        assert!(
+            dumped.contains("1:18-1:20 [17..19] Symbol >>\n"),
+            "{}",
+            dumped
+        );
+        let (ast, iter) = parse("ts", file);
+        let dumped = tree(&ast, &iter, None);
+        assert!(!dumped.contains(">>"), "{}", dumped);
+    }
+
+    #[test]
+    fn dump_tree() {
+        let (ast, iter) = parse("ts", "let v: Vec<u8> = {\n  f(\n};");
+        assert_eq!(
+            tree(&ast, &iter, None),
+            "1:1-1:4 [0..3] Identifier let\n\
+             1:5-1:6 [4..5] Identifier v\n\
+             1:6-1:7 [5..6] Symbol :\n\
+             1:8-1:11 [7..10] Identifier Vec\n\
+             1:11-1:15 [10..14] Block Symbol < ... Symbol >\n\
+             \x20 1:12-1:14 [11..13] Identifier u8\n\
+             1:16-1:17 [15..16] Symbol =\n\
+             1:18-3:2 [17..25] Block Symbol { ... Symbol }\n\
+             \x20 2:3-2:4 [21..22] Identifier f\n\
+             \x20 2:4-2:5 [22..23] Block Symbol ( ... (unclosed)\n\
+             3:2-3:3 [25..26] Symbol ;\n"
+        );
+        assert_eq!(
+            tree(&ast, &iter, Some(2..=2)),
+            "1:18-3:2 [17..25] Block Symbol { ... Symbol }\n\
+             \x20 2:3-2:4 [21..22] Identifier f\n\
+             \x20 2:4-2:5 [22..23] Block Symbol ( ... (unclosed)\n"
+        );
+    }
+}
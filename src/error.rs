@@ -0,0 +1,176 @@
+//! Errors in queries and configuration files.
+
+use std::fmt;
+
+use crate::psi::Span;
+
+/// Error found while loading the language definitions, or while tokenizing, parsing or compiling
+/// a query.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SynsError {
+    /// The query ended right after a `\`.
+    UnexpectedEnd {
+        /// Position of the `\`.
+        span: Span,
+    },
+    /// `\` followed by something which isn't a query command, eg. `\x`.
+    UnknownCommand {
+        /// The command without the `\`.
+        name: String,
+        /// Position of the command.
+        span: Span,
+    },
+    /// `\:` without a metavariable name.
+    MissingCaptureName {
+        /// Position of the `\:`.
+        span: Span,
+    },
+    /// Invalid regex in `\"..."`, `\#"..."`, `\i"..."`, `\sym"..."` or `\s"..."`.
+    InvalidRegex {
+        /// Error from the regex crate.
+        message: String,
+        /// Position of the regex.
+        span: Span,
+    },
+    /// Bound of `\n` which isn't a number, eg. `\n>x`.
+    InvalidNumber {
+        /// The bound as written.
+        value: String,
+        /// Position of the `\n` command.
+        span: Span,
+    },
+    /// The query couldn't be read.
+    Io(String),
+    /// A configuration file couldn't be read or is invalid.
+    Config(String),
+    /// A language which isn't built in or defined in a configuration file.
+    UnknownLanguage(String),
+    /// A bug in syns.
+    Internal(String),
+}
+
+impl SynsError {
+    /// Position of the error in the query string, if it has one.
+    pub fn span(&self) -> Option<Span> {
+        match self {
+            SynsError::UnexpectedEnd { span }
+            | SynsError::UnknownCommand { span, .. }
+            | SynsError::MissingCaptureName { span }
+            | SynsError::InvalidRegex { span, .. }
+            | SynsError::InvalidNumber { span, .. } => Some(*span),
+            SynsError::Io(_)
+            | SynsError::Config(_)
+            | SynsError::UnknownLanguage(_)
+            | SynsError::Internal(_) => None,
+        }
+    }
+
+    /// Describe the error, followed by the line of `query` it's on with the error marked with
+    /// carets.
+    ///
+    /// ```
+    /// use syns::error::SynsError;
+    /// use syns::psi::Span;
+    /// let error = SynsError::UnknownCommand { name: "x".to_string(), span: Span { lo: 4, hi: 5 } };
+    /// assert_eq!(
+    ///     error.render("foo(\\x)"),
+    ///     "Unknown query command \\x\n  |\n1 | foo(\\x)\n  |     ^^"
+    /// );
+    /// ```
+    pub fn render(&self, query: &str) -> String {
+        match self.span() {
+            Some(span) => format!("{}\n{}", self, annotate(query, span)),
+            None => self.to_string(),
+        }
+    }
+}
+
+/// Show the line of `query` containing `span`, with the span marked with carets.
+pub fn annotate(query: &str, span: Span) -> String {
+    let lo = span.lo.min(query.len());
+    let line_start = query[..lo].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = query[lo..].find('\n').map_or(query.len(), |i| lo + i);
+    let line = &query[line_start..line_end];
+    let line_number = query[..lo].matches('\n').count() + 1;
+    let column = query[line_start..lo].chars().count();
+    // Spans include their last character
+    let hi = span.hi.clamp(lo, line_end);
+    let end = query
+        .get(hi..)
+        .and_then(|s| s.chars().next())
+        .map_or(hi, |c| hi + c.len_utf8())
+        .min(line_end);
+    let width = query.get(lo..end).map_or(0, |s| s.chars().count()).max(1);
+    let margin = " ".repeat(line_number.to_string().len());
+    format!(
+        "{} |\n{} | {}\n{} | {}{}",
+        margin,
+        line_number,
+        line,
+        margin,
+        " ".repeat(column),
+        "^".repeat(width)
+    )
+}
+
+impl fmt::Display for SynsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SynsError::UnexpectedEnd { .. } => write!(f, "Unexpected end of query after \\"),
+            SynsError::UnknownCommand { name, .. } => write!(f, "Unknown query command \\{}", name),
+            SynsError::MissingCaptureName { .. } => {
+                write!(f, "Missing metavariable name after \\:")
+            }
+            SynsError::InvalidRegex { message, .. } => write!(f, "Invalid regex: {}", message),
+            SynsError::InvalidNumber { value, .. } => {
+                write!(f, "Invalid number in \\n: '{}'", value)
+            }
+            SynsError::Io(message) => write!(f, "Failed to read query: {}", message),
+            SynsError::Config(message) => write!(f, "{}", message),
+            SynsError::UnknownLanguage(name) => {
+                write!(f, "Unknown language '{}', see --lang for the list", name)
+            }
+            SynsError::Internal(message) => write!(f, "Internal error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for SynsError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(lo: usize, hi: usize) -> Span {
+        Span { lo, hi }
+    }
+
+    #[test]
+    fn render_multiline() {
+        let error = SynsError::MissingCaptureName { span: span(7, 8) };
+        assert_eq!(
+            error.render("foo(\n  \\:)"),
+            "Missing metavariable name after \\:\n  |\n2 |   \\:)\n  |   ^^"
+        );
+    }
+
+    #[test]
+    fn render_at_end() {
+        let error = SynsError::UnexpectedEnd { span: span(3, 3) };
+        assert_eq!(
+            error.render("ab \\"),
+            "Unexpected end of query after \\\n  |\n1 | ab \\\n  |    ^"
+        );
+        let error = SynsError::UnexpectedEnd { span: span(9, 9) };
+        assert_eq!(
+            error.render("ab"),
+            "Unexpected end of query after \\\n  |\n1 | ab\n  |   ^"
+        );
+    }
+
+    #[test]
+    fn render_without_span() {
+        let error = SynsError::Io("broken pipe".to_string());
+        assert_eq!(error.render("foo"), "Failed to read query: broken pipe");
+    }
+}
@@ -0,0 +1,445 @@
+//! Human-readable descriptions of queries, and warnings about suspicious queries.
+
+use std::collections::HashSet;
+
+use crate::error::{annotate, SynsError};
+use crate::options::Options;
+use crate::parser::{parse_query, ParsedAstMatcher};
+use crate::psi::Span;
+use crate::tokenizer::{
+    tokenize_query, Comparison, NumberBound, QueryToken, QueryTokenType, SpecialTokenType,
+    StandardToken, StandardTokenType, TokenClass,
+};
+
+/// Suspicious part of a query, which probably doesn't do what was intended.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QueryWarning {
+    /// What's wrong.
+    pub message: String,
+    /// Position of the suspicious part in the query string.
+    pub span: Span,
+}
+
+impl QueryWarning {
+    /// Describe the warning, followed by the line of `query` it's on with the warning marked
+    /// with carets.
+    pub fn render(&self, query: &str) -> String {
+        format!("Warning: {}\n{}", self.message, annotate(query, self.span))
+    }
+}
+
+/// What a query matches.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Explanation {
+    /// Description of the whole query.
+    pub description: String,
+    /// Warnings about the query.
+    pub warnings: Vec<QueryWarning>,
+}
+
+impl Explanation {
+    /// The description followed by the warnings, which point into `query`.
+    pub fn render(&self, query: &str) -> String {
+        self.warnings
+            .iter()
+            .fold(self.description.clone(), |res, warning| {
+                format!("{}\n{}", res, warning.render(query))
+            })
+    }
+}
+
+/// Describe what `pattern` matches with the language settings in `options`.
+///
+/// ```
+/// use syns::explain::explain;
+/// use syns::options::Options;
+/// let options = Options::new("js".as_ref(), &["syns", "printf(\\.\\$)", "-"]).unwrap();
+/// let explanation = explain(&options.query, &options).unwrap();
+/// assert_eq!(
+///     explanation.description,
+///     "identifier `printf`, then a `(...)` block whose contents are: any tree, then end of block"
+/// );
+/// ```
+pub fn explain(pattern: &str, options: &Options) -> Result<Explanation, SynsError> {
+    let (parsed, _) = parse_query(pattern.as_bytes(), options)?;
+    let description = describe_list(&parsed, &mut HashSet::new());
+    Ok(Explanation {
+        description: if description.is_empty() {
+            "nothing".to_string()
+        } else {
+            description
+        },
+        warnings: lint(pattern, options)?,
+    })
+}
+
+/// Find parts of `pattern` which are valid but probably mistakes, such as `\+` with nothing
+/// before it.
+///
+/// ```
+/// use syns::explain::lint;
+/// use syns::options::Options;
+/// let options = Options::new("js".as_ref(), &["syns", "foo", "-"]).unwrap();
+/// assert!(lint("foo(\\.\\+)", &options).unwrap().is_empty());
+/// assert_eq!(lint("foo(\\+)", &options).unwrap().len(), 1);
+/// ```
+pub fn lint(pattern: &str, options: &Options) -> Result<Vec<QueryWarning>, SynsError> {
+    let (tokens, _) = tokenize_query(pattern.as_bytes(), options)?;
+    let mut warnings = Vec::new();
+    lint_tokens(&tokens, options, &mut warnings);
+    Ok(warnings)
+}
+
+fn lint_tokens(tokens: &[QueryToken], options: &Options, warnings: &mut Vec<QueryWarning>) {
+    fn warn(warnings: &mut Vec<QueryWarning>, message: String, span: Span) {
+        warnings.push(QueryWarning { message, span });
+    }
+    for (i, token) in tokens.iter().enumerate() {
+        let prev = i.checked_sub(1).and_then(|i| tokens.get(i));
+        let next = tokens.get(i + 1);
+        let QueryTokenType::Special(special) = &token.ty else {
+            continue;
+        };
+        match special {
+            SpecialTokenType::Star | SpecialTokenType::Plus | SpecialTokenType::QuestionMark
+                if !ends_matcher(prev, options) =>
+            {
+                warn(
+                    warnings,
+                    format!(
+                        "Nothing before {} to repeat, so it repeats \\.",
+                        command_name(special)
+                    ),
+                    token.span,
+                );
+            }
+            SpecialTokenType::Or => {
+                if !ends_matcher(prev, options) {
+                    warn(
+                        warnings,
+                        "Nothing before \\|, so the left side matches any single tree".to_string(),
+                        token.span,
+                    );
+                }
+                if !starts_matcher(next, options) {
+                    warn(
+                        warnings,
+                        "Nothing after \\|, so the right side matches without consuming anything"
+                            .to_string(),
+                        token.span,
+                    );
+                }
+            }
+            SpecialTokenType::Not | SpecialTokenType::Has if !starts_matcher(next, options) => {
+                warn(
+                    warnings,
+                    format!(
+                        "Nothing after {} to apply it to, so it's ignored",
+                        command_name(special)
+                    ),
+                    token.span,
+                );
+            }
+            SpecialTokenType::Number(bounds) if bounds.iter().any(expects_sign) => {
+                warn(
+                    warnings,
+                    "Numbers are matched without a leading -, which is a separate symbol, eg. \
+                     -\\n>2 matches numbers below -2"
+                        .to_string(),
+                    token.span,
+                );
+            }
+            SpecialTokenType::Nested(inner) => lint_tokens(inner, options, warnings),
+            _ => {}
+        }
+    }
+}
+
+/// Is `bound` written for a negative number, eg. `<0` or `=-2`?
+fn expects_sign(bound: &NumberBound) -> bool {
+    *bound.value < 0.0 || (bound.op == Comparison::Less && *bound.value == 0.0)
+}
+
+/// Does the token `prev` end a matcher which a following `\*` or `\|` can apply to?
+fn ends_matcher(prev: Option<&QueryToken>, options: &Options) -> bool {
+    match prev.map(|token| &token.ty) {
+        None => false,
+        Some(QueryTokenType::Standard(StandardTokenType::Symbol(s))) => !options.is_open_paren(s),
+        Some(QueryTokenType::Special(
+            SpecialTokenType::Or | SpecialTokenType::Not | SpecialTokenType::Has,
+        )) => false,
+        Some(_) => true,
+    }
+}
+
+/// Does the token `next` start a matcher which a preceding `\!` or `\|` can apply to?
+fn starts_matcher(next: Option<&QueryToken>, options: &Options) -> bool {
+    match next.map(|token| &token.ty) {
+        None => false,
+        Some(QueryTokenType::Standard(StandardTokenType::Symbol(s))) => !options.is_close_paren(s),
+        // A repetition with nothing before it repeats `\.`, which is warned about separately
+        Some(QueryTokenType::Special(SpecialTokenType::Or)) => false,
+        Some(_) => true,
+    }
+}
+
+fn command_name(special: &SpecialTokenType) -> &'static str {
+    match special {
+        SpecialTokenType::Star => "\\*",
+        SpecialTokenType::Plus => "\\+",
+        SpecialTokenType::QuestionMark => "\\?",
+        SpecialTokenType::Not => "\\!",
+        SpecialTokenType::Has => "\\has",
+        _ => "\\",
+    }
+}
+
+/// Source text of a token, eg. `printf` or `"%d"`.
+fn token_text(ty: &StandardTokenType) -> String {
+    match ty {
+        StandardTokenType::Identifier(s) | StandardTokenType::Symbol(s) => s.clone(),
+        StandardTokenType::Integer(i) => i.to_string(),
+        StandardTokenType::Float(f) => f.to_string(),
+        StandardTokenType::StringLiteral { prefix, content } => {
+            format!("{}\"{}\"", prefix, content)
+        }
+        StandardTokenType::Regex(r) => format!("/{}/", r),
+        StandardTokenType::Comment(c) => c.clone(),
+        StandardTokenType::Indent => "indent".to_string(),
+        StandardTokenType::Dedent => "dedent".to_string(),
+    }
+}
+
+fn describe_token(ty: &StandardTokenType) -> String {
+    let kind = match ty {
+        StandardTokenType::Identifier(_) => "identifier",
+        StandardTokenType::Symbol(_) => "symbol",
+        StandardTokenType::Integer(_) | StandardTokenType::Float(_) => "number",
+        StandardTokenType::StringLiteral { .. } => "string",
+        StandardTokenType::Regex(_) => "regex literal",
+        StandardTokenType::Comment(_) => "comment",
+        StandardTokenType::Indent => return "start of an indented block".to_string(),
+        StandardTokenType::Dedent => return "end of an indented block".to_string(),
+    };
+    format!("{} `{}`", kind, token_text(ty))
+}
+
+/// Name of a block such as `(...)` or `do...end`.
+fn block_name(op: &StandardToken, cp: Option<&StandardToken>) -> String {
+    format!(
+        "`{}...{}`",
+        token_text(&op.ty),
+        cp.map(|cp| token_text(&cp.ty)).unwrap_or_default()
+    )
+}
+
+fn describe_list(matchers: &[ParsedAstMatcher], captures: &mut HashSet<String>) -> String {
+    matchers
+        .iter()
+        .map(|matcher| describe(matcher, captures))
+        .collect::<Vec<_>>()
+        .join(", then ")
+}
+
+/// Describe a group of matchers as a single item, in parentheses if it has several parts.
+fn describe_group(matcher: &ParsedAstMatcher, captures: &mut HashSet<String>) -> String {
+    match matcher {
+        ParsedAstMatcher::Nested(list) if list.is_empty() => "nothing".to_string(),
+        ParsedAstMatcher::Nested(list) if list.len() > 1 => {
+            format!("({})", describe_list(list, captures))
+        }
+        _ => describe(matcher, captures),
+    }
+}
+
+fn describe(matcher: &ParsedAstMatcher, captures: &mut HashSet<String>) -> String {
+    match matcher {
+        ParsedAstMatcher::Token(token) => describe_token(&token.ty),
+        ParsedAstMatcher::Delimited { op, cp, content } => {
+            let block = block_name(op, cp.as_ref());
+            if content.is_empty() {
+                format!("a {} block with anything inside", block)
+            } else if matches!(content.last(), Some(ParsedAstMatcher::End)) {
+                format!(
+                    "a {} block whose contents are: {}",
+                    block,
+                    describe_list(content, captures)
+                )
+            } else {
+                format!(
+                    "a {} block whose contents start with: {}",
+                    block,
+                    describe_list(content, captures)
+                )
+            }
+        }
+        ParsedAstMatcher::Any => "any tree".to_string(),
+        ParsedAstMatcher::End => "end of block".to_string(),
+        ParsedAstMatcher::Plus(inner) => {
+            format!("one or more of {}", describe_group(inner, captures))
+        }
+        ParsedAstMatcher::Star(inner) => {
+            format!("zero or more of {}", describe_group(inner, captures))
+        }
+        ParsedAstMatcher::QuestionMark(inner) => {
+            format!("optionally {}", describe_group(inner, captures))
+        }
+        ParsedAstMatcher::Or(a, b) => format!(
+            "either {} or {}",
+            describe_group(a, captures),
+            describe_group(b, captures)
+        ),
+        ParsedAstMatcher::Nested(list) => describe_list(list, captures),
+        ParsedAstMatcher::Regex(prefix, re) => match prefix {
+            Some(prefix) => format!("string with prefix `{}` matching /{}/", prefix, re.as_str()),
+            None => format!("string matching /{}/", re.as_str()),
+        },
+        ParsedAstMatcher::Comment(re) => format!("comment matching /{}/", re.as_str()),
+        ParsedAstMatcher::Class(class, re) => {
+            let kind = match class {
+                TokenClass::Identifier => "identifier",
+                TokenClass::Symbol => "symbol",
+            };
+            match re {
+                Some(re) => format!("{} matching /{}/", kind, re.as_str()),
+                None => format!("any {}", kind),
+            }
+        }
+        ParsedAstMatcher::Number(bounds) if bounds.is_empty() => "any number".to_string(),
+        ParsedAstMatcher::Number(bounds) => format!(
+            "number {}",
+            bounds
+                .iter()
+                .map(|bound| format!("{} {}", bound.op.as_str(), *bound.value))
+                .collect::<Vec<_>>()
+                .join(" and ")
+        ),
+        ParsedAstMatcher::Capture(name) => {
+            if captures.insert(name.clone()) {
+                format!("any tree as `{}`", name)
+            } else {
+                format!("the same tree as `{}`", name)
+            }
+        }
+        ParsedAstMatcher::Not(inner) => format!(
+            "a position not followed by {}",
+            describe_group(inner, captures)
+        ),
+        ParsedAstMatcher::Contains { op, content } => {
+            let block = match op {
+                Some(op) => format!("a {} block", block_name(op, None)),
+                None => "a block".to_string(),
+            };
+            if content.is_empty() {
+                block
+            } else {
+                format!(
+                    "{} containing at any depth: {}",
+                    block,
+                    describe_list(content, captures)
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> Options {
+        Options::new("js".as_ref(), &["syns", "query", "-"]).expect("options")
+    }
+
+    fn description(pattern: &str) -> String {
+        explain(pattern, &options()).expect("query").description
+    }
+
+    fn warnings(pattern: &str) -> Vec<(String, usize)> {
+        lint(pattern, &options())
+            .expect("query")
+            .into_iter()
+            .map(|w| (w.message, w.span.lo))
+            .collect()
+    }
+
+    #[test]
+    fn describe_matchers() {
+        assert_eq!(description(""), "nothing");
+        assert_eq!(
+            description(r"foo() \:x == \:x"),
+            "identifier `foo`, then a `(...)` block with anything inside, then any tree as `x`, \
+             then symbol `==`, then the same tree as `x`"
+        );
+        assert_eq!(
+            description(r"a\+ \(b 1\)\* \!c\?"),
+            "one or more of identifier `a`, then zero or more of (identifier `b`, then number \
+             `1`), then optionally a position not followed by identifier `c`"
+        );
+        assert_eq!(
+            description(r"x \| y z"),
+            "either identifier `x` or (identifier `y`, then identifier `z`)"
+        );
+        assert_eq!(
+            description(r#"\i"^get" \sym \n>=1<10 \s"x" \#"TODO" \has{f}"#),
+            "identifier matching /^get/, then any symbol, then number >= 1 and < 10, then \
+             string matching /x/, then comment matching /TODO/, then a `{...` block containing \
+             at any depth: identifier `f`"
+        );
+    }
+
+    #[test]
+    fn lint_warnings() {
+        assert_eq!(warnings(r"a\+ b\* \(c \| d\) \!e"), vec![]);
+        assert_eq!(
+            warnings(r"\+ f(\*)"),
+            vec![
+                (
+                    "Nothing before \\+ to repeat, so it repeats \\.".to_string(),
+                    0
+                ),
+                (
+                    "Nothing before \\* to repeat, so it repeats \\.".to_string(),
+                    5
+                ),
+            ]
+        );
+        assert_eq!(
+            warnings(r"\(\| a\) (b \|)"),
+            vec![
+                (
+                    "Nothing before \\|, so the left side matches any single tree".to_string(),
+                    2
+                ),
+                (
+                    "Nothing after \\|, so the right side matches without consuming anything"
+                        .to_string(),
+                    12
+                ),
+            ]
+        );
+        assert_eq!(
+            warnings(r"a \| \+"),
+            vec![(
+                "Nothing before \\+ to repeat, so it repeats \\.".to_string(),
+                5
+            )]
+        );
+        assert_eq!(
+            warnings(r"f(\n<0) g(-\n>2)"),
+            vec![(
+                "Numbers are matched without a leading -, which is a separate symbol, eg. \
+                 -\\n>2 matches numbers below -2"
+                    .to_string(),
+                2
+            )]
+        );
+        assert_eq!(
+            warnings(r"a \!"),
+            vec![(
+                "Nothing after \\! to apply it to, so it's ignored".to_string(),
+                2
+            )]
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! Interactive prompt for trying queries on files which are only read and parsed once.
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+
+use termcolor::WriteColor;
+
+use crate::dump;
+use crate::explain;
+use crate::options::Options;
+use crate::parser::{parse_tokens, Ast};
+use crate::psi::PeekableStringIterator;
+use crate::query::Query;
+use crate::render_machine;
+use crate::run;
+use crate::tokenizer::{tokenize, StandardToken};
+
+const HELP: &str = "\
+Type a query to print its matches in the loaded files, or one of these commands:
+  :tokens [LINES]   Print the tokens of each file, or only those on LINES (eg. 12 or 12-20)
+  :tree [LINES]     Print the parsed tree of each file, or only the parts on LINES
+  :machine [QUERY]  Print the state machine of QUERY, or of the last query, as a dot graph
+  :help             Print this help
+  :quit             Exit
+Other lines are queries, including those starting with : such as Ruby's :name \\.
+Start a line with a space to search for a query which is also a command, eg. \" :tree\".";
+
+/// A file parsed once and searched by every query.
+struct LoadedFile {
+    path: PathBuf,
+    /// Options for the language of the file.
+    options: Options,
+    /// Tokens before parsing, for `:tokens`.
+    tokens: Vec<StandardToken>,
+    ast: Vec<Ast>,
+    iter: PeekableStringIterator,
+}
+
+/// Parsed files and the state of an interactive session.
+pub struct Session {
+    files: Vec<LoadedFile>,
+    /// Last query which compiled, used by `:machine`.
+    last_query: Option<String>,
+}
+
+impl Session {
+    /// Read and parse `paths` once, using the defaults for the language of each file together
+    /// with the command line `args`.
+    pub fn load<S: AsRef<OsStr>, P: AsRef<Path>>(
+        options: &Options,
+        args: &[S],
+        paths: &[P],
+    ) -> io::Result<Session> {
+        let mut files = Vec::new();
+        for path in paths {
+            let path = path.as_ref();
+            let content = fs::read(path)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+            // Files without an extension are plain text unless detected otherwise
+            let language = options
+                .detect_language(path, &content)
+                .map_err(io::Error::other)?
+                .or_else(|| path.extension().is_none().then_some("plain"));
+            let options = Options::for_language(language, args).map_err(io::Error::other)?;
+            let (tokens, iter) = tokenize(&path.to_string_lossy(), content.as_slice(), &options);
+            let ast = parse_tokens(tokens.clone(), &options);
+            files.push(LoadedFile {
+                path: path.to_path_buf(),
+                options,
+                tokens,
+                ast,
+                iter,
+            });
+        }
+        Ok(Session {
+            files,
+            last_query: None,
+        })
+    }
+
+    /// Run a single line of input, writing the result to `out`. Returns false once the session
+    /// should end.
+    pub fn execute<W: WriteColor>(&mut self, line: &str, out: &mut W) -> io::Result<bool> {
+        let escaped = line.starts_with([' ', '\t']);
+        let line = line.trim();
+        let (command, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let arg = arg.trim();
+        match command {
+            "" => {}
+            _ if escaped => self.search(line, out)?,
+            ":q" | ":quit" | ":exit" => return Ok(false),
+            ":h" | ":help" => writeln!(out, "{}", HELP)?,
+            ":tokens" | ":tree" => {
+                let lines = if arg.is_empty() {
+                    None
+                } else {
+                    match dump::parse_line_range(arg) {
+                        Some(lines) => Some(lines),
+                        None => {
+                            writeln!(out, "Err: Invalid line range '{}'", arg)?;
+                            return Ok(true);
+                        }
+                    }
+                };
+                for file in &self.files {
+                    if self.files.len() > 1 {
+                        writeln!(out, "{}:", file.path.display())?;
+                    }
+                    let dumped = if command == ":tokens" {
+                        dump::tokens(&file.tokens, &file.iter, lines.clone())
+                    } else {
+                        dump::tree(&file.ast, &file.iter, lines.clone())
+                    };
+                    write!(out, "{}", dumped)?;
+                }
+            }
+            ":machine" => {
+                let pattern = if arg.is_empty() {
+                    self.last_query.clone()
+                } else {
+                    Some(arg.to_string())
+                };
+                match pattern {
+                    Some(pattern) => {
+                        if let Some(query) = self.compile(&pattern, 0, out)? {
+                            writeln!(out, "{}", render_machine::to_dot_graph(&query.machine))?;
+                        }
+                    }
+                    None => writeln!(out, "Err: No query yet, use :machine QUERY")?,
+                }
+            }
+            _ => self.search(line, out)?,
+        }
+        Ok(true)
+    }
+
+    /// Compile `pattern` with the options of the file at `index`, printing any error to `out`.
+    fn compile<W: WriteColor>(
+        &self,
+        pattern: &str,
+        index: usize,
+        out: &mut W,
+    ) -> io::Result<Option<Query>> {
+        let default_options;
+        let options = match self.files.get(index) {
+            Some(file) => &file.options,
+            None => {
+                default_options = Options::builder()
+                    .query(pattern)
+                    .build()
+                    .map_err(io::Error::other)?;
+                &default_options
+            }
+        };
+        match Query::from_pattern(pattern, options) {
+            Ok(query) => Ok(Some(query)),
+            Err(e) => {
+                writeln!(out, "Err: {}", e.render(pattern))?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Print the matches of `pattern` in every file.
+    fn search<W: WriteColor>(&mut self, pattern: &str, out: &mut W) -> io::Result<()> {
+        // Files of the same language share a compiled query
+        let mut queries: HashMap<Option<String>, Query> = HashMap::new();
+        let mut found = false;
+        for (i, file) in self.files.iter().enumerate() {
+            if !queries.contains_key(&file.options.language) {
+                match self.compile(pattern, i, out)? {
+                    Some(query) => queries.insert(file.options.language.clone(), query),
+                    None => return Ok(()),
+                };
+            }
+            if let Some(query) = queries.get(&file.options.language) {
+                found |= run::print_matches(
+                    query,
+                    &file.options,
+                    &file.path,
+                    &file.ast,
+                    &file.iter,
+                    None,
+                    out,
+                );
+            }
+        }
+        let _ = out.reset();
+        if let Some(file) = self.files.first() {
+            for warning in explain::lint(pattern, &file.options).unwrap_or_default() {
+                writeln!(out, "{}", warning.render(pattern))?;
+            }
+        }
+        if !found {
+            writeln!(out, "No matches")?;
+        }
+        self.last_query = Some(pattern.to_string());
+        Ok(())
+    }
+
+    /// Read lines from `input` and execute them until it ends or `:quit` is entered, printing
+    /// `prompt` before each line.
+    pub fn run<R: BufRead, W: WriteColor>(
+        &mut self,
+        mut input: R,
+        prompt: &str,
+        out: &mut W,
+    ) -> io::Result<()> {
+        let mut line = String::new();
+        loop {
+            write!(out, "{}", prompt)?;
+            out.flush()?;
+            line.clear();
+            if input.read_line(&mut line)? == 0 || !self.execute(&line, out)? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use termcolor::Buffer;
+
+    fn session() -> Session {
+        let args = ["syns", "--interactive", "test-files/main.c"];
+        let options = Options::new("c".as_ref(), &args).expect("options");
+        Session::load(&options, &args, &options.paths).expect("load")
+    }
+
+    fn execute(session: &mut Session, line: &str) -> String {
+        let mut out = Buffer::no_color();
+        assert!(session.execute(line, &mut out).expect("write"));
+        String::from_utf8_lossy(out.as_slice()).to_string()
+    }
+
+    #[test]
+    fn search_repeatedly() {
+        let mut session = session();
+        assert_eq!(
+            execute(&mut session, "printf(\\.\\*)"),
+            "[test-files/main.c:4]     printf(\"Hello %s!\\n\", \"world\");\n"
+        );
+        assert_eq!(
+            execute(&mut session, "return \\."),
+            "[test-files/main.c:5]     return 0;\n"
+        );
+        assert_eq!(execute(&mut session, "foo"), "No matches\n");
+        assert_eq!(
+            execute(&mut session, "foo(\\x)"),
+            "Err: Unknown query command \\x\n  |\n1 | foo(\\x)\n  |     ^^\n"
+        );
+        assert!(execute(&mut session, ":machine").starts_with("digraph"));
+        assert_eq!(execute(&mut session, ""), "");
+    }
+
+    #[test]
+    fn commands() {
+        let mut session = session();
+        assert_eq!(
+            execute(&mut session, ":tokens 5"),
+            "5:5-5:11 [73..79] Identifier return\n\
+             5:12-5:13 [80..81] Integer 0\n\
+             5:13-5:14 [81..82] Symbol ;\n"
+        );
+        assert!(execute(&mut session, ":tree 3").starts_with("3:1-3:4 [20..23] Identifier int\n"));
+        assert_eq!(
+            execute(&mut session, ":tree x"),
+            "Err: Invalid line range 'x'\n"
+        );
+        assert_eq!(
+            execute(&mut session, ":machine"),
+            "Err: No query yet, use :machine QUERY\n"
+        );
+        assert_eq!(execute(&mut session, ":foo \\."), "No matches\n");
+
+        let mut out = Buffer::no_color();
+        session
+            .run("return \\.\n:quit\nfoo\n".as_bytes(), "> ", &mut out)
+            .expect("write");
+        assert_eq!(
+            String::from_utf8_lossy(out.as_slice()),
+            "> [test-files/main.c:5]     return 0;\n> "
+        );
+    }
+
+    #[test]
+    fn colon_queries() {
+        let path = std::env::temp_dir().join(format!("syns-colon-{}.rb", std::process::id()));
+        fs::write(&path, "get :tree, to: :index\n").expect("write");
+        let args = ["syns", "--interactive"];
+        let options = Options::new("rb".as_ref(), &args).expect("options");
+        let session = Session::load(&options, &args, &[&path]);
+        fs::remove_file(&path).expect("remove");
+        let mut session = session.expect("load");
+        let line = format!("[{}:1] get :tree, to: :index\n", path.display());
+
+        // Lines starting with `:` are only commands if they name one
+        assert_eq!(execute(&mut session, ":index"), line);
+        assert!(execute(&mut session, ":tree").starts_with("1:1-1:4 [0..3] Identifier get\n"));
+        assert_eq!(execute(&mut session, " :tree"), line);
+    }
+}
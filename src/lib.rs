@@ -7,14 +7,25 @@ pub mod collection;
 
 pub mod argparse;
 pub mod compiler;
+pub mod dump;
+pub mod error;
+pub mod explain;
+pub mod interactive;
 pub mod multipeek_putbackn;
 pub mod options;
 pub mod parser;
 pub mod psi;
 pub mod query;
 pub mod render_machine;
+pub mod rewrite;
+pub mod rules;
 pub mod run;
+pub mod sarif;
+pub mod search;
 pub mod tokenizer;
 pub mod wrappers;
 
+pub use error::SynsError;
+pub use options::{Options, OptionsBuilder};
 pub use run::run_cached;
+pub use search::{SearchMatch, Searcher};
@@ -1,38 +1,448 @@
 //! syntax-searcher -- Generic source code searcher for paren-delimited languages.
 
-#[macro_use]
-mod collection;
-
-mod argparse;
-mod compiler;
-mod multipeek_putbackn;
-mod options;
-mod parser;
-mod psi;
-mod query;
-mod render_machine;
-mod run;
-mod tokenizer;
-mod wrappers;
-
-use crate::query::Query;
-use ignore::WalkBuilder;
+use ignore::{DirEntry, WalkBuilder, WalkState};
+use itertools::Itertools;
 use log::{debug, info};
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::env;
-use std::fs::{self, File};
-use std::io;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use syns::interactive::Session;
+use syns::options::*;
+use syns::parser::{parse_file, parse_tokens};
+use syns::query::Query;
+use syns::rules::{self, Rule};
+use syns::tokenizer::tokenize;
+use syns::{dump, explain, render_machine, rewrite, run, sarif, SynsError};
+use termcolor::{Buffer, BufferWriter, StandardStream};
 
-use options::*;
+/// Options and compiled queries for files of a single language.
+struct Compiled {
+    options: Options,
+    /// One query for each rule, or just the query from the command line. Rules for other
+    /// languages aren't compiled, as their patterns may not be valid in this one.
+    queries: Vec<Option<Query>>,
+}
+
+/// State shared by all walker threads.
+struct Shared<'a> {
+    args: &'a [String],
+    options: &'a Options,
+    rules: Option<&'a [Rule]>,
+    cache: Mutex<HashMap<Option<&'static str>, Arc<Compiled>>>,
+    sarif_results: Mutex<Vec<(PathBuf, Vec<sarif::SarifResult>)>>,
+    stdout: BufferWriter,
+    matched: AtomicBool,
+    failed: AtomicBool,
+    dumped: AtomicBool,
+}
+
+impl Shared<'_> {
+    /// Record the outcome of scanning a single file.
+    fn record(&self, res: Result<bool, Box<dyn std::error::Error>>) {
+        match res {
+            Ok(did_match) => {
+                if did_match {
+                    self.matched.store(true, Ordering::SeqCst);
+                }
+            }
+            Err(e) => {
+                eprintln!("Err: {}", e);
+                self.failed.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Get the options and queries for `language`, compiling them on first use.
+    fn compiled(&self, language: Option<&'static str>) -> Arc<Compiled> {
+        let mut cache = self.cache.lock().expect("Query cache lock poisoned");
+        cache
+            .entry(language)
+            .or_insert_with(|| {
+                // This options accounts for the detected language
+                let options = options_or_exit(Options::for_language(language, self.args));
+                debug!(
+                    "Created new options for language {}:  {:#?}",
+                    language.unwrap_or("(default)"),
+                    options
+                );
+                let queries = match self.rules {
+                    Some(rules) => rules
+                        .iter()
+                        .map(|rule| {
+                            rule.applies_to_language(language)
+                                .then(|| compile_or_exit(&rule.pattern, &options, Some(rule)))
+                        })
+                        .collect(),
+                    None => vec![Some(compile_or_exit(&options.query, &options, None))],
+                };
+                Arc::new(Compiled { options, queries })
+            })
+            .clone()
+    }
+}
+
+/// Get the options, exiting with a diagnostic if the configuration files couldn't be loaded.
+fn options_or_exit<T>(options: Result<T, SynsError>) -> T {
+    options.unwrap_or_else(|e| {
+        eprintln!("Err: {}", e);
+        std::process::exit(2)
+    })
+}
+
+/// Compile `pattern`, from `rule` if given, exiting with a diagnostic if it's invalid.
+fn compile_or_exit(pattern: &str, options: &Options, rule: Option<&Rule>) -> Query {
+    Query::from_pattern(pattern, options).unwrap_or_else(|e| {
+        match rule {
+            Some(rule) => eprintln!(
+                "Err: Invalid pattern in rule '{}': {}",
+                rule.id,
+                e.render(pattern)
+            ),
+            None => eprintln!("Err: {}", e.render(pattern)),
+        }
+        std::process::exit(2)
+    })
+}
+
+/// Language of the file at `path` containing `content`.
+fn file_language(
+    options: &Options,
+    path: &Path,
+    content: &[u8],
+) -> Result<Option<&'static str>, SynsError> {
+    // Files without an extension are plain text unless detected otherwise
+    Ok(options
+        .detect_language(path, content)?
+        .or_else(|| path.extension().is_none().then_some("plain")))
+}
+
+/// Options for each language the query may run against, as far as can be told without walking
+/// any directory. The first is for `--lang`, the first file or `--stdin-filename` given, or else
+/// the general defaults.
+fn candidate_options(
+    options: &Options,
+    args: &[String],
+    read_stdin: bool,
+    paths: &[&OsString],
+) -> Result<Vec<Options>, SynsError> {
+    let forced = Options::for_language(None, args)?;
+    if forced.language.is_some() {
+        return Ok(vec![forced]);
+    }
+    let mut languages = Vec::new();
+    // Directories and stdin without a name may contain any language
+    let mut any = paths.is_empty() && !read_stdin;
+    for path in paths {
+        let path = Path::new(path);
+        if path.is_file() {
+            // Unreadable files are reported when searching them
+            let content = fs::read(path).unwrap_or_default();
+            languages.push(file_language(options, path, &content)?);
+        } else {
+            any = true;
+        }
+    }
+    if read_stdin {
+        match &options.stdin_filename {
+            Some(name) => languages.push(file_language(options, Path::new(name), b"")?),
+            None => any = true,
+        }
+    }
+    if any {
+        languages.push(None);
+        languages.extend(options.known_languages()?.into_iter().map(Some));
+    }
+    languages
+        .into_iter()
+        .unique()
+        .map(|language| Options::for_language(language, args))
+        .collect()
+}
+
+/// The first of `candidates` which `rule` runs on, if given.
+fn options_for<'a>(candidates: &'a [Options], rule: Option<&Rule>) -> Option<&'a Options> {
+    candidates.iter().find(|options| runs_on(rule, options))
+}
+
+/// Is `rule`, if given, run on files using `options`?
+fn runs_on(rule: Option<&Rule>, options: &Options) -> bool {
+    rule.is_none_or(|rule| rule.applies_to_language(options.language.as_deref()))
+}
+
+/// Check that `pattern`, from `rule` if given, compiles with at least one of `candidates` and
+/// print any warnings about it. Errors which only happen in some languages are reported once a
+/// file of such a language is searched.
+fn check_pattern(pattern: &str, candidates: &[Options], rule: Option<&Rule>) {
+    let Some(options) = options_for(candidates, rule) else {
+        // The rule doesn't run on any of the files
+        return;
+    };
+    let compiles = candidates
+        .iter()
+        .filter(|options| runs_on(rule, options))
+        .any(|options| Query::from_pattern(pattern, options).is_ok());
+    if !compiles {
+        compile_or_exit(pattern, options, rule);
+    }
+    if options.explain || options.dump_tokens || options.dump_ast {
+        // Printed along with the explanation, and dumping files doesn't use the query
+        return;
+    }
+    for warning in explain::lint(pattern, options).unwrap_or_default() {
+        match rule {
+            Some(rule) => eprintln!("In rule '{}': {}", rule.id, warning.render(pattern)),
+            None => eprintln!("{}", warning.render(pattern)),
+        }
+    }
+}
+
+/// Describe `pattern` along with its warnings, exiting with a diagnostic if it's invalid.
+fn explain_or_exit(pattern: &str, options: &Options) -> String {
+    match explain::explain(pattern, options) {
+        Ok(explanation) => explanation.render(pattern),
+        Err(e) => {
+            eprintln!("Err: {}", e.render(pattern));
+            std::process::exit(2)
+        }
+    }
+}
+
+/// Where the contents of a scanned file come from.
+enum Source<'a> {
+    /// Read the file from disk.
+    File,
+    /// Contents read from stdin, which can't be rewritten in place.
+    Stdin(&'a [u8]),
+}
 
 fn run_file(
     query: &Query,
     options: &Options,
-    file: ignore::DirEntry,
+    path: &Path,
+    content: &[u8],
+    source: &Source,
+    sarif_results: &mut Vec<sarif::SarifResult>,
+    out: &mut Buffer,
 ) -> Result<bool, Box<dyn std::error::Error>> {
-    let path = file.path();
-    let fp = File::open(path)?;
-    Ok(run::run_cached(query, options, path, fp))
+    if options.replace.is_some() && !options.dry_run && matches!(source, Source::Stdin(_)) {
+        // Behave like a filter, the whole input is echoed whether it matched or not
+        return Ok(rewrite::rewrite_to(query, options, content, out)?);
+    }
+    if !query.may_match(content) {
+        debug!("Skipping {}, required literals missing", path.display());
+        return Ok(false);
+    }
+    if options.sarif {
+        let mut results = sarif::file_results(query, options, sarif::QUERY_RULE_ID, path, content);
+        let did_match = !results.is_empty();
+        sarif_results.append(&mut results);
+        Ok(did_match)
+    } else if options.replace.is_some() {
+        Ok(rewrite::rewrite_cached(query, options, path, content, out)?)
+    } else {
+        Ok(run::run_cached(query, options, path, content, out))
+    }
+}
+
+fn run_rules(
+    rules: &[Rule],
+    queries: &[Option<Query>],
+    options: &Options,
+    path: &Path,
+    content: &[u8],
+    sarif_results: &mut Vec<sarif::SarifResult>,
+    out: &mut Buffer,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let applicable = rules
+        .iter()
+        .zip(queries)
+        .filter(|(rule, _)| rule.applies_to(path, options.language.as_deref()))
+        .filter_map(|(rule, query)| query.as_ref().map(|query| (rule, query)))
+        .filter(|(_, query)| query.may_match(content))
+        .collect::<Vec<_>>();
+    if applicable.is_empty() {
+        debug!("Skipping {}, required literals missing", path.display());
+        return Ok(false);
+    }
+
+    // Parse once, run every rule on the same tree
+    let (ast, iter) = parse_file(content, options);
+    let mut did_match = false;
+    for (rule, query) in applicable {
+        if options.sarif {
+            let mut results = sarif::match_results(
+                query,
+                &rule.id,
+                rule.severity.as_str(),
+                &rule.message,
+                path,
+                &ast,
+                &iter,
+            );
+            did_match |= !results.is_empty();
+            sarif_results.append(&mut results);
+        } else {
+            did_match |= run::print_matches(query, options, path, &ast, &iter, Some(rule), out);
+            if did_match && options.only_print_filenames {
+                break;
+            }
+        }
+    }
+    Ok(did_match)
+}
+
+/// Scan a single walker entry, writing any output to `out`.
+fn scan_entry(
+    shared: &Shared,
+    f: &DirEntry,
+    out: &mut Buffer,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let options = shared.options;
+    let file_path = f.path();
+    let lossy_filename = file_path.to_string_lossy();
+    if let Some(r) = &options.only_files_matching {
+        if !r.is_match(&lossy_filename) {
+            info!(
+                "Ignoring file {} as it didn't match regex '{:?}'",
+                &lossy_filename, &r
+            );
+            return Ok(false);
+        }
+    }
+    if let Some(r) = &options.ignore_files_matching {
+        if r.is_match(&lossy_filename) {
+            info!(
+                "Ignoring file {} as it matches regex '{:?}'",
+                &lossy_filename, &r
+            );
+            return Ok(false);
+        }
+    }
+    if let Ok(attr) = fs::metadata(file_path) {
+        if attr.is_dir() {
+            return Ok(false);
+        }
+    }
+    if let Ok(attr) = fs::symlink_metadata(file_path) {
+        if attr.is_symlink() && !options.follow_symlinks {
+            return Ok(false);
+        }
+    }
+
+    info!("Scanning file {}", lossy_filename);
+    scan_source(shared, file_path, Source::File, out)
+}
+
+/// Search the file labeled `path` using the defaults for its language, writing any output to
+/// `out`.
+fn scan_source(
+    shared: &Shared,
+    path: &Path,
+    source: Source,
+    out: &mut Buffer,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let content = match source {
+        Source::File => Cow::Owned(fs::read(path)?),
+        Source::Stdin(content) => Cow::Borrowed(content),
+    };
+    let language = file_language(shared.options, path, &content)?;
+    debug!("Detected language {:?} for {}", language, path.display());
+    let compiled = shared.compiled(language);
+    let options = &compiled.options;
+
+    if options.dump_tokens || options.dump_ast {
+        let (tokens, iter) = tokenize(&path.to_string_lossy(), content.as_ref(), options);
+        writeln!(out, "{}:", path.display())?;
+        if options.dump_tokens {
+            write!(
+                out,
+                "{}",
+                dump::tokens(&tokens, &iter, options.dump_lines.clone())
+            )?;
+        }
+        if options.dump_ast {
+            let ast = parse_tokens(tokens, options);
+            write!(
+                out,
+                "{}",
+                dump::tree(&ast, &iter, options.dump_lines.clone())
+            )?;
+        }
+        return Ok(false);
+    }
+
+    if options.dump_machine {
+        if !shared.dumped.swap(true, Ordering::SeqCst) {
+            if let Some(query) = compiled.queries.iter().flatten().next() {
+                writeln!(out, "{}", render_machine::to_dot_graph(&query.machine))?;
+            }
+        }
+        return Ok(false);
+    }
+
+    let mut sarif_results = Vec::new();
+    let did_match = match shared.rules {
+        Some(rules) => run_rules(
+            rules,
+            &compiled.queries,
+            options,
+            path,
+            &content,
+            &mut sarif_results,
+            out,
+        )?,
+        None => match compiled.queries.first().and_then(Option::as_ref) {
+            Some(query) => run_file(
+                query,
+                options,
+                path,
+                &content,
+                &source,
+                &mut sarif_results,
+                out,
+            )?,
+            None => false,
+        },
+    };
+    if !sarif_results.is_empty() {
+        shared
+            .sarif_results
+            .lock()
+            .expect("SARIF result lock poisoned")
+            .push((path.to_path_buf(), sarif_results));
+    }
+    Ok(did_match)
+}
+
+/// Is stdin something which can be searched, rather than a terminal or `/dev/null`?
+#[cfg(unix)]
+fn stdin_is_readable() -> bool {
+    use std::os::fd::AsFd;
+    use std::os::unix::fs::FileTypeExt;
+
+    io::stdin()
+        .as_fd()
+        .try_clone_to_owned()
+        .and_then(|fd| fs::File::from(fd).metadata())
+        .map(|meta| {
+            let ty = meta.file_type();
+            ty.is_file() || ty.is_fifo() || ty.is_socket()
+        })
+        .unwrap_or(false)
+}
+
+/// Is stdin something which can be searched, rather than a terminal?
+#[cfg(not(unix))]
+fn stdin_is_readable() -> bool {
+    use std::io::IsTerminal;
+
+    !io::stdin().is_terminal()
 }
 
 fn main() -> io::Result<()> {
@@ -40,93 +450,171 @@ fn main() -> io::Result<()> {
         env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "warn"),
     );
     let args: Vec<String> = env::args().collect();
-    let mut opt_cache = HashMap::new();
-    let mut query_cache = HashMap::new();
-    let txt: std::ffi::OsString = "txt".to_string().into();
+    let txt: OsString = "txt".to_string().into();
     // This options is only used for enumerating paths
-    let options = Options::new(&txt, &args);
-    let default_path = "./".into();
-    let mut walker = WalkBuilder::new(options.paths.first().unwrap_or(&default_path));
-    if options.follow_symlinks {
-        walker.follow_links(true);
-    }
-    for path in options.paths.iter().skip(1) {
-        walker.add(path);
-    }
-    let mut retval = 1;
-    for f in walker.build() {
-        let res = match f {
-            Ok(f) => {
-                let file_path = std::path::Path::new(f.path());
-                let lossy_filename = file_path.to_string_lossy();
-                if let Some(r) = &options.only_files_matching {
-                    if !r.is_match(&lossy_filename) {
-                        info!(
-                            "Ignoring file {} as it didn't match regex '{:?}'",
-                            &lossy_filename, &r
-                        );
-                        continue;
-                    }
-                }
-                if let Some(r) = &options.ignore_files_matching {
-                    if r.is_match(&lossy_filename) {
-                        info!(
-                            "Ignoring file {} as it matches regex '{:?}'",
-                            &lossy_filename, &r
-                        );
-                        continue;
-                    }
-                }
-                if let Ok(attr) = fs::metadata(file_path) {
-                    if attr.is_dir() {
-                        continue;
+    let options = options_or_exit(Options::new(&txt, &args));
+    if options.interactive {
+        if options.paths.is_empty() {
+            eprintln!("Err: --interactive needs at least one FILE");
+            std::process::exit(2);
+        }
+        let mut session = Session::load(&options, &args, &options.paths).unwrap_or_else(|e| {
+            eprintln!("Err: {}", e);
+            std::process::exit(2);
+        });
+        let mut out = StandardStream::stdout(options.color);
+        session.run(io::stdin().lock(), "syns> ", &mut out)?;
+        std::process::exit(0);
+    }
+    let rules = options.rules.as_ref().map(|path| {
+        if options.replace.is_some() {
+            eprintln!("Err: --replace can't be combined with --rules");
+            std::process::exit(2);
+        }
+        rules::load_rules(Path::new(path), &options).unwrap_or_else(|e| {
+            eprintln!("Err: {}", e);
+            std::process::exit(2);
+        })
+    });
+    let read_stdin = options.paths.iter().any(|path| path == "-")
+        || (options.paths.is_empty() && stdin_is_readable());
+    let paths = options
+        .paths
+        .iter()
+        .filter(|path| *path != "-")
+        .collect::<Vec<_>>();
+
+    // Report invalid queries even if there are no files to search
+    let candidates = options_or_exit(candidate_options(&options, &args, read_stdin, &paths));
+    match &rules {
+        Some(rules) => {
+            for rule in rules {
+                check_pattern(&rule.pattern, &candidates, Some(rule));
+            }
+        }
+        None => check_pattern(&options.query, &candidates, None),
+    }
+    if options.explain {
+        // Uses the language of the files given, so no directories need to be walked
+        match &rules {
+            Some(rules) => {
+                for rule in rules {
+                    if let Some(options) = options_for(&candidates, Some(rule)) {
+                        println!("{}: {}", rule.id, explain_or_exit(&rule.pattern, options));
                     }
                 }
-                if let Ok(attr) = fs::symlink_metadata(file_path) {
-                    if attr.is_symlink() && !options.follow_symlinks {
-                        continue;
-                    }
+            }
+            None => {
+                if let Some(options) = candidates.first() {
+                    println!("{}", explain_or_exit(&options.query, options));
                 }
+            }
+        }
+        std::process::exit(0);
+    }
 
-                info!("Scanning file {}", lossy_filename);
-
-                let ext = file_path.extension().unwrap_or(&txt).to_owned();
-
-                let options = opt_cache.entry(ext.clone()).or_insert_with_key(|ext| {
-                    // This options accounts for proper file extensions
-                    let opts = Options::new(ext, &args);
-                    debug!(
-                        "Created new options for extension .{}:  {:#?}",
-                        ext.to_string_lossy(),
-                        opts
-                    );
-                    opts
-                });
-                let query = query_cache
-                    .entry(ext)
-                    .or_insert_with(|| Query::new(options));
-
-                if options.dump_machine {
-                    println!("{}", crate::render_machine::to_dot_graph(&query.machine));
-                    break;
+    let shared = Shared {
+        args: &args,
+        options: &options,
+        rules: rules.as_deref(),
+        cache: Mutex::new(HashMap::new()),
+        sarif_results: Mutex::new(Vec::new()),
+        stdout: BufferWriter::stdout(options.color),
+        matched: AtomicBool::new(false),
+        failed: AtomicBool::new(false),
+        dumped: AtomicBool::new(false),
+    };
+    let mut closed = false;
+    if read_stdin {
+        let name = options
+            .stdin_filename
+            .clone()
+            .unwrap_or_else(|| "<stdin>".into());
+        let mut content = Vec::new();
+        let mut out = shared.stdout.buffer();
+        let res = io::stdin()
+            .read_to_end(&mut content)
+            .map_err(|e| e.into())
+            .and_then(|_| {
+                scan_source(&shared, Path::new(&name), Source::Stdin(&content), &mut out)
+            });
+        shared.record(res);
+        closed = shared.stdout.print(&out).is_err();
+    }
+
+    if !closed && !shared.dumped.load(Ordering::SeqCst) && (!paths.is_empty() || !read_stdin) {
+        let default_path = "./".into();
+        let mut walker = WalkBuilder::new(paths.first().copied().unwrap_or(&default_path));
+        if options.follow_symlinks {
+            walker.follow_links(true);
+        }
+        for path in paths.iter().skip(1) {
+            walker.add(path);
+        }
+        walker.threads(options.threads);
+        walker.build_parallel().run(|| {
+            let shared = &shared;
+            Box::new(move |entry| {
+                // Buffer the output of each file so that files never interleave
+                let mut out = shared.stdout.buffer();
+                shared.record(
+                    entry
+                        .map_err(|e| e.into())
+                        .and_then(|f| scan_entry(shared, &f, &mut out)),
+                );
+                if shared.stdout.print(&out).is_err() {
+                    // Most likely a closed pipe, nothing more can be printed
+                    return WalkState::Quit;
                 }
+                if shared.dumped.load(Ordering::SeqCst) {
+                    WalkState::Quit
+                } else {
+                    WalkState::Continue
+                }
+            })
+        });
+    }
 
-                run_file(query, options, f)
-            }
-            Err(e) => Err(e.into()),
+    let mut failed = shared.failed.load(Ordering::SeqCst);
+    if options.sarif {
+        let sarif_rules = match &rules {
+            Some(rules) => rules
+                .iter()
+                .map(|rule| sarif::Rule::new(&rule.id, &rule.message, rule.severity.as_str()))
+                .collect(),
+            None => vec![sarif::Rule::new(
+                sarif::QUERY_RULE_ID,
+                &options.query,
+                "warning",
+            )],
         };
-        match res {
-            Ok(did_match) => {
-                if retval == 1 && did_match {
-                    retval = 0;
-                }
-            }
+        // Files finish in arbitrary order, sort them to keep the log stable
+        let mut file_results = shared
+            .sarif_results
+            .into_inner()
+            .expect("SARIF result lock poisoned");
+        file_results.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let results = file_results
+            .into_iter()
+            .flat_map(|(_, results)| results)
+            .collect();
+        match sarif::to_log(sarif_rules, results) {
+            Ok(log) => println!("{}", log),
             Err(e) => {
                 eprintln!("Err: {}", e);
-                retval = 2;
+                failed = true;
             }
         }
     }
 
+    // Dumping files doesn't search them, so there's nothing to match
+    let dumped_files = options.dump_tokens || options.dump_ast;
+    let retval = if failed {
+        2
+    } else if dumped_files || shared.matched.load(Ordering::SeqCst) {
+        0
+    } else {
+        1
+    };
     std::process::exit(retval);
 }
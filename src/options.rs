@@ -1,15 +1,22 @@
 //! Options parsing and handling.
 
 use crate::argparse::{parse_args, Arg, ArgRef};
+use crate::dump::parse_line_range;
+use crate::error::SynsError;
 use itertools::Itertools;
 use lazy_static::lazy_static;
-use log::warn;
+use log::debug;
+use memchr::{memchr_iter, memrchr_iter};
 use regex::Regex;
 use serde::Deserialize;
+use serde_json::{Map, Value};
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::ffi::{OsStr, OsString};
 use std::iter::Peekable;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 use termcolor::ColorChoice;
 
 /// Parsed options.
@@ -19,21 +26,38 @@ pub struct Options {
     pub paths: Vec<OsString>,
     /// Query string.
     pub query: String,
+    /// Name of the built-in language whose defaults are in use, if any.
+    pub language: Option<String>,
     /// Only use paths matching this regex.
     pub only_files_matching: Option<Regex>,
     /// Ignore paths matching this regex.
     pub ignore_files_matching: Option<Regex>,
 
-    /// Set of strings which start or end a string literal (eg. "'").
+    /// Set of strings which start or end a string literal (eg. "'" or "\"\"\"").
     pub string_characters: HashSet<String>,
+    /// Prefixes which may precede a string literal (eg. "f" in Python).
+    pub string_prefixes: HashSet<String>,
+    /// Prefixes of raw string literals and their syntax (eg. "r" for Rust's r#"..."#).
+    pub raw_strings: HashMap<String, RawString>,
+    /// Operators which start a heredoc (eg. "<<" in shell scripts).
+    pub heredocs: HashSet<String>,
+    /// Allow spaces between a heredoc operator and its terminator (eg. `<< EOF` in shell
+    /// scripts). Otherwise the terminator must follow the operator directly, so that `a << b`
+    /// isn't a heredoc.
+    pub heredoc_spaces: bool,
     /// Set of strings which start or end a single-line comment (eg. "//").
     pub single_line_comments: HashSet<String>,
     /// Set of strings which start and end a multi-line comment (eg. ("/*", "*/")).
     pub multi_line_comments: HashSet<(String, String)>,
-    /// List of block openers (eg. "(" or "do")
-    pub block_openers: HashSet<String>,
-    /// List of block closers (eg. ")" or "end")
-    pub block_closers: HashSet<String>,
+    /// Multi-line comments which may contain nested comments of the same kind (eg. `/+ +/` in D,
+    /// but not `/* */`).
+    pub nested_comments: HashSet<(String, String)>,
+    /// List of block delimiters (eg. ("(", ")") or ("do", "end")).
+    pub blocks: Vec<Block>,
+    /// Match keyword block delimiters regardless of case (eg. "End Sub" and "END SUB").
+    pub case_insensitive_blocks: bool,
+    /// Match identifiers regardless of case (eg. "SELECT" and "select").
+    pub case_insensitive_identifiers: bool,
     /// Regex to match first letter of an identifier
     pub identifier_regex_start: Regex,
     /// Regex to match non-first letters of an identifier
@@ -44,6 +68,8 @@ pub struct Options {
     pub regex_delimiters: HashSet<String>,
     /// Parse <> as type parameter delimiters when they follow an identifier.
     pub type_parameter_parsing: bool,
+    /// Parse more indented lines as blocks (eg. in Python).
+    pub indent_blocks: bool,
 
     /// Print only matching parts of the source code.
     pub only_matching: bool,
@@ -60,6 +86,37 @@ pub struct Options {
 
     /// Print the state machine as a dot graph and exit.
     pub dump_machine: bool,
+    /// Print a description of the query and warnings about it, and exit.
+    pub explain: bool,
+    /// Read queries from standard input and search PATHs for each of them.
+    pub interactive: bool,
+    /// Print the tokens of each file instead of searching it.
+    pub dump_tokens: bool,
+    /// Print the parsed tree of each file instead of searching it.
+    pub dump_ast: bool,
+    /// Only print the tokens and tree on these lines.
+    pub dump_lines: Option<RangeInclusive<usize>>,
+    /// Rewrite all matches using this template.
+    pub replace: Option<String>,
+    /// Print the rewrites as a unified diff instead of modifying files.
+    pub dry_run: bool,
+    /// Print matches as JSON Lines.
+    pub json: bool,
+    /// Print all matches as a single SARIF log.
+    pub sarif: bool,
+    /// Run the named queries in this rule file instead of PATTERN.
+    pub rules: Option<OsString>,
+    /// Number of threads used for scanning files, or 0 to pick automatically.
+    pub threads: usize,
+    /// Number of lines to print before each match.
+    pub before_context: usize,
+    /// Number of lines to print after each match.
+    pub after_context: usize,
+    /// Name used for input read from stdin, which also selects its language defaults.
+    pub stdin_filename: Option<OsString>,
+    /// Configuration file with language definitions, taking precedence over the user and
+    /// project configuration files.
+    pub config: Option<OsString>,
 }
 
 #[derive(Clone, Debug)]
@@ -84,73 +141,418 @@ enum OptionCommand {
     SearchBinary,
     TypeParameterParsing,
     NoTypeParameterParsing,
+    IndentBlocks,
+    NoIndentBlocks,
+    NestedComments,
+    NoNestedComments,
+    IgnoreCase,
     Color(ColorChoice),
     DumpMachine,
+    Explain,
+    Interactive,
+    DumpTokens,
+    DumpAst,
+    DumpLines(RangeInclusive<usize>),
+    Replace(String),
+    DryRun,
+    Json,
+    Sarif,
+    Rules(OsString),
+    Threads(usize),
+    BeforeContext(usize),
+    AfterContext(usize),
+    Context(usize),
+    StdinFilename(OsString),
+    Config(OsString),
+    ListLanguages,
 }
 
+/// A pair of block delimiters, with the keywords which may appear between them (eg. "else"
+/// between "do" and "end"). Several blocks may share a closer, and keywords may consist of
+/// several words (eg. "End Sub").
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Block {
+    /// Opener of the block.
+    pub open: String,
+    /// Closer of the block.
+    pub close: String,
+    /// Keywords separating parts of the block.
+    pub middle: Vec<String>,
+}
+
+impl Block {
+    fn new(open: &str, close: &str) -> Block {
+        Block {
+            open: open.to_string(),
+            close: close.to_string(),
+            middle: Vec::new(),
+        }
+    }
+}
+
+/// Syntax of a raw string literal following its prefix. Raw strings don't have backslash escapes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RawString {
+    /// Delimited by `"` and any number of `#`, as in Rust's `r#"..."#`.
+    Hashes,
+    /// Delimited by `"DELIMITER(` and `)DELIMITER"`, as in C++'s `R"x(...)x"`.
+    Parens,
+    /// Delimited by `"`, with `""` standing for a quote, as in C#'s `@"..."`.
+    Doubled,
+}
+
+/// A block in a language definition, either `[open, close]` or `[open, close, [middle...]]`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+enum BlockDefinition {
+    Pair(String, String),
+    WithMiddle(String, String, Vec<String>),
+}
+
+/// A multi-line comment in a language definition, either `[start, end]` or
+/// `[start, end, nested]`.
 #[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+enum CommentDefinition {
+    Pair(String, String),
+    Nesting(String, String, bool),
+}
+
+impl CommentDefinition {
+    fn delimiters(&self) -> (String, String) {
+        match self {
+            CommentDefinition::Pair(start, end) | CommentDefinition::Nesting(start, end, _) => {
+                (start.clone(), end.clone())
+            }
+        }
+    }
+
+    fn nested(&self) -> bool {
+        matches!(self, CommentDefinition::Nesting(_, _, true))
+    }
+}
+
+impl From<&BlockDefinition> for Block {
+    fn from(definition: &BlockDefinition) -> Block {
+        match definition {
+            BlockDefinition::Pair(open, close) => Block::new(open, close),
+            BlockDefinition::WithMiddle(open, close, middle) => Block {
+                middle: middle.clone(),
+                ..Block::new(open, close)
+            },
+        }
+    }
+}
+
+/// Defaults for a single language, from the built-in database or a configuration file. Missing
+/// fields are empty, except for `identifier` and `blocks` which use the general defaults.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 struct BuiltinLanguageDefaults {
     identifier: Vec<String>,
     extensions: Vec<String>,
+    /// Exact file names, eg. "Makefile".
+    filenames: Vec<String>,
+    /// Interpreter names in a `#!` line, with any version suffix removed.
+    shebangs: Vec<String>,
+    /// Names used in Emacs and Vim modelines.
+    modelines: Vec<String>,
     strings: Vec<String>,
+    string_prefixes: Vec<String>,
+    raw_strings: HashMap<String, RawString>,
+    heredocs: Vec<String>,
+    heredoc_spaces: bool,
     single_comments: Vec<String>,
-    multi_comments: Vec<(String, String)>,
-    blocks: Option<Vec<(String, String)>>, // default () [] {}
+    multi_comments: Vec<CommentDefinition>,
+    blocks: Option<Vec<BlockDefinition>>, // default () [] {}
+    case_insensitive_blocks: bool,
+    case_insensitive_identifiers: bool,
     regex: Vec<String>,
-    #[serde(default)]
     type_parameter_parsing: bool,
+    indent_blocks: bool,
 }
 
 const BUILTIN_DATABASE: &str = include_str!("../config.json");
 
+/// Name of the project configuration file, searched from the current directory upwards.
+#[cfg_attr(test, allow(dead_code))]
+const PROJECT_CONFIG: &str = ".syns.json";
+
+/// Language definitions from the built-in database merged with any configuration files.
+#[derive(Debug)]
+struct LanguageDatabase {
+    languages: HashMap<String, BuiltinLanguageDefaults>,
+    settings: HashMap<String, Options>,
+}
+
 lazy_static! {
-    static ref PARSED_DB: HashMap<String, BuiltinLanguageDefaults> =
-        serde_json::from_str(BUILTIN_DATABASE).unwrap_or_else(|e| {
-            warn!("Built-in JSON database has a syntax error: {}", e);
-            HashMap::new()
-        });
-    static ref EXTENSION_TO_SETTINGS: HashMap<String, Options> = {
-        let mut res = HashMap::new();
-        let default_opts = Options::default();
-
-        #[allow(clippy::iter_over_hash_type)]
-        for ty in PARSED_DB.values() {
-            let opts = Options {
-                string_characters: ty.strings.iter().cloned().collect(),
-                single_line_comments: ty.single_comments.iter().cloned().collect(),
-                multi_line_comments: ty.multi_comments.iter().cloned().collect(),
-                block_openers: ty
-                    .blocks
-                    .as_ref()
-                    .map(|blocks| blocks.iter().map(|(start, _)| start.clone()).collect())
-                    .unwrap_or_else(|| default_opts.block_openers.clone()),
-                block_closers: ty
-                    .blocks
-                    .as_ref()
-                    .map(|blocks| blocks.iter().map(|(_, end)| end.clone()).collect())
-                    .unwrap_or_else(|| default_opts.block_closers.clone()),
-                identifier_regex_start: ty
-                    .identifier
-                    .first()
-                    .map(|r| Regex::new(r).expect("Invalid identifier regex in builtin database"))
-                    .unwrap_or_else(|| default_opts.identifier_regex_start.clone()),
-                identifier_regex_continue: ty
-                    .identifier
-                    .get(1)
-                    .map(|r| Regex::new(r).expect("Invalid identifier regex in builtin database"))
-                    .unwrap_or_else(|| default_opts.identifier_regex_continue.clone()),
-                regex_delimiters: ty.regex.iter().cloned().collect(),
-                type_parameter_parsing: ty.type_parameter_parsing,
-                ..Options::default()
-            };
-
-            for ext in &ty.extensions {
-                res.insert(ext.to_string(), opts.clone());
+    /// Databases loaded for each `--config` argument. They are needed until the program exits.
+    static ref DATABASES: Mutex<HashMap<Option<OsString>, &'static LanguageDatabase>> =
+        Mutex::new(HashMap::new());
+    static ref EMACS_MODELINE: Regex =
+        Regex::new(r"-\*-\s*(?:.*;\s*)?(?i:mode:)?\s*([\w+-]+)\s*(?:;.*)?-\*-")
+            .expect("internal error");
+    static ref VIM_MODELINE: Regex =
+        Regex::new(r"\b(?:vi|vim|ex)(?:[<=>]?\d+)?:.*\b(?:ft|filetype|syntax)=([\w+-]+)")
+            .expect("internal error");
+}
+
+/// Merge the language definitions in `json` into `languages`. Fields given for an existing
+/// language replace its fields one by one, and extensions and file names given for a language
+/// are removed from all other languages.
+fn merge_languages(languages: &mut Map<String, Value>, json: &str) -> Result<(), String> {
+    let layer: Map<String, Value> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    for (name, fields) in layer {
+        let Value::Object(fields) = fields else {
+            return Err(format!("Definition of language '{}' isn't an object", name));
+        };
+        serde_json::from_value::<BuiltinLanguageDefaults>(Value::Object(fields.clone()))
+            .map_err(|e| format!("Invalid definition for language '{}': {}", name, e))?;
+        for key in ["extensions", "filenames"] {
+            if let Some(Value::Array(claimed)) = fields.get(key) {
+                for (other, definition) in languages.iter_mut() {
+                    if let (true, Some(Value::Array(values))) =
+                        (other != &name, definition.get_mut(key))
+                    {
+                        values.retain(|v| !claimed.contains(v));
+                    }
+                }
+            }
+        }
+        match languages.get_mut(&name) {
+            Some(Value::Object(existing)) => existing.extend(fields),
+            _ => {
+                languages.insert(name, Value::Object(fields));
             }
         }
+    }
+    Ok(())
+}
 
-        res
+/// Get the default options for language `name`.
+fn language_settings(name: &str, ty: &BuiltinLanguageDefaults) -> Result<Options, String> {
+    let default_opts = Options::default();
+    let regex = |r: &String| {
+        Regex::new(r)
+            .map_err(|e| format!("Invalid identifier regex for language '{}': {}", name, e))
     };
+    Ok(Options {
+        language: Some(name.to_string()),
+        string_characters: ty.strings.iter().cloned().collect(),
+        string_prefixes: ty.string_prefixes.iter().cloned().collect(),
+        raw_strings: ty.raw_strings.clone(),
+        heredocs: ty.heredocs.iter().cloned().collect(),
+        heredoc_spaces: ty.heredoc_spaces,
+        single_line_comments: ty.single_comments.iter().cloned().collect(),
+        multi_line_comments: ty.multi_comments.iter().map(|c| c.delimiters()).collect(),
+        nested_comments: ty
+            .multi_comments
+            .iter()
+            .filter(|c| c.nested())
+            .map(|c| c.delimiters())
+            .collect(),
+        blocks: ty
+            .blocks
+            .as_ref()
+            .map(|blocks| blocks.iter().map(Block::from).collect())
+            .unwrap_or_else(|| default_opts.blocks.clone()),
+        case_insensitive_blocks: ty.case_insensitive_blocks,
+        case_insensitive_identifiers: ty.case_insensitive_identifiers,
+        identifier_regex_start: match ty.identifier.first() {
+            Some(r) => regex(r)?,
+            None => default_opts.identifier_regex_start.clone(),
+        },
+        identifier_regex_continue: match ty.identifier.get(1) {
+            Some(r) => regex(r)?,
+            None => default_opts.identifier_regex_continue.clone(),
+        },
+        regex_delimiters: ty.regex.iter().cloned().collect(),
+        type_parameter_parsing: ty.type_parameter_parsing,
+        indent_blocks: ty.indent_blocks,
+        ..Options::default()
+    })
+}
+
+impl LanguageDatabase {
+    /// Build the database from the built-in definitions and the configuration `layers`, in
+    /// increasing order of precedence.
+    fn from_layers(layers: &[(&Path, String)]) -> Result<LanguageDatabase, String> {
+        let mut merged = Map::new();
+        merge_languages(&mut merged, BUILTIN_DATABASE)
+            .map_err(|e| format!("Built-in JSON database is invalid: {}", e))?;
+        for (path, json) in layers {
+            merge_languages(&mut merged, json).map_err(|e| {
+                format!(
+                    "Invalid configuration file {}: {}",
+                    path.to_string_lossy(),
+                    e
+                )
+            })?;
+        }
+
+        let mut languages = HashMap::new();
+        let mut settings = HashMap::new();
+        for (name, definition) in merged {
+            let ty: BuiltinLanguageDefaults = serde_json::from_value(definition)
+                .map_err(|e| format!("Invalid definition for language '{}': {}", name, e))?;
+            settings.insert(name.clone(), language_settings(&name, &ty)?);
+            languages.insert(name, ty);
+        }
+        Ok(LanguageDatabase {
+            languages,
+            settings,
+        })
+    }
+
+    #[cfg(not(tarpaulin_include))]
+    /// Load the database using the user configuration, the project configuration and finally
+    /// `config`.
+    fn load(config: Option<&OsStr>) -> Result<LanguageDatabase, String> {
+        let files = LanguageDatabase::config_files();
+        let mut layers = Vec::new();
+        for path in files
+            .iter()
+            .map(|p| p.as_path())
+            .chain(config.map(Path::new))
+        {
+            let json = std::fs::read_to_string(path).map_err(|e| {
+                format!(
+                    "Failed to read configuration file {}: {}",
+                    path.to_string_lossy(),
+                    e
+                )
+            })?;
+            debug!("Loaded configuration file {}", path.to_string_lossy());
+            layers.push((path, json));
+        }
+        LanguageDatabase::from_layers(&layers)
+    }
+
+    /// Unit tests only use the built-in definitions and `--config`, so that they don't depend on
+    /// the configuration of whoever runs them.
+    #[cfg(test)]
+    fn config_files() -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    /// The user configuration and the project configuration, if they exist.
+    #[cfg(not(test))]
+    fn config_files() -> Vec<PathBuf> {
+        let user = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|dir| dir.join("syns").join("config.json"))
+            .filter(|path| path.is_file());
+        // Directories above the root of a repository belong to other projects
+        let project = std::env::current_dir().ok().and_then(|dir| {
+            dir.ancestors()
+                .take_while_inclusive(|dir| !dir.join(".git").exists())
+                .map(|dir| dir.join(PROJECT_CONFIG))
+                .find(|path| path.is_file())
+        });
+        user.into_iter().chain(project).collect()
+    }
+
+    #[cfg(not(tarpaulin_include))]
+    /// Get the database for `config`, loading it on first use.
+    fn get(config: Option<&OsStr>) -> Result<&'static LanguageDatabase, SynsError> {
+        let mut databases = DATABASES.lock().expect("Language database lock poisoned");
+        let key = config.map(|c| c.to_owned());
+        if let Some(db) = databases.get(&key) {
+            return Ok(db);
+        }
+        // Failures aren't cached, the files may be fixed before the next attempt
+        let db = Box::leak(Box::new(
+            LanguageDatabase::load(config).map_err(SynsError::Config)?,
+        ));
+        databases.insert(key, db);
+        Ok(db)
+    }
+
+    /// Get the language whose defaults are used for files with `extension`.
+    fn language_for_extension(&'static self, extension: &OsStr) -> Option<&'static str> {
+        let extension = extension.to_str()?;
+        self.find_language(|ty| ty.extensions.iter().any(|e| e == extension))
+    }
+
+    fn find_language<F: Fn(&BuiltinLanguageDefaults) -> bool>(
+        &'static self,
+        f: F,
+    ) -> Option<&'static str> {
+        self.languages
+            .iter()
+            .filter(|(_, ty)| f(ty))
+            .map(|(name, _)| name.as_str())
+            .min()
+    }
+
+    fn detect_language(&'static self, path: &Path, content: &[u8]) -> Option<&'static str> {
+        self.modeline_language(content)
+            .or_else(|| {
+                let name = path.file_name()?.to_str()?;
+                self.find_language(|ty| ty.filenames.iter().any(|f| f == name))
+            })
+            .or_else(|| self.shebang_language(content))
+            .or_else(|| self.language_for_extension(path.extension()?))
+    }
+
+    fn shebang_language(&'static self, content: &[u8]) -> Option<&'static str> {
+        let line = content.strip_prefix(b"#!")?.split(|&b| b == b'\n').next()?;
+        let line = String::from_utf8_lossy(line);
+        let mut words = line.split_whitespace();
+        let mut interpreter = words.next()?.rsplit('/').next()?;
+        if interpreter == "env" {
+            // Skip flags such as -S and variable assignments
+            interpreter = words.find(|w| !w.starts_with('-') && !w.contains('='))?;
+        }
+        let trimmed = interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+        self.find_language(|ty| ty.shebangs.iter().any(|s| s == interpreter || s == trimmed))
+    }
+
+    fn modeline_language(&'static self, content: &[u8]) -> Option<&'static str> {
+        // Vim only checks the first and last five lines by default. Only those are decoded, the
+        // rest of the file may be large.
+        let head_end = memchr_iter(b'\n', content)
+            .nth(4)
+            .map_or(content.len(), |i| i + 1);
+        let body = content.strip_suffix(b"\n").unwrap_or(content);
+        let tail_start = memrchr_iter(b'\n', body)
+            .nth(4)
+            .map_or(0, |i| i + 1)
+            .max(head_end);
+        let head = String::from_utf8_lossy(&content[..head_end]);
+        let tail = String::from_utf8_lossy(&content[tail_start..]);
+        head.lines()
+            .chain(tail.lines())
+            .filter_map(|line| {
+                EMACS_MODELINE
+                    .captures(line)
+                    .or_else(|| VIM_MODELINE.captures(line))
+            })
+            .find_map(|captures| {
+                let name = captures.get(1)?.as_str().to_lowercase();
+                self.find_language(|ty| ty.modelines.contains(&name))
+            })
+    }
+
+    #[cfg(not(tarpaulin_include))]
+    fn print_languages(&self) -> ! {
+        println!("Available languages:");
+        for (lang, defs) in self
+            .languages
+            .iter()
+            .sorted_by(|(k1, _), (k2, _)| k1.cmp(k2))
+        {
+            println!(
+                "- {} [{}]",
+                lang,
+                defs.extensions.iter().chain(&defs.filenames).join(", ")
+            );
+        }
+        std::process::exit(0)
+    }
 }
 
 impl Default for Options {
@@ -158,24 +560,32 @@ impl Default for Options {
         Options {
             paths: Vec::new(),
             query: "".to_string(),
+            language: None,
             only_files_matching: None,
             ignore_files_matching: None,
             string_characters: ["\"", "'", "`"].iter().map(|s| s.to_string()).collect(),
+            string_prefixes: HashSet::new(),
+            raw_strings: HashMap::new(),
+            heredocs: HashSet::new(),
+            heredoc_spaces: false,
             single_line_comments: ["//"].iter().map(|s| s.to_string()).collect(),
             multi_line_comments: [("/*", "*/")]
                 .iter()
                 .map(|(from, to)| (from.to_string(), to.to_string()))
                 .collect(),
-            block_openers: vec!["(".to_string(), "[".to_string(), "{".to_string()]
-                .into_iter()
-                .collect(),
-            block_closers: vec![")".to_string(), "]".to_string(), "}".to_string()]
-                .into_iter()
-                .collect(),
+            nested_comments: HashSet::new(),
+            blocks: vec![
+                Block::new("(", ")"),
+                Block::new("[", "]"),
+                Block::new("{", "}"),
+            ],
+            case_insensitive_blocks: false,
+            case_insensitive_identifiers: false,
             identifier_regex_start: Regex::new("[\\p{ID_Start}_]").expect("internal error"),
             identifier_regex_continue: Regex::new("\\p{ID_Continue}").expect("internal error"),
             regex_delimiters: HashSet::new(),
             type_parameter_parsing: false,
+            indent_blocks: false,
             ranges: true,
 
             only_matching: false,
@@ -185,6 +595,21 @@ impl Default for Options {
             search_binary: false,
             color: ColorChoice::Auto,
             dump_machine: false,
+            explain: false,
+            interactive: false,
+            dump_tokens: false,
+            dump_ast: false,
+            dump_lines: None,
+            replace: None,
+            dry_run: false,
+            json: false,
+            sarif: false,
+            rules: None,
+            threads: 0,
+            before_context: 0,
+            after_context: 0,
+            stdin_filename: None,
+            config: None,
         }
     }
 }
@@ -202,12 +627,18 @@ Pass --help for more information.",
     } else {
         println!(
             r#"Usage: {} [OPTION]... PATTERN PATH...
-Search for PATTERN in PATHs.
+  or:  {} [OPTION]... --rules FILE PATH...
+  or:  {} [OPTION]... --interactive FILE...
+  or:  {} [OPTION]... --dump-tokens|--dump-ast PATH...
+Search for PATTERN in PATHs. A PATH of - reads standard input, which is also
+searched when no PATH is given and it isn't a terminal.
 
 Options:
   -h, --help                    Display this message
   --lang LANGUAGE               Force defaults for LANGUAGE. Call 'syns --lang'
                                 to display available languages.
+  --config FILE                 Read language definitions from FILE in addition
+                                to ~/.config/syns/config.json and .syns.json
   --[no-]color                  Enable or disable color output
 
   -i, --identifier START END    Match identifiers using START regex for the
@@ -224,22 +655,42 @@ Options:
   -I, --dont-print-filenames    Don't print any filenames
   -L, --follow                  Follow symlinks
   -a, --text                    Search binary files as if they were text
+  -j, --threads NUM             Scan files using NUM threads (default: number
+                                of CPUs)
+  -A, --after-context NUM       Print NUM lines of context after each match
+  -B, --before-context NUM      Print NUM lines of context before each match
+  -C, --context NUM             Print NUM lines of context around each match
+  --json                        Print matches as JSON Lines
+  --sarif                       Print all matches as a SARIF 2.1.0 log
+  --rules FILE                  Run every rule in the JSON rule file FILE
+                                instead of PATTERN
+  --explain                     Describe what PATTERN matches and warn about
+                                suspicious parts of it
+  --interactive                 Parse the FILEs once and search them for each
+                                query typed, see :help at the prompt
+  --dump-tokens                 Print the tokens of each file instead of
+                                searching it
+  --dump-ast                    Print the parsed tree of each file instead of
+                                searching it
+  --lines N[-M]                 Only print the tokens and tree on lines N to M
+  --stdin-filename NAME         Label input read from stdin as NAME and use
+                                the defaults for its file name
   --[no-]type-parameter-parsing Parse <> as type parameter delimiters
+  --[no-]indent-blocks          Parse more indented lines as blocks
+  --[no-]nested-comments        Allow every kind of multi-line comment inside itself
+  --ignore-case                 Match identifiers and block keywords regardless
+                                of case
+
+  --replace TEMPLATE            Rewrite matches in place with TEMPLATE, where
+                                \:name is replaced with the captured text
+  --dry-run                     Print the rewrites as a unified diff instead
 "#,
-            filename
+            filename, filename, filename, filename
         );
     }
     std::process::exit(status)
 }
 
-fn print_langs() -> ! {
-    println!("Available languages:");
-    for (lang, defs) in PARSED_DB.iter().sorted_by(|(k1, _), (k2, _)| k1.cmp(k2)) {
-        println!("- {} [{}]", lang, defs.extensions.join(", "));
-    }
-    std::process::exit(0)
-}
-
 fn get_whole_arg<I: Iterator<Item = Arg>>(iter: &mut Peekable<I>) -> Option<OsString> {
     let arg = iter.next()?;
     let index = arg.index();
@@ -249,6 +700,21 @@ fn get_whole_arg<I: Iterator<Item = Arg>>(iter: &mut Peekable<I>) -> Option<OsSt
     Some(arg.entire_match())
 }
 
+fn get_count_arg<I: Iterator<Item = Arg>>(iter: &mut Peekable<I>, name: &str) -> usize {
+    if let Some(arg) = get_whole_arg(iter) {
+        match arg.to_string_lossy().parse() {
+            Ok(n) => n,
+            Err(e) => {
+                println!("Invalid argument for --{}: {}", name, e);
+                print_help(false, 1)
+            }
+        }
+    } else {
+        println!("Missing argument for --{}", name);
+        print_help(false, 1)
+    }
+}
+
 fn parse_options<S: AsRef<OsStr>>(args: &[S]) -> (Vec<OptionCommand>, Vec<OsString>) {
     let mut opts = Vec::new();
     let mut positionals = Vec::new();
@@ -263,7 +729,15 @@ fn parse_options<S: AsRef<OsStr>>(args: &[S]) -> (Vec<OptionCommand>, Vec<OsStri
                 if let Some(arg) = get_whole_arg(&mut arg_iter) {
                     OptionCommand::Language(arg.to_string_lossy().to_string())
                 } else {
-                    print_langs()
+                    OptionCommand::ListLanguages
+                }
+            }
+            ArgRef::Long("config") => {
+                if let Some(arg) = get_whole_arg(&mut arg_iter) {
+                    OptionCommand::Config(arg)
+                } else {
+                    println!("Missing argument for --config");
+                    print_help(false, 1)
                 }
             }
 
@@ -435,7 +909,69 @@ fn parse_options<S: AsRef<OsStr>>(args: &[S]) -> (Vec<OptionCommand>, Vec<OsStri
             ArgRef::Short('a') | ArgRef::Long("text") => OptionCommand::SearchBinary,
             ArgRef::Long("type-parameter-parsing") => OptionCommand::TypeParameterParsing,
             ArgRef::Long("no-type-parameter-parsing") => OptionCommand::NoTypeParameterParsing,
+            ArgRef::Long("indent-blocks") => OptionCommand::IndentBlocks,
+            ArgRef::Long("no-indent-blocks") => OptionCommand::NoIndentBlocks,
+            ArgRef::Long("nested-comments") => OptionCommand::NestedComments,
+            ArgRef::Long("no-nested-comments") => OptionCommand::NoNestedComments,
+            ArgRef::Long("ignore-case") => OptionCommand::IgnoreCase,
             ArgRef::Long("dump-machine") => OptionCommand::DumpMachine,
+            ArgRef::Long("explain") => OptionCommand::Explain,
+            ArgRef::Long("interactive") => OptionCommand::Interactive,
+            ArgRef::Long("dump-tokens") => OptionCommand::DumpTokens,
+            ArgRef::Long("dump-ast") => OptionCommand::DumpAst,
+            ArgRef::Long("lines") => {
+                if let Some(arg) = get_whole_arg(&mut arg_iter) {
+                    match parse_line_range(&arg.to_string_lossy()) {
+                        Some(lines) => OptionCommand::DumpLines(lines),
+                        None => {
+                            println!("Invalid argument for --lines: {}", arg.to_string_lossy());
+                            print_help(false, 1)
+                        }
+                    }
+                } else {
+                    println!("Missing argument for --lines");
+                    print_help(false, 1)
+                }
+            }
+            ArgRef::Long("replace") => {
+                if let Some(arg) = get_whole_arg(&mut arg_iter) {
+                    OptionCommand::Replace(arg.to_string_lossy().to_string())
+                } else {
+                    println!("Missing argument for --replace");
+                    print_help(false, 1)
+                }
+            }
+            ArgRef::Long("dry-run") => OptionCommand::DryRun,
+            ArgRef::Long("json") => OptionCommand::Json,
+            ArgRef::Long("sarif") => OptionCommand::Sarif,
+            ArgRef::Short('j') | ArgRef::Long("threads") => {
+                OptionCommand::Threads(get_count_arg(&mut arg_iter, "threads"))
+            }
+            ArgRef::Short('A') | ArgRef::Long("after-context") => {
+                OptionCommand::AfterContext(get_count_arg(&mut arg_iter, "after-context"))
+            }
+            ArgRef::Short('B') | ArgRef::Long("before-context") => {
+                OptionCommand::BeforeContext(get_count_arg(&mut arg_iter, "before-context"))
+            }
+            ArgRef::Short('C') | ArgRef::Long("context") => {
+                OptionCommand::Context(get_count_arg(&mut arg_iter, "context"))
+            }
+            ArgRef::Long("stdin-filename") => {
+                if let Some(arg) = get_whole_arg(&mut arg_iter) {
+                    OptionCommand::StdinFilename(arg)
+                } else {
+                    println!("Missing argument for --stdin-filename");
+                    print_help(false, 1)
+                }
+            }
+            ArgRef::Long("rules") => {
+                if let Some(arg) = get_whole_arg(&mut arg_iter) {
+                    OptionCommand::Rules(arg)
+                } else {
+                    println!("Missing argument for --rules");
+                    print_help(false, 1)
+                }
+            }
 
             ArgRef::Positional => {
                 positionals.push(arg.entire_match());
@@ -457,48 +993,269 @@ fn parse_options<S: AsRef<OsStr>>(args: &[S]) -> (Vec<OptionCommand>, Vec<OsStri
     (opts, positionals)
 }
 
+/// Builder for `Options`, for using syns as a library. Settings are applied in order on top of
+/// the defaults for the selected language, like the corresponding command line flags.
+#[derive(Clone, Debug, Default)]
+pub struct OptionsBuilder {
+    language: Option<String>,
+    extension: Option<OsString>,
+    config: Option<OsString>,
+    query: String,
+    commands: Vec<OptionCommand>,
+}
+
+impl OptionsBuilder {
+    /// Use the defaults for the language `name`, as listed by `--lang`.
+    pub fn language(mut self, name: &str) -> OptionsBuilder {
+        self.language = Some(name.to_string());
+        self
+    }
+
+    /// Use the defaults for files with `extension`, unless a language is set.
+    pub fn extension<S: AsRef<OsStr>>(mut self, extension: S) -> OptionsBuilder {
+        self.extension = Some(extension.as_ref().to_owned());
+        self
+    }
+
+    /// Load language definitions from the configuration file `path`, like `--config`.
+    pub fn config<S: AsRef<OsStr>>(mut self, path: S) -> OptionsBuilder {
+        self.config = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// Set the query string.
+    pub fn query(mut self, query: &str) -> OptionsBuilder {
+        self.query = query.to_string();
+        self
+    }
+
+    /// Add `s` to the string delimiters, like `--string`.
+    pub fn string(mut self, s: &str) -> OptionsBuilder {
+        self.commands
+            .push(OptionCommand::AddStringCharacter(s.to_string()));
+        self
+    }
+
+    /// Remove `s` from the string delimiters, like `--no-string`.
+    pub fn no_string(mut self, s: &str) -> OptionsBuilder {
+        self.commands
+            .push(OptionCommand::RemoveStringCharacter(s.to_string()));
+        self
+    }
+
+    /// Add `s` to the single-line comments, like `--comment`.
+    pub fn comment(mut self, s: &str) -> OptionsBuilder {
+        self.commands
+            .push(OptionCommand::AddSingleComment(s.to_string()));
+        self
+    }
+
+    /// Remove `s` from the single-line comments, like `--no-comment`.
+    pub fn no_comment(mut self, s: &str) -> OptionsBuilder {
+        self.commands
+            .push(OptionCommand::RemoveSingleComment(s.to_string()));
+        self
+    }
+
+    /// Add (`start`, `end`) to the multi-line comments, like `--multi`.
+    pub fn multi_comment(mut self, start: &str, end: &str) -> OptionsBuilder {
+        self.commands.push(OptionCommand::AddMultiComment(
+            start.to_string(),
+            end.to_string(),
+        ));
+        self
+    }
+
+    /// Add (`start`, `end`) to the block delimiters, like `--block`.
+    pub fn block(mut self, start: &str, end: &str) -> OptionsBuilder {
+        self.commands.push(OptionCommand::AddBlockSeparator(
+            start.to_string(),
+            end.to_string(),
+        ));
+        self
+    }
+
+    /// Set the regexes for the first and the rest of the characters of an identifier.
+    pub fn identifier(mut self, start: Regex, cont: Regex) -> OptionsBuilder {
+        self.commands.push(OptionCommand::Identifier(start, cont));
+        self
+    }
+
+    /// Parse <> after an identifier as type parameter delimiters.
+    pub fn type_parameter_parsing(mut self, enabled: bool) -> OptionsBuilder {
+        self.commands.push(if enabled {
+            OptionCommand::TypeParameterParsing
+        } else {
+            OptionCommand::NoTypeParameterParsing
+        });
+        self
+    }
+
+    /// Parse lines indented more than the line before them as blocks.
+    pub fn indent_blocks(mut self, enabled: bool) -> OptionsBuilder {
+        self.commands.push(if enabled {
+            OptionCommand::IndentBlocks
+        } else {
+            OptionCommand::NoIndentBlocks
+        });
+        self
+    }
+
+    /// Allow or forbid nesting for every kind of multi-line comment.
+    pub fn nested_comments(mut self, enabled: bool) -> OptionsBuilder {
+        self.commands.push(if enabled {
+            OptionCommand::NestedComments
+        } else {
+            OptionCommand::NoNestedComments
+        });
+        self
+    }
+
+    /// Match identifiers and block keywords regardless of case, like `--ignore-case`.
+    pub fn ignore_case(mut self) -> OptionsBuilder {
+        self.commands.push(OptionCommand::IgnoreCase);
+        self
+    }
+
+    /// Build the options. Fails if the configuration files can't be loaded or the language is
+    /// unknown.
+    pub fn build(self) -> Result<Options, SynsError> {
+        let db = LanguageDatabase::get(self.config.as_deref())?;
+        let lang = self.language.as_deref().or_else(|| {
+            self.extension
+                .as_deref()
+                .and_then(|extension| db.language_for_extension(extension))
+        });
+        let mut opts = Options::from_commands(db, lang, self.commands)?;
+        opts.query = self.query;
+        opts.config = self.config;
+        Ok(opts)
+    }
+}
+
 impl Options {
-    /// Parse options from `args`, using defaults for file type `extension`.
+    /// Parse options from `args`, using defaults for file type `extension`. Fails if the
+    /// configuration files can't be loaded.
     ///
     /// ```
     /// use syns::options::Options;
-    /// let options = Options::new("js".as_ref(), &vec!["syns", "query", "filename"]);
+    /// let options = Options::new("js".as_ref(), &vec!["syns", "query", "filename"]).unwrap();
     /// assert_eq!(options.query, "query");
     /// assert_eq!(options.paths, vec!["filename"]);
     /// assert_eq!(options.only_matching, false);
     /// ```
-    pub fn new<S: AsRef<OsStr>>(extension: &OsStr, args: &[S]) -> Options {
+    pub fn new<S: AsRef<OsStr>>(extension: &OsStr, args: &[S]) -> Result<Options, SynsError> {
+        Options::build(|db| db.language_for_extension(extension), args)
+    }
+
+    /// Parse options from command line arguments, using the defaults for `language` unless
+    /// overridden with `--lang`.
+    /// ```
+    /// use syns::options::Options;
+    /// let options = Options::for_language(Some("python"), &["syns", "query", "filename"]).unwrap();
+    /// assert!(options.single_line_comments.contains("#"));
+    /// let args = ["syns", "--lang", "c", "query", "-"];
+    /// let options = Options::for_language(Some("python"), &args).unwrap();
+    /// assert_eq!(options.language.as_deref(), Some("c"));
+    /// ```
+    pub fn for_language<S: AsRef<OsStr>>(
+        language: Option<&str>,
+        args: &[S],
+    ) -> Result<Options, SynsError> {
+        Options::build(|_| language, args)
+    }
+
+    fn build<'a, F, S>(language: F, args: &[S]) -> Result<Options, SynsError>
+    where
+        F: FnOnce(&'static LanguageDatabase) -> Option<&'a str>,
+        S: AsRef<OsStr>,
+    {
         let (cmds, positionals) = parse_options(args);
+        let config = cmds
+            .iter()
+            .filter_map(|c| {
+                if let OptionCommand::Config(path) = c {
+                    Some(path.as_os_str())
+                } else {
+                    None
+                }
+            })
+            .next_back();
+        let db = LanguageDatabase::get(config)?;
+        if cmds
+            .iter()
+            .any(|c| matches!(c, OptionCommand::ListLanguages))
+        {
+            db.print_languages();
+        }
         let empty_osstring: OsString = "".to_string().into();
+        let has_rules = cmds.iter().any(|c| matches!(c, OptionCommand::Rules(_)));
+        let interactive = cmds.iter().any(|c| matches!(c, OptionCommand::Interactive));
+        let dump = cmds
+            .iter()
+            .any(|c| matches!(c, OptionCommand::DumpTokens | OptionCommand::DumpAst));
 
-        if positionals.is_empty() {
+        if !dump
+            && cmds
+                .iter()
+                .any(|c| matches!(c, OptionCommand::DumpLines(_)))
+        {
+            println!("Invalid argument --lines without --dump-tokens or --dump-ast");
+            print_help(false, 1);
+        }
+        if positionals.is_empty() && !has_rules && !interactive && !dump {
             println!("Missing required argument: PATTERN\n");
             print_help(false, 1);
         };
-        let query = positionals
-            .first()
-            .unwrap_or(&empty_osstring)
-            .to_string_lossy()
-            .to_string();
-
-        let files: Vec<OsString> = positionals.into_iter().skip(1).collect();
+        // With a rule file the patterns come from the rules, in interactive mode they're typed
+        // later and dumping files doesn't need any, so every positional is a path.
+        let (query, files) = if has_rules || interactive || dump {
+            (String::new(), positionals)
+        } else {
+            let query = positionals
+                .first()
+                .unwrap_or(&empty_osstring)
+                .to_string_lossy()
+                .to_string();
+            (query, positionals.into_iter().skip(1).collect())
+        };
 
         let lang = cmds
             .iter()
             .filter_map(|c| {
                 if let OptionCommand::Language(l) = c {
-                    Some(PARSED_DB[l].extensions[0].to_string())
+                    Some(l.as_str())
                 } else {
                     None
                 }
             })
             .next_back()
-            .unwrap_or_else(|| extension.to_string_lossy().to_string());
+            .or_else(|| language(db))
+            .map(|lang| lang.to_string());
 
-        let mut opts: Options = EXTENSION_TO_SETTINGS
-            .get(&lang)
-            .cloned()
-            .unwrap_or_default();
+        let mut opts = Options::from_commands(db, lang.as_deref(), cmds)?;
+        opts.query = query;
+        opts.paths = files;
+
+        Ok(opts)
+    }
+
+    /// Apply `cmds` on top of the defaults for `lang`. Fails if `lang` isn't a known language.
+    fn from_commands(
+        db: &'static LanguageDatabase,
+        lang: Option<&str>,
+        cmds: Vec<OptionCommand>,
+    ) -> Result<Options, SynsError> {
+        let mut opts: Options = match lang {
+            Some(lang) => db
+                .settings
+                .get(lang)
+                .cloned()
+                .ok_or_else(|| SynsError::UnknownLanguage(lang.to_string()))?,
+            None => Options::default(),
+        };
+        // Applies to every multi-line comment, including those added after the flag
+        let mut nested_comments = None;
 
         for cmd in cmds {
             match cmd {
@@ -518,17 +1275,19 @@ impl Options {
                     opts.multi_line_comments.insert((start, end));
                 }
                 OptionCommand::RemoveMultiComment(start, end) => {
+                    opts.nested_comments.remove(&(start.clone(), end.clone()));
                     opts.multi_line_comments.remove(&(start, end));
                 }
                 OptionCommand::AddBlockSeparator(start, end) => {
-                    opts.block_openers.insert(start);
-                    opts.block_closers.insert(end);
+                    if !opts.closes(&start, &end) {
+                        opts.blocks.push(Block::new(&start, &end));
+                    }
                 }
                 OptionCommand::RemoveBlockOpener(start) => {
-                    opts.block_openers.remove(&start);
+                    opts.blocks.retain(|block| block.open != start);
                 }
                 OptionCommand::RemoveBlockCloser(end) => {
-                    opts.block_closers.remove(&end);
+                    opts.blocks.retain(|block| block.close != end);
                 }
                 OptionCommand::OnlyFilesMatching(regex) => {
                     opts.only_files_matching = Some(regex);
@@ -547,38 +1306,159 @@ impl Options {
                 OptionCommand::SearchBinary => opts.search_binary = true,
                 OptionCommand::TypeParameterParsing => opts.type_parameter_parsing = true,
                 OptionCommand::NoTypeParameterParsing => opts.type_parameter_parsing = false,
+                OptionCommand::IndentBlocks => opts.indent_blocks = true,
+                OptionCommand::NoIndentBlocks => opts.indent_blocks = false,
+                OptionCommand::NestedComments => nested_comments = Some(true),
+                OptionCommand::NoNestedComments => nested_comments = Some(false),
+                OptionCommand::IgnoreCase => {
+                    opts.case_insensitive_identifiers = true;
+                    opts.case_insensitive_blocks = true;
+                }
                 OptionCommand::Color(choice) => opts.color = choice,
                 OptionCommand::DumpMachine => opts.dump_machine = true,
+                OptionCommand::Explain => opts.explain = true,
+                OptionCommand::Interactive => opts.interactive = true,
+                OptionCommand::DumpTokens => opts.dump_tokens = true,
+                OptionCommand::DumpAst => opts.dump_ast = true,
+                OptionCommand::DumpLines(lines) => opts.dump_lines = Some(lines),
+                OptionCommand::Replace(template) => opts.replace = Some(template),
+                OptionCommand::DryRun => opts.dry_run = true,
+                OptionCommand::Json => opts.json = true,
+                OptionCommand::Sarif => opts.sarif = true,
+                OptionCommand::Rules(path) => opts.rules = Some(path),
+                OptionCommand::StdinFilename(name) => opts.stdin_filename = Some(name),
+                OptionCommand::Config(path) => opts.config = Some(path),
+                OptionCommand::ListLanguages => {}
+                OptionCommand::Threads(n) => opts.threads = n,
+                OptionCommand::BeforeContext(n) => opts.before_context = n,
+                OptionCommand::AfterContext(n) => opts.after_context = n,
+                OptionCommand::Context(n) => {
+                    opts.before_context = n;
+                    opts.after_context = n;
+                }
                 OptionCommand::Language(_) => {}
             }
         }
 
-        opts.query = query;
-        opts.paths = files;
+        match nested_comments {
+            Some(true) => opts.nested_comments = opts.multi_line_comments.clone(),
+            Some(false) => opts.nested_comments.clear(),
+            None => {}
+        }
+        Ok(opts)
+    }
+
+    /// Start building options without a command line.
+    ///
+    /// ```
+    /// use syns::options::Options;
+    /// let options = Options::builder().language("python").ignore_case().build().unwrap();
+    /// assert!(options.single_line_comments.contains("#"));
+    /// assert!(options.case_insensitive_identifiers);
+    /// ```
+    pub fn builder() -> OptionsBuilder {
+        OptionsBuilder::default()
+    }
+
+    /// Is `name` one of the languages listed by `--lang`, including those from configuration
+    /// files?
+    /// ```
+    /// use syns::options::Options;
+    /// let options = Options::new("".as_ref(), &["syns", "query", "-"]).unwrap();
+    /// assert!(options.is_known_language("python").unwrap());
+    /// assert!(!options.is_known_language("klingon").unwrap());
+    /// ```
+    pub fn is_known_language(&self, name: &str) -> Result<bool, SynsError> {
+        Ok(LanguageDatabase::get(self.config.as_deref())?
+            .languages
+            .contains_key(name))
+    }
 
-        opts
+    /// Names of the languages listed by `--lang`, including those from configuration files, in
+    /// alphabetical order.
+    /// ```
+    /// use syns::options::Options;
+    /// let options = Options::new("".as_ref(), &["syns", "query", "-"]).unwrap();
+    /// let languages = options.known_languages().unwrap();
+    /// assert!(languages.contains(&"python"));
+    /// assert!(languages.is_sorted());
+    /// ```
+    pub fn known_languages(&self) -> Result<Vec<&'static str>, SynsError> {
+        Ok(LanguageDatabase::get(self.config.as_deref())?
+            .languages
+            .keys()
+            .map(|name| name.as_str())
+            .sorted()
+            .collect())
+    }
+
+    /// Detect the language of the file at `path` containing `content`. In order of precedence,
+    /// this uses an Emacs or Vim modeline, the file name, a `#!` line and finally the extension.
+    /// ```
+    /// use syns::options::Options;
+    /// let options = Options::new("".as_ref(), &["syns", "query", "-"]).unwrap();
+    /// let detect = |path: &str, content: &[u8]| options.detect_language(path.as_ref(), content);
+    /// assert_eq!(detect("a/Makefile", b"all:").unwrap(), Some("make"));
+    /// assert_eq!(detect("bin/deploy", b"#!/usr/bin/env python3\n").unwrap(), Some("python"));
+    /// assert_eq!(detect("x.js", b"// vim: ft=c").unwrap(), Some("c"));
+    /// ```
+    pub fn detect_language(
+        &self,
+        path: &Path,
+        content: &[u8],
+    ) -> Result<Option<&'static str>, SynsError> {
+        Ok(LanguageDatabase::get(self.config.as_deref())?.detect_language(path, content))
     }
 
     /// Is `c` an open paren for the current file type?
     /// ```
     /// use syns::options::Options;
-    /// let options = Options::new("js".as_ref(), &vec!["syns", "query", "filename"]);
+    /// let options = Options::new("js".as_ref(), &vec!["syns", "query", "filename"]).unwrap();
     /// assert!(options.is_open_paren("{"));
     /// assert!(!options.is_open_paren("}"));
     /// ```
     pub fn is_open_paren(&self, c: &str) -> bool {
-        self.block_openers.iter().any(|s| c == s)
+        self.blocks.iter().any(|block| block.open == c)
     }
 
     /// Is `c` a close paren for the current file type?
     /// ```
     /// use syns::options::Options;
-    /// let options = Options::new("js".as_ref(), &vec!["syns", "query", "filename"]);
+    /// let options = Options::new("js".as_ref(), &vec!["syns", "query", "filename"]).unwrap();
     /// assert!(!options.is_close_paren("{"));
     /// assert!(options.is_close_paren("}"));
     /// ```
     pub fn is_close_paren(&self, c: &str) -> bool {
-        self.block_closers.iter().any(|e| c == e)
+        self.blocks.iter().any(|block| block.close == c)
+    }
+
+    /// Does `close` end a block opened by `open`?
+    /// ```
+    /// use syns::options::Options;
+    /// let options = Options::new("js".as_ref(), &vec!["syns", "query", "filename"]).unwrap();
+    /// assert!(options.closes("(", ")"));
+    /// assert!(!options.closes("(", "]"));
+    /// ```
+    pub fn closes(&self, open: &str, close: &str) -> bool {
+        self.blocks
+            .iter()
+            .any(|block| block.open == open && block.close == close)
+    }
+
+    /// All openers, closers and intermediate keywords of blocks.
+    pub fn block_keywords(&self) -> impl Iterator<Item = &String> {
+        self.blocks
+            .iter()
+            .flat_map(|block| [&block.open, &block.close].into_iter().chain(&block.middle))
+    }
+
+    /// Compare the words `a` and `b` of block keywords, ignoring case if the language does.
+    pub fn block_keyword_eq(&self, a: &str, b: &str) -> bool {
+        if self.case_insensitive_blocks {
+            a.eq_ignore_ascii_case(b)
+        } else {
+            a == b
+        }
     }
 }
 
@@ -588,14 +1468,14 @@ mod tests {
 
     #[test]
     fn parse_options() {
-        let options = Options::new("js".as_ref(), &["syns", "query", "filename"]);
+        let options = Options::new("js".as_ref(), &["syns", "query", "filename"]).expect("options");
         assert_eq!(options.query, "query");
         assert_eq!(options.paths[0], "filename");
     }
 
     #[test]
     fn options_parens() {
-        let options = Options::new("js".as_ref(), &["syns", "query", "filename"]);
+        let options = Options::new("js".as_ref(), &["syns", "query", "filename"]).expect("options");
         assert!(options.is_open_paren("{"));
         assert!(options.is_open_paren("("));
         assert!(options.is_open_paren("["));
@@ -619,14 +1499,16 @@ mod tests {
 
     #[test]
     fn multiple_paths() {
-        let options = Options::new("js".as_ref(), &["syns", "query", "a", "b", "c"]);
+        let options =
+            Options::new("js".as_ref(), &["syns", "query", "a", "b", "c"]).expect("options");
         assert_eq!(options.query, "query");
         assert_eq!(options.paths, vec!["a", "b", "c"]);
     }
 
     #[test]
     fn lang_flag() {
-        let options = Options::new("".as_ref(), &["syns", "--lang", "c", "query", "file"]);
+        let options =
+            Options::new("".as_ref(), &["syns", "--lang", "c", "query", "file"]).expect("options");
         assert!(options.string_characters.contains("\""));
         assert!(options.string_characters.contains("'"));
         assert!(!options.string_characters.contains("`"));
@@ -635,32 +1517,37 @@ mod tests {
 
     #[test]
     fn add_string_character_short() {
-        let options = Options::new("".as_ref(), &["syns", "-s", "|", "query", "file"]);
+        let options =
+            Options::new("".as_ref(), &["syns", "-s", "|", "query", "file"]).expect("options");
         assert!(options.string_characters.contains("|"));
     }
 
     #[test]
     fn add_string_character_long() {
-        let options = Options::new("".as_ref(), &["syns", "--string", "|", "query", "file"]);
+        let options = Options::new("".as_ref(), &["syns", "--string", "|", "query", "file"])
+            .expect("options");
         assert!(options.string_characters.contains("|"));
     }
 
     #[test]
     fn remove_string_character() {
-        let options = Options::new("".as_ref(), &["syns", "--no-string", "'", "query", "file"]);
+        let options = Options::new("".as_ref(), &["syns", "--no-string", "'", "query", "file"])
+            .expect("options");
         assert!(!options.string_characters.contains("'"));
         assert!(options.string_characters.contains("\""));
     }
 
     #[test]
     fn add_single_comment_short() {
-        let options = Options::new("".as_ref(), &["syns", "-c", "#", "query", "file"]);
+        let options =
+            Options::new("".as_ref(), &["syns", "-c", "#", "query", "file"]).expect("options");
         assert!(options.single_line_comments.contains("#"));
     }
 
     #[test]
     fn add_single_comment_long() {
-        let options = Options::new("".as_ref(), &["syns", "--comment", "#", "query", "file"]);
+        let options = Options::new("".as_ref(), &["syns", "--comment", "#", "query", "file"])
+            .expect("options");
         assert!(options.single_line_comments.contains("#"));
     }
 
@@ -669,13 +1556,15 @@ mod tests {
         let options = Options::new(
             "".as_ref(),
             &["syns", "--no-comment", "//", "query", "file"],
-        );
+        )
+        .expect("options");
         assert!(!options.single_line_comments.contains("//"));
     }
 
     #[test]
     fn add_multi_comment_short() {
-        let options = Options::new("".as_ref(), &["syns", "-m", "<!--", "-->", "query", "file"]);
+        let options = Options::new("".as_ref(), &["syns", "-m", "<!--", "-->", "query", "file"])
+            .expect("options");
         assert!(options
             .multi_line_comments
             .contains(&("<!--".to_string(), "-->".to_string())));
@@ -686,7 +1575,8 @@ mod tests {
         let options = Options::new(
             "".as_ref(),
             &["syns", "--multi", "<!--", "-->", "query", "file"],
-        );
+        )
+        .expect("options");
         assert!(options
             .multi_line_comments
             .contains(&("<!--".to_string(), "-->".to_string())));
@@ -697,7 +1587,8 @@ mod tests {
         let options = Options::new(
             "".as_ref(),
             &["syns", "--no-multi", "/*", "*/", "query", "file"],
-        );
+        )
+        .expect("options");
         assert!(!options
             .multi_line_comments
             .contains(&("/*".to_string(), "*/".to_string())));
@@ -705,9 +1596,9 @@ mod tests {
 
     #[test]
     fn add_block_separator_short() {
-        let options = Options::new("".as_ref(), &["syns", "-b", "do", "end", "query", "file"]);
-        assert!(options.block_openers.contains("do"));
-        assert!(options.block_closers.contains("end"));
+        let options = Options::new("".as_ref(), &["syns", "-b", "do", "end", "query", "file"])
+            .expect("options");
+        assert!(options.closes("do", "end"));
     }
 
     #[test]
@@ -715,9 +1606,9 @@ mod tests {
         let options = Options::new(
             "".as_ref(),
             &["syns", "--block", "do", "end", "query", "file"],
-        );
-        assert!(options.block_openers.contains("do"));
-        assert!(options.block_closers.contains("end"));
+        )
+        .expect("options");
+        assert!(options.closes("do", "end"));
     }
 
     #[test]
@@ -725,9 +1616,11 @@ mod tests {
         let options = Options::new(
             "".as_ref(),
             &["syns", "--no-block-opener", "(", "query", "file"],
-        );
-        assert!(!options.block_openers.contains("("));
-        assert!(options.block_openers.contains("["));
+        )
+        .expect("options");
+        assert!(!options.is_open_paren("("));
+        assert!(!options.is_close_paren(")"));
+        assert!(options.is_open_paren("["));
     }
 
     #[test]
@@ -735,9 +1628,11 @@ mod tests {
         let options = Options::new(
             "".as_ref(),
             &["syns", "--no-block-closer", ")", "query", "file"],
-        );
-        assert!(!options.block_closers.contains(")"));
-        assert!(options.block_closers.contains("]"));
+        )
+        .expect("options");
+        assert!(!options.is_close_paren(")"));
+        assert!(!options.is_open_paren("("));
+        assert!(options.is_close_paren("]"));
     }
 
     #[test]
@@ -745,7 +1640,8 @@ mod tests {
         let options = Options::new(
             "".as_ref(),
             &["syns", "-i", "[a-z]", "[a-z0-9]", "query", "file"],
-        );
+        )
+        .expect("options");
         assert!(options.identifier_regex_start.is_match("a"));
         assert!(!options.identifier_regex_start.is_match("A"));
         assert!(options.identifier_regex_continue.is_match("1"));
@@ -756,7 +1652,8 @@ mod tests {
         let options = Options::new(
             "".as_ref(),
             &["syns", "--identifier", "[a-z]", "[a-z0-9]", "query", "file"],
-        );
+        )
+        .expect("options");
         assert!(options.identifier_regex_start.is_match("a"));
         assert!(!options.identifier_regex_start.is_match("A"));
     }
@@ -766,8 +1663,9 @@ mod tests {
         let options = Options::new(
             "".as_ref(),
             &["syns", "--only-files-matching", "\\.rs$", "query", "file"],
-        );
-        let re = options.only_files_matching.unwrap();
+        )
+        .expect("options");
+        let re = options.only_files_matching.expect("regex");
         assert!(re.is_match("main.rs"));
         assert!(!re.is_match("main.js"));
     }
@@ -777,27 +1675,29 @@ mod tests {
         let options = Options::new(
             "".as_ref(),
             &["syns", "--ignore-files-matching", "\\.rs$", "query", "file"],
-        );
-        let re = options.ignore_files_matching.unwrap();
+        )
+        .expect("options");
+        let re = options.ignore_files_matching.expect("regex");
         assert!(re.is_match("main.rs"));
         assert!(!re.is_match("main.js"));
     }
 
     #[test]
     fn only_matching_short() {
-        let options = Options::new("".as_ref(), &["syns", "-o", "query", "file"]);
+        let options = Options::new("".as_ref(), &["syns", "-o", "query", "file"]).expect("options");
         assert!(options.only_matching);
     }
 
     #[test]
     fn only_matching_long() {
-        let options = Options::new("".as_ref(), &["syns", "--only-matching", "query", "file"]);
+        let options = Options::new("".as_ref(), &["syns", "--only-matching", "query", "file"])
+            .expect("options");
         assert!(options.only_matching);
     }
 
     #[test]
     fn only_print_filenames_short() {
-        let options = Options::new("".as_ref(), &["syns", "-l", "query", "file"]);
+        let options = Options::new("".as_ref(), &["syns", "-l", "query", "file"]).expect("options");
         assert!(options.only_print_filenames);
     }
 
@@ -806,13 +1706,14 @@ mod tests {
         let options = Options::new(
             "".as_ref(),
             &["syns", "--only-print-filenames", "query", "file"],
-        );
+        )
+        .expect("options");
         assert!(options.only_print_filenames);
     }
 
     #[test]
     fn dont_print_filenames_short() {
-        let options = Options::new("".as_ref(), &["syns", "-I", "query", "file"]);
+        let options = Options::new("".as_ref(), &["syns", "-I", "query", "file"]).expect("options");
         assert!(options.dont_print_filenames);
     }
 
@@ -821,31 +1722,34 @@ mod tests {
         let options = Options::new(
             "".as_ref(),
             &["syns", "--dont-print-filenames", "query", "file"],
-        );
+        )
+        .expect("options");
         assert!(options.dont_print_filenames);
     }
 
     #[test]
     fn follow_symlinks_short() {
-        let options = Options::new("".as_ref(), &["syns", "-L", "query", "file"]);
+        let options = Options::new("".as_ref(), &["syns", "-L", "query", "file"]).expect("options");
         assert!(options.follow_symlinks);
     }
 
     #[test]
     fn follow_symlinks_long() {
-        let options = Options::new("".as_ref(), &["syns", "--follow", "query", "file"]);
+        let options =
+            Options::new("".as_ref(), &["syns", "--follow", "query", "file"]).expect("options");
         assert!(options.follow_symlinks);
     }
 
     #[test]
     fn search_binary_short() {
-        let options = Options::new("".as_ref(), &["syns", "-a", "query", "file"]);
+        let options = Options::new("".as_ref(), &["syns", "-a", "query", "file"]).expect("options");
         assert!(options.search_binary);
     }
 
     #[test]
     fn search_binary_long() {
-        let options = Options::new("".as_ref(), &["syns", "--text", "query", "file"]);
+        let options =
+            Options::new("".as_ref(), &["syns", "--text", "query", "file"]).expect("options");
         assert!(options.search_binary);
     }
 
@@ -854,7 +1758,8 @@ mod tests {
         let options = Options::new(
             "".as_ref(),
             &["syns", "--type-parameter-parsing", "query", "file"],
-        );
+        )
+        .expect("options");
         assert!(options.type_parameter_parsing);
     }
 
@@ -869,54 +1774,554 @@ mod tests {
                 "query",
                 "file",
             ],
-        );
+        )
+        .expect("options");
         assert!(!options.type_parameter_parsing);
     }
 
+    #[test]
+    fn indent_blocks_flag() {
+        let options = Options::new("".as_ref(), &["syns", "--indent-blocks", "query", "file"])
+            .expect("options");
+        assert!(options.indent_blocks);
+        let options = Options::new(
+            "py".as_ref(),
+            &["syns", "--no-indent-blocks", "query", "file"],
+        )
+        .expect("options");
+        assert!(!options.indent_blocks);
+    }
+
+    #[test]
+    fn nested_comments_flag() {
+        let options = Options::new("".as_ref(), &["syns", "--nested-comments", "query", "file"])
+            .expect("options");
+        assert_eq!(options.nested_comments, options.multi_line_comments);
+        let options = Options::new(
+            "d".as_ref(),
+            &[
+                "syns",
+                "--nested-comments",
+                "--multi",
+                "{-",
+                "-}",
+                "query",
+                "file",
+            ],
+        )
+        .expect("options");
+        assert_eq!(options.nested_comments.len(), 3);
+        let options = Options::new(
+            "rs".as_ref(),
+            &["syns", "--no-nested-comments", "query", "file"],
+        )
+        .expect("options");
+        assert!(options.nested_comments.is_empty());
+    }
+
+    #[test]
+    fn ignore_case_flag() {
+        let options = Options::new("".as_ref(), &["syns", "--ignore-case", "query", "file"])
+            .expect("options");
+        assert!(options.case_insensitive_identifiers);
+        assert!(options.case_insensitive_blocks);
+    }
+
     #[test]
     fn color_flag() {
-        let options = Options::new("".as_ref(), &["syns", "--color", "query", "file"]);
+        let options =
+            Options::new("".as_ref(), &["syns", "--color", "query", "file"]).expect("options");
         assert_eq!(options.color, ColorChoice::Always);
     }
 
     #[test]
     fn no_color_flag() {
-        let options = Options::new("".as_ref(), &["syns", "--no-color", "query", "file"]);
+        let options =
+            Options::new("".as_ref(), &["syns", "--no-color", "query", "file"]).expect("options");
         assert_eq!(options.color, ColorChoice::Never);
     }
 
     #[test]
     fn dump_machine_flag() {
-        let options = Options::new("".as_ref(), &["syns", "--dump-machine", "query", "file"]);
+        let options = Options::new("".as_ref(), &["syns", "--dump-machine", "query", "file"])
+            .expect("options");
         assert!(options.dump_machine);
     }
 
+    #[test]
+    fn explain_flag() {
+        let options =
+            Options::new("".as_ref(), &["syns", "--explain", "query", "file"]).expect("options");
+        assert!(options.explain);
+    }
+
+    #[test]
+    fn dump_flags() {
+        let options = Options::new(
+            "".as_ref(),
+            &[
+                "syns",
+                "--dump-tokens",
+                "--dump-ast",
+                "--lines",
+                "3-5",
+                "a.c",
+            ],
+        )
+        .expect("options");
+        assert!(options.dump_tokens);
+        assert!(options.dump_ast);
+        assert_eq!(options.dump_lines, Some(3..=5));
+        assert_eq!(options.query, "");
+        assert_eq!(options.paths, vec!["a.c"]);
+    }
+
+    #[test]
+    fn interactive_flag() {
+        let options =
+            Options::new("".as_ref(), &["syns", "--interactive", "a.c", "b.c"]).expect("options");
+        assert!(options.interactive);
+        assert_eq!(options.query, "");
+        assert_eq!(options.paths, vec!["a.c", "b.c"]);
+    }
+
+    #[test]
+    fn replace_flag() {
+        let options = Options::new(
+            "".as_ref(),
+            &[
+                "syns",
+                "--replace",
+                "bar(\\:x)",
+                "--dry-run",
+                "query",
+                "file",
+            ],
+        )
+        .expect("options");
+        assert_eq!(options.replace.as_deref(), Some("bar(\\:x)"));
+        assert!(options.dry_run);
+        assert_eq!(options.query, "query");
+    }
+
+    #[test]
+    fn json_flag() {
+        let options =
+            Options::new("".as_ref(), &["syns", "--json", "query", "file"]).expect("options");
+        assert!(options.json);
+    }
+
+    #[test]
+    fn sarif_flag() {
+        let options =
+            Options::new("".as_ref(), &["syns", "--sarif", "query", "file"]).expect("options");
+        assert!(options.sarif);
+    }
+
+    #[test]
+    fn rules_flag() {
+        let options = Options::new("".as_ref(), &["syns", "--rules", "rules.json", "a", "b"])
+            .expect("options");
+        assert_eq!(options.rules, Some("rules.json".into()));
+        assert_eq!(options.query, "");
+        assert_eq!(options.paths, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn threads_flag() {
+        let options =
+            Options::new("".as_ref(), &["syns", "-j", "4", "query", "file"]).expect("options");
+        assert_eq!(options.threads, 4);
+        let options = Options::new("".as_ref(), &["syns", "--threads", "1", "query", "file"])
+            .expect("options");
+        assert_eq!(options.threads, 1);
+    }
+
+    #[test]
+    fn context_flags() {
+        let options =
+            Options::new("".as_ref(), &["syns", "-C", "3", "query", "file"]).expect("options");
+        assert_eq!((options.before_context, options.after_context), (3, 3));
+        let options = Options::new(
+            "".as_ref(),
+            &["syns", "-C", "3", "-A", "1", "query", "file"],
+        )
+        .expect("options");
+        assert_eq!((options.before_context, options.after_context), (3, 1));
+        let options = Options::new(
+            "".as_ref(),
+            &[
+                "syns",
+                "--before-context",
+                "2",
+                "--context",
+                "0",
+                "query",
+                "file",
+            ],
+        )
+        .expect("options");
+        assert_eq!((options.before_context, options.after_context), (0, 0));
+    }
+
+    #[test]
+    fn stdin_filename_flag() {
+        let options = Options::new(
+            "c".as_ref(),
+            &["syns", "--stdin-filename", "x.c", "query", "-"],
+        )
+        .expect("options");
+        assert_eq!(options.stdin_filename, Some("x.c".into()));
+        assert_eq!(options.paths, vec!["-"]);
+    }
+
+    #[test]
+    fn language_name() {
+        let options = Options::new("py".as_ref(), &["syns", "query", "file"]).expect("options");
+        assert_eq!(options.language.as_deref(), Some("python"));
+        let options =
+            Options::new("unknown".as_ref(), &["syns", "query", "file"]).expect("options");
+        assert_eq!(options.language, None);
+    }
+
     #[test]
     fn default_options() {
         let opts = Options::default();
         assert!(opts.string_characters.contains("\""));
         assert!(opts.string_characters.contains("'"));
         assert!(opts.string_characters.contains("`"));
+        assert!(opts.string_prefixes.is_empty());
+        assert!(opts.raw_strings.is_empty());
+        assert!(opts.heredocs.is_empty());
+        assert!(!opts.heredoc_spaces);
         assert!(opts.single_line_comments.contains("//"));
         assert!(opts
             .multi_line_comments
             .contains(&("/*".to_string(), "*/".to_string())));
-        assert!(opts.block_openers.contains("("));
-        assert!(opts.block_closers.contains(")"));
+        assert!(opts.nested_comments.is_empty());
+        assert!(opts.closes("(", ")"));
+        assert!(!opts.case_insensitive_blocks);
+        assert!(!opts.case_insensitive_identifiers);
         assert!(!opts.only_matching);
         assert!(!opts.only_print_filenames);
         assert!(!opts.dont_print_filenames);
         assert!(!opts.follow_symlinks);
         assert!(!opts.search_binary);
         assert!(!opts.type_parameter_parsing);
+        assert!(!opts.indent_blocks);
         assert!(opts.ranges);
         assert_eq!(opts.color, ColorChoice::Auto);
         assert!(!opts.dump_machine);
+        assert!(!opts.explain);
+        assert!(!opts.interactive);
+        assert!(!opts.dump_tokens);
+        assert!(!opts.dump_ast);
+        assert!(opts.dump_lines.is_none());
+        assert!(opts.replace.is_none());
+        assert!(!opts.dry_run);
+        assert!(!opts.json);
+        assert!(!opts.sarif);
+        assert!(opts.rules.is_none());
+        assert_eq!(opts.threads, 0);
+        assert_eq!(opts.before_context, 0);
+        assert_eq!(opts.after_context, 0);
+        assert!(opts.stdin_filename.is_none());
+        assert!(opts.config.is_none());
+        assert!(opts.language.is_none());
+    }
+
+    #[test]
+    fn detect_language() {
+        let detect = |path: &str, content: &str| {
+            Options::default()
+                .detect_language(path.as_ref(), content.as_bytes())
+                .expect("options")
+        };
+        assert_eq!(detect("Makefile", ""), Some("make"));
+        assert_eq!(detect("docker/Dockerfile", ""), Some("dockerfile"));
+        assert_eq!(detect("Rakefile", ""), Some("ruby"));
+        assert_eq!(detect("/home/user/.bashrc", ""), Some("shell"));
+        assert_eq!(
+            detect("bin/deploy", "#!/usr/bin/env python3\nimport os\n"),
+            Some("python")
+        );
+        assert_eq!(
+            detect("bin/deploy", "#!/usr/bin/python3.11 -u\n"),
+            Some("python")
+        );
+        assert_eq!(detect("bin/run", "#! /bin/bash -e\n"), Some("shell"));
+        assert_eq!(
+            detect("bin/run", "#!/usr/bin/env -S node --harmony\n"),
+            Some("javascript")
+        );
+        assert_eq!(detect("bin/run", "#!/usr/bin/klingon\n"), None);
+        assert_eq!(detect("bin/run", "echo '#!/bin/sh'\n"), None);
+        assert_eq!(detect("x.h", ""), Some("c"));
+        assert_eq!(detect("x.unknown", ""), None);
+        assert_eq!(detect("x", ""), None);
+    }
+
+    #[test]
+    fn detect_language_modeline() {
+        let detect = |path: &str, content: &str| {
+            Options::default()
+                .detect_language(path.as_ref(), content.as_bytes())
+                .expect("options")
+        };
+        assert_eq!(detect("x", "# -*- mode: ruby -*-\n"), Some("ruby"));
+        assert_eq!(
+            detect("x", "# -*- coding: utf-8; mode: Python -*-\n"),
+            Some("python")
+        );
+        assert_eq!(detect("x", "/* -*- c++ -*- */\n"), Some("c"));
+        assert_eq!(detect("x", "# -*- coding: utf-8 -*-\n"), None);
+        assert_eq!(detect("x", "# vim: set ft=sh:\n"), Some("shell"));
+        assert_eq!(detect("x", "// vim600: filetype=rust\n"), Some("rust"));
+        // Modelines take precedence over the file name, extension and shebang
+        assert_eq!(detect("Makefile", "# vim: ft=python\n"), Some("python"));
+        assert_eq!(detect("a.js", "#!/bin/sh\n# vi: ft=ruby\n"), Some("ruby"));
+        // Only the first and last five lines are searched
+        let body = "\n".repeat(10);
+        assert_eq!(
+            detect("x", &format!("{}# vim: ft=sh\n", body)),
+            Some("shell")
+        );
+        assert_eq!(
+            detect("x", &format!("{}# vim: ft=sh\n{}", body, body)),
+            None
+        );
+        assert_eq!(
+            detect("x", &format!("{}# vim: ft=sh\na\nb\nc\nd", body)),
+            Some("shell")
+        );
+        assert_eq!(
+            detect("x", &format!("{}# vim: ft=sh\na\nb\nc\nd\ne", body)),
+            None
+        );
+        assert_eq!(
+            detect("x", &format!("a\nb\nc\nd\n# vim: ft=sh{}", body)),
+            Some("shell")
+        );
+        assert_eq!(
+            detect("x", &format!("a\nb\nc\nd\ne\n# vim: ft=sh{}", body)),
+            None
+        );
+        // Invalid UTF-8 in the rest of the file doesn't matter
+        let mut content = b"# vim: ft=sh\n".to_vec();
+        content.extend(b"\xff\n".repeat(20));
+        assert_eq!(
+            Options::default()
+                .detect_language("x".as_ref(), &content)
+                .expect("options"),
+            Some("shell")
+        );
+    }
+
+    fn has_middle(options: &Options, open: &str, keyword: &str) -> bool {
+        options
+            .blocks
+            .iter()
+            .any(|block| block.open == open && block.middle.iter().any(|m| m == keyword))
+    }
+
+    fn layered(layers: &[&str]) -> Result<LanguageDatabase, String> {
+        let layers = layers
+            .iter()
+            .map(|json| (Path::new("config.json"), json.to_string()))
+            .collect::<Vec<_>>();
+        LanguageDatabase::from_layers(&layers)
+    }
+
+    #[test]
+    fn config_layers() {
+        let db = layered(&[
+            r##"{"python": {"strings": ["'"]}, "hcl": {"extensions": ["tf"], "single_comments": ["#"]}}"##,
+            r#"{"python": {"single_comments": ["//"]}}"#,
+        ])
+        .expect("Failed to load layers");
+        let db: &'static LanguageDatabase = Box::leak(Box::new(db));
+
+        // Fields are replaced one by one, later layers winning
+        let python = &db.settings["python"];
+        assert_eq!(python.string_characters, ["'".to_string()].into());
+        assert_eq!(python.single_line_comments, ["//".to_string()].into());
+        assert_eq!(db.language_for_extension("py".as_ref()), Some("python"));
+
+        // New languages use the general defaults for identifiers and blocks
+        let hcl = &db.settings["hcl"];
+        assert_eq!(hcl.language.as_deref(), Some("hcl"));
+        assert!(hcl.single_line_comments.contains("#"));
+        assert!(hcl.string_characters.is_empty());
+        assert!(hcl.closes("{", "}"));
+        assert_eq!(db.language_for_extension("tf".as_ref()), Some("hcl"));
+        assert_eq!(db.language_for_extension("c".as_ref()), Some("c"));
+    }
+
+    #[test]
+    fn config_layers_take_over_extensions() {
+        let db =
+            layered(&[r#"{"cpp": {"extensions": ["cpp", "hpp"], "filenames": ["Makefile"]}}"#])
+                .expect("Failed to load layers");
+        let db: &'static LanguageDatabase = Box::leak(Box::new(db));
+        assert_eq!(db.language_for_extension("cpp".as_ref()), Some("cpp"));
+        assert_eq!(db.language_for_extension("c".as_ref()), Some("c"));
+        assert_eq!(db.detect_language("Makefile".as_ref(), b""), Some("cpp"));
+        assert!(!db.languages["c"].extensions.contains(&"cpp".to_string()));
+    }
+
+    #[test]
+    fn invalid_config_layers() {
+        assert!(layered(&["[]"]).is_err());
+        assert!(layered(&[r#"{"python": []}"#]).is_err());
+        assert!(layered(&[r#"{"python": {"strings": "'"}}"#]).is_err());
+        assert!(layered(&[r#"{"python": {"string": ["'"]}}"#]).is_err());
+        assert!(layered(&[r#"{"python": {"identifier": ["[", "a"]}}"#]).is_err());
+        assert!(layered(&[r#"{"python": {"blocks": [["do"]]}}"#]).is_err());
+    }
+
+    #[test]
+    fn unreadable_config() {
+        let args = ["syns", "--config", "test-files/missing.json", "query", "-"];
+        match Options::new("".as_ref(), &args) {
+            Err(SynsError::Config(message)) => {
+                assert!(message
+                    .starts_with("Failed to read configuration file test-files/missing.json"))
+            }
+            res => panic!("Unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn unknown_language() {
+        assert_eq!(
+            Options::builder().language("pyhton").build().err(),
+            Some(SynsError::UnknownLanguage("pyhton".to_string()))
+        );
+        let args = ["syns", "--lang", "pyhton", "query", "-"];
+        assert_eq!(
+            Options::new("py".as_ref(), &args).err(),
+            Some(SynsError::UnknownLanguage("pyhton".to_string()))
+        );
+        let options = Options::builder()
+            .language("python")
+            .build()
+            .expect("options");
+        assert_eq!(options.language.as_deref(), Some("python"));
+    }
+
+    #[test]
+    fn config_blocks() {
+        let db = layered(&[
+            r#"{"lua": {"blocks": [["(", ")"], ["do", "end"], ["then", "end", ["elseif", "else"]]]}}"#,
+        ])
+        .expect("Failed to load layers");
+        let lua = &db.settings["lua"];
+        assert!(lua.closes("do", "end"));
+        assert!(lua.closes("then", "end"));
+        assert!(has_middle(lua, "then", "else"));
+        assert!(!has_middle(lua, "do", "else"));
+        assert!(!lua.is_open_paren("{"));
+    }
+
+    #[test]
+    fn string_form_defaults() {
+        let python =
+            Options::for_language(Some("python"), &["syns", "query", "file"]).expect("options");
+        assert!(python.string_characters.contains("\"\"\""));
+        assert!(python.string_prefixes.contains("rb"));
+        let rust =
+            Options::for_language(Some("rust"), &["syns", "query", "file"]).expect("options");
+        assert_eq!(rust.raw_strings.get("br"), Some(&RawString::Hashes));
+        let c = Options::for_language(Some("c"), &["syns", "query", "file"]).expect("options");
+        assert_eq!(c.raw_strings.get("u8R"), Some(&RawString::Parens));
+        let csharp =
+            Options::for_language(Some("csharp"), &["syns", "query", "file"]).expect("options");
+        assert_eq!(csharp.raw_strings.get("@"), Some(&RawString::Doubled));
+        assert!(csharp.string_prefixes.contains("$"));
+        for language in ["shell", "ruby", "php"] {
+            let options =
+                Options::for_language(Some(language), &["syns", "query", "file"]).expect("options");
+            assert!(!options.heredocs.is_empty(), "{}", language);
+            assert_eq!(options.heredoc_spaces, language != "ruby", "{}", language);
+        }
+        assert!(layered(&[r#"{"rust": {"raw_strings": {"r": "dashes"}}}"#]).is_err());
+    }
+
+    #[test]
+    fn nested_comment_defaults() {
+        for language in ["rust", "haskell", "swift", "scala", "ocaml"] {
+            let options =
+                Options::for_language(Some(language), &["syns", "query", "file"]).expect("options");
+            assert_eq!(
+                options.nested_comments, options.multi_line_comments,
+                "{}",
+                language
+            );
+        }
+        let options =
+            Options::for_language(Some("d"), &["syns", "query", "file"]).expect("options");
+        assert!(options
+            .nested_comments
+            .contains(&("/+".to_string(), "+/".to_string())));
+        assert!(!options
+            .nested_comments
+            .contains(&("/*".to_string(), "*/".to_string())));
+        let options =
+            Options::for_language(Some("c"), &["syns", "query", "file"]).expect("options");
+        assert!(options.nested_comments.is_empty());
+    }
+
+    #[test]
+    fn indent_block_defaults() {
+        for language in ["python", "haskell", "nim", "yaml"] {
+            let options =
+                Options::for_language(Some(language), &["syns", "query", "file"]).expect("options");
+            assert!(options.indent_blocks, "{}", language);
+        }
+        let options =
+            Options::for_language(Some("c"), &["syns", "query", "file"]).expect("options");
+        assert!(!options.indent_blocks);
+    }
+
+    #[test]
+    fn keyword_block_defaults() {
+        let elixir =
+            Options::for_language(Some("elixir"), &["syns", "query", "file"]).expect("options");
+        assert!(elixir.closes("do", "end"));
+        assert!(elixir.closes("fn", "end"));
+        assert!(elixir.closes("(", ")"));
+        assert!(!elixir.case_insensitive_blocks);
+
+        let vb = Options::for_language(Some("visualbasic"), &["syns", "query", "file"])
+            .expect("options");
+        assert!(vb.closes("Sub", "End Sub"));
+        assert!(has_middle(&vb, "Try", "Catch"));
+        assert!(vb.block_keyword_eq("END", "End"));
+
+        let sql = Options::for_language(Some("sql"), &["syns", "query", "file"]).expect("options");
+        assert!(sql.closes("BEGIN", "END"));
+        assert!(sql.closes("CASE", "END"));
+        assert!(has_middle(&sql, "CASE", "WHEN"));
+        assert!(sql.case_insensitive_blocks);
+        assert!(sql.case_insensitive_identifiers);
+        assert!(vb.case_insensitive_identifiers);
+        assert!(!elixir.case_insensitive_identifiers);
+    }
+
+    #[test]
+    fn language_defaults() {
+        let options =
+            Options::for_language(Some("shell"), &["syns", "query", "file"]).expect("options");
+        assert_eq!(options.language.as_deref(), Some("shell"));
+        assert!(options.single_line_comments.contains("#"));
+        let options = Options::for_language(None, &["syns", "query", "file"]).expect("options");
+        assert_eq!(options.language, None);
+        assert!(options.single_line_comments.contains("//"));
     }
 
     #[test]
     fn extension_based_defaults() {
-        let options = Options::new("rs".as_ref(), &["syns", "query", "file"]);
+        let options = Options::new("rs".as_ref(), &["syns", "query", "file"]).expect("options");
         // Rust uses " for strings but not ' or `
         assert!(options.string_characters.contains("\""));
         assert!(!options.string_characters.contains("`"));
@@ -936,7 +2341,8 @@ mod tests {
                 "query",
                 "file",
             ],
-        );
+        )
+        .expect("options");
         assert!(options.only_matching);
         assert!(options.only_print_filenames);
         assert!(options.follow_symlinks);
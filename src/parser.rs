@@ -5,12 +5,13 @@ use regex::Regex;
 use std::convert::TryInto;
 use std::io::Read;
 
+use crate::error::SynsError;
 use crate::multipeek_putbackn::{multipeek_put_back_n, MultiPeekPutBackN};
 use crate::options::Options;
 use crate::psi::{PeekableStringIterator, Span};
 use crate::tokenizer::{
-    tokenize, tokenize_query, QueryToken, QueryTokenType, SpecialTokenType, StandardToken,
-    StandardTokenType,
+    tokenize, tokenize_query, NumberBound, QueryToken, QueryTokenType, SpecialTokenType,
+    StandardToken, StandardTokenType, TokenClass,
 };
 use crate::wrappers::RegexEq;
 
@@ -43,6 +44,50 @@ impl Ast {
             ),
         }
     }
+
+    /// Is this node a comment?
+    pub fn is_comment(&self) -> bool {
+        matches!(
+            self,
+            Ast::Token(StandardToken {
+                ty: StandardTokenType::Comment(_),
+                ..
+            })
+        )
+    }
+
+    /// Compare two AST nodes while ignoring their spans and any comments inside them.
+    pub fn structurally_eq(&self, other: &Ast, ignore_case: bool) -> bool {
+        match (self, other) {
+            (Ast::Token(t1), Ast::Token(t2)) => t1.ty.eq_ignoring_case(&t2.ty, ignore_case),
+            (
+                Ast::Delimited {
+                    op: op1,
+                    cp: cp1,
+                    content: content1,
+                },
+                Ast::Delimited {
+                    op: op2,
+                    cp: cp2,
+                    content: content2,
+                },
+            ) => {
+                let content1 = content1.iter().filter(|ast| !ast.is_comment());
+                let content2 = content2.iter().filter(|ast| !ast.is_comment());
+                let cp_eq = match (cp1, cp2) {
+                    (Some(cp1), Some(cp2)) => cp1.ty.eq_ignoring_case(&cp2.ty, ignore_case),
+                    (cp1, cp2) => cp1.is_none() && cp2.is_none(),
+                };
+                op1.ty.eq_ignoring_case(&op2.ty, ignore_case)
+                    && cp_eq
+                    && content1.clone().count() == content2.clone().count()
+                    && content1
+                        .zip(content2)
+                        .all(|(a, b)| a.structurally_eq(b, ignore_case))
+            }
+            _ => false,
+        }
+    }
 }
 
 fn is_open_type_param(c: &str, inside_type_param: bool) -> bool {
@@ -53,6 +98,81 @@ fn is_close_type_param(c: &str, inside_type_param: bool) -> bool {
     c == ">" && inside_type_param
 }
 
+/// Does `ty` open a block?
+fn opens_block(options: &Options, ty: &StandardTokenType, inside_type_param: bool) -> bool {
+    match ty {
+        StandardTokenType::Indent => true,
+        StandardTokenType::Symbol(c) => {
+            options.is_open_paren(c) || is_open_type_param(c, inside_type_param)
+        }
+        _ => false,
+    }
+}
+
+/// Does `ty` close the block opened by `op`?
+fn closes_block(
+    options: &Options,
+    op: &StandardTokenType,
+    ty: &StandardTokenType,
+    inside_type_param: bool,
+) -> bool {
+    match (op, ty) {
+        (StandardTokenType::Symbol(op), StandardTokenType::Symbol(s)) => {
+            options.closes(op, s) || (op == "<" && is_close_type_param(s, inside_type_param))
+        }
+        (StandardTokenType::Indent, StandardTokenType::Dedent) => true,
+        _ => false,
+    }
+}
+
+/// Does `ty` end the innermost of the blocks opened by `open`? Besides its own closer, a block
+/// ends at the closer of any enclosing block, leaving it unterminated instead of mismatching
+/// the rest of the file.
+fn ends_block(
+    options: &Options,
+    open: &[StandardTokenType],
+    ty: &StandardTokenType,
+    inside_type_param: bool,
+) -> bool {
+    matches!(ty, StandardTokenType::Symbol(s) if is_close_type_param(s, inside_type_param))
+        || open.iter().any(|op| closes_block(options, op, ty, false))
+}
+
+/// Take the closer of the block opened by `op`, or None if the next token doesn't close it.
+fn take_closer(
+    options: &Options,
+    iter: &mut MultiPeekPutBackN<impl Iterator<Item = StandardToken>>,
+    op: &StandardTokenType,
+    inside_type_param: bool,
+) -> Option<StandardToken> {
+    iter.reset_peek();
+    let found = matches!(
+        iter.peek(),
+        Some(token) if closes_block(options, op, &token.ty, inside_type_param)
+    );
+    iter.reset_peek();
+    found.then(|| iter.next()).flatten()
+}
+
+fn take_closer_query(
+    options: &Options,
+    iter: &mut MultiPeekPutBackN<impl Iterator<Item = QueryToken>>,
+    op: &StandardTokenType,
+    inside_type_param: bool,
+) -> Option<StandardToken> {
+    iter.reset_peek();
+    let found = matches!(
+        iter.peek(),
+        Some(QueryToken { ty: QueryTokenType::Standard(ty), .. })
+            if closes_block(options, op, ty, inside_type_param)
+    );
+    iter.reset_peek();
+    found
+        .then(|| iter.next())
+        .flatten()
+        .and_then(|t| t.try_into().ok())
+}
+
 fn last_is_identifier(res: &[Ast]) -> bool {
     res.is_empty()
         || matches!(
@@ -179,40 +299,42 @@ fn split_to_symbols(options: &Options, s: &str, mut span: Span) -> Vec<StandardT
     res
 }
 
+/// Parse tokens until the end of the innermost of the blocks opened by `open`.
 fn parse(
     options: &Options,
     iter: &mut MultiPeekPutBackN<impl Iterator<Item = StandardToken>>,
-    recur: bool,
+    open: &[StandardTokenType],
     inside_type_param: bool,
 ) -> Vec<Ast> {
     let mut res = Vec::new();
     loop {
-        if let Some(StandardToken {
-            ty: StandardTokenType::Symbol(s),
-            span,
-        }) = iter.peek()
-        {
-            if recur && (options.is_close_paren(s) || is_close_type_param(s, inside_type_param)) {
+        if let Some(StandardToken { ty, span }) = iter.peek() {
+            if ends_block(options, open, ty, inside_type_param) {
                 break;
             }
-            if inside_type_param && s.chars().count() > 1 {
-                let syms = split_to_symbols(options, s, *span);
-                if syms.len() > 1 {
-                    assert!(iter.next().is_some());
-                    for sym in syms.into_iter().rev() {
-                        iter.put_back(sym);
+            if let StandardTokenType::Symbol(s) = ty {
+                if inside_type_param && s.chars().count() > 1 {
+                    let syms = split_to_symbols(options, s, *span);
+                    if syms.len() > 1 {
+                        assert!(iter.next().is_some());
+                        for sym in syms.into_iter().rev() {
+                            iter.put_back(sym);
+                        }
+                        continue;
                     }
-                    continue;
                 }
             }
         }
         if let Some(token) = iter.next() {
             match &token.ty {
-                StandardTokenType::Symbol(c)
-                    if options.is_open_paren(c) || is_open_type_param(c, inside_type_param) =>
-                {
-                    let content = parse(options, iter, true, inside_type_param);
-                    let cp = iter.next();
+                ty if opens_block(options, ty, inside_type_param) => {
+                    let content = parse(
+                        options,
+                        iter,
+                        &[open, std::slice::from_ref(ty)].concat(),
+                        inside_type_param,
+                    );
+                    let cp = take_closer(options, iter, ty, inside_type_param);
                     res.push(Ast::Delimited {
                         op: token,
                         content,
@@ -243,8 +365,13 @@ fn parse(
                 StandardTokenType::Symbol(c) if c == "<" && options.type_parameter_parsing => {
                     if last_is_identifier(&res) && peek_is_type_params(options, iter) {
                         iter.reset_peek();
-                        let content = parse(options, iter, true, true);
-                        let cp = iter.next();
+                        let content = parse(
+                            options,
+                            iter,
+                            &[open, std::slice::from_ref(&token.ty)].concat(),
+                            true,
+                        );
+                        let cp = take_closer(options, iter, &token.ty, true);
                         res.push(Ast::Delimited {
                             op: token,
                             content,
@@ -264,13 +391,15 @@ fn parse(
     res
 }
 
+/// Parse the tokens of a source file, as returned by `tokenize`, into a list of ASTs.
+pub fn parse_tokens(tokens: Vec<StandardToken>, options: &Options) -> Vec<Ast> {
+    parse(options, &mut multipeek_put_back_n(tokens), &[], false)
+}
+
 /// Parse a source file into a list of ASTs.
 pub fn parse_file<R: Read>(file: R, options: &Options) -> (Vec<Ast>, PeekableStringIterator) {
     let (tokens, iter) = tokenize("filename", file, options);
-    (
-        parse(options, &mut multipeek_put_back_n(tokens), false, false),
-        iter,
-    )
+    (parse_tokens(tokens, options), iter)
 }
 
 /// Abstract syntax tree for query strings.
@@ -301,26 +430,85 @@ pub enum ParsedAstMatcher {
     Or(Box<ParsedAstMatcher>, Box<ParsedAstMatcher>),
     /// Grouped `ParsedAstMatcher`s
     Nested(Vec<ParsedAstMatcher>),
-    /// Match string literal by regex
-    Regex(RegexEq),
+    /// Match string literal by regex, and by prefix if one is given
+    Regex(Option<String>, RegexEq),
+    /// Match comment by regex
+    Comment(RegexEq),
+    /// Match identifier or symbol, by regex if one is given
+    Class(TokenClass, Option<RegexEq>),
+    /// Match number within bounds
+    Number(Vec<NumberBound>),
+    /// Match any token or tree, binding it to a metavariable
+    Capture(String),
+    /// Match without consuming anything if `ParsedAstMatcher` doesn't match
+    Not(Box<ParsedAstMatcher>),
+    /// Paren-delimited block containing a match at any depth.
+    Contains {
+        /// Opening paren of the block, or None for any block.
+        op: Option<StandardToken>,
+        /// Content to search for.
+        content: Vec<ParsedAstMatcher>,
+    },
+}
+
+/// Build a `\has` matcher from the matcher following it. A `(...)` group matches inside any
+/// block, while other blocks only match inside blocks of the same kind.
+fn contains_matcher(matcher: ParsedAstMatcher) -> ParsedAstMatcher {
+    match matcher {
+        ParsedAstMatcher::Delimited { op, content, .. }
+            if op.ty == StandardTokenType::Symbol("(".to_string()) =>
+        {
+            ParsedAstMatcher::Contains { op: None, content }
+        }
+        ParsedAstMatcher::Delimited { op, content, .. } => ParsedAstMatcher::Contains {
+            op: Some(op),
+            content,
+        },
+        ParsedAstMatcher::Nested(content) => ParsedAstMatcher::Contains { op: None, content },
+        other => ParsedAstMatcher::Contains {
+            op: None,
+            content: vec![other],
+        },
+    }
+}
+
+/// Compile the regex `content` of the query command at `span`.
+fn query_regex(content: &str, span: Span) -> Result<RegexEq, SynsError> {
+    Regex::new(content).map(RegexEq).map_err(|e| {
+        // The full message repeats the regex with a caret, only keep the description
+        let message = e.to_string();
+        let message = message.lines().last().unwrap_or_default();
+        SynsError::InvalidRegex {
+            message: message.trim_start_matches("error: ").to_string(),
+            span,
+        }
+    })
 }
 
 fn parse_query_ast(
     options: &Options,
     iter: &mut MultiPeekPutBackN<impl Iterator<Item = QueryToken>>,
-    recur: bool,
+    open: &[StandardTokenType],
     inside_type_param: bool,
-) -> Vec<ParsedAstMatcher> {
+) -> Result<Vec<ParsedAstMatcher>, SynsError> {
     let mut res = Vec::new();
+    let mut prefixes: Vec<SpecialTokenType> = Vec::new();
     loop {
         if let Some(QueryToken {
-            ty: QueryTokenType::Standard(StandardTokenType::Symbol(s)),
-            span,
+            ty: QueryTokenType::Standard(ty),
+            ..
         }) = iter.peek()
         {
-            if recur && (options.is_close_paren(s) || is_close_type_param(s, inside_type_param)) {
+            if ends_block(options, open, ty, inside_type_param) {
                 break;
             }
+        }
+        iter.reset_peek();
+        if let Some(QueryToken {
+            ty: QueryTokenType::Standard(StandardTokenType::Symbol(s)),
+            span,
+        }) = iter.peek()
+        {
             if inside_type_param && s.chars().count() > 1 {
                 let syms = split_to_symbols(options, s, *span);
                 if syms.len() > 1 {
@@ -336,6 +524,7 @@ fn parse_query_ast(
             }
         }
         if let Some(token) = iter.next() {
+            let pending = std::mem::take(&mut prefixes);
             match &token.ty {
                 QueryTokenType::Standard(StandardTokenType::Symbol(c))
                     if options.is_open_paren(c) || is_open_type_param(c, inside_type_param) =>
@@ -344,11 +533,13 @@ fn parse_query_ast(
                         ty: StandardTokenType::Symbol(c.clone()),
                         span: token.span,
                     };
-                    let content = parse_query_ast(options, iter, true, inside_type_param);
-                    let cp = iter.next().map(|t| {
-                        t.try_into()
-                            .expect("Expected closing paren but got special token")
-                    });
+                    let content = parse_query_ast(
+                        options,
+                        iter,
+                        &[open, std::slice::from_ref(&op.ty)].concat(),
+                        inside_type_param,
+                    )?;
+                    let cp = take_closer_query(options, iter, &op.ty, inside_type_param);
                     res.push(ParsedAstMatcher::Delimited { op, content, cp });
                 }
                 QueryTokenType::Standard(StandardTokenType::Symbol(c))
@@ -384,11 +575,13 @@ fn parse_query_ast(
                             ty: StandardTokenType::Symbol(c.clone()),
                             span: token.span,
                         };
-                        let content = parse_query_ast(options, iter, true, true);
-                        let cp = iter.next().map(|t| {
-                            t.try_into()
-                                .expect("Expected closing paren but got special token")
-                        });
+                        let content = parse_query_ast(
+                            options,
+                            iter,
+                            &[open, std::slice::from_ref(&op.ty)].concat(),
+                            true,
+                        )?;
+                        let cp = take_closer_query(options, iter, &op.ty, true);
                         res.push(ParsedAstMatcher::Delimited { op, content, cp });
                     } else {
                         iter.reset_peek();
@@ -428,7 +621,7 @@ fn parse_query_ast(
                         res = Vec::new();
                         Box::new(ParsedAstMatcher::Nested(inner))
                     };
-                    let next = parse_query_ast(options, iter, true, inside_type_param);
+                    let next = parse_query_ast(options, iter, open, inside_type_param)?;
                     res.push(ParsedAstMatcher::Or(
                         prev,
                         Box::new(ParsedAstMatcher::Nested(next)),
@@ -438,44 +631,70 @@ fn parse_query_ast(
                     let list = parse_query_ast(
                         options,
                         &mut multipeek_put_back_n(list.clone()),
-                        false,
+                        &[],
                         inside_type_param,
-                    );
+                    )?;
                     res.push(ParsedAstMatcher::Nested(list));
                 }
-                QueryTokenType::Special(SpecialTokenType::Regex(content)) => {
-                    match Regex::new(content) {
-                        Ok(r) => {
-                            let matcher = ParsedAstMatcher::Regex(RegexEq(r));
-                            res.push(matcher);
-                        }
-                        Err(e) => {
-                            println!("{}", e);
-                            std::process::exit(1);
-                        }
-                    }
+                QueryTokenType::Special(SpecialTokenType::Capture(name)) => {
+                    res.push(ParsedAstMatcher::Capture(name.clone()));
+                }
+                QueryTokenType::Special(
+                    prefix @ (SpecialTokenType::Not | SpecialTokenType::Has),
+                ) => {
+                    // Applies to the next matcher, which hasn't been parsed yet
+                    prefixes = pending;
+                    prefixes.push(prefix.clone());
+                    continue;
+                }
+                QueryTokenType::Special(SpecialTokenType::Regex(prefix, content)) => {
+                    res.push(ParsedAstMatcher::Regex(
+                        prefix.clone(),
+                        query_regex(content, token.span)?,
+                    ));
+                }
+                QueryTokenType::Special(SpecialTokenType::Comment(content)) => {
+                    res.push(ParsedAstMatcher::Comment(query_regex(content, token.span)?));
+                }
+                QueryTokenType::Special(SpecialTokenType::Class(class, content)) => {
+                    let regex = match content {
+                        Some(content) => Some(query_regex(content, token.span)?),
+                        None => None,
+                    };
+                    res.push(ParsedAstMatcher::Class(*class, regex));
+                }
+                QueryTokenType::Special(SpecialTokenType::Number(bounds)) => {
+                    res.push(ParsedAstMatcher::Number(bounds.clone()));
+                }
+            }
+            for prefix in pending.into_iter().rev() {
+                if let Some(prev) = res.pop() {
+                    res.push(match prefix {
+                        SpecialTokenType::Has => contains_matcher(prev),
+                        _ => ParsedAstMatcher::Not(Box::new(prev)),
+                    });
                 }
             }
         } else {
             break;
         }
     }
-    res
+    Ok(res)
 }
 
 /// Parse a query into a list of query ASTs.
 pub fn parse_query<R: Read>(
     file: R,
     options: &Options,
-) -> (Vec<ParsedAstMatcher>, PeekableStringIterator) {
+) -> Result<(Vec<ParsedAstMatcher>, PeekableStringIterator), SynsError> {
     debug!("Tokenizing query");
-    let (tokens, iter) = tokenize_query(file, options);
+    let (tokens, iter) = tokenize_query(file, options)?;
     debug!("Tokenized query: {:#?}", tokens);
     debug!("Parsing query");
-    let parsed = parse_query_ast(options, &mut multipeek_put_back_n(tokens), false, false);
+    let parsed = parse_query_ast(options, &mut multipeek_put_back_n(tokens), &[], false)?;
     debug!("Parsed query: {:#?}", parsed);
 
-    (parsed, iter)
+    Ok((parsed, iter))
 }
 
 #[cfg(test)]
@@ -483,14 +702,9 @@ mod tests_ast {
     use super::*;
 
     fn parse_str(input: &str, ext: &str) -> Vec<Ast> {
-        let options = Options::new(ext.as_ref(), &["syns", "query", "file"]);
+        let options = Options::new(ext.as_ref(), &["syns", "query", "file"]).expect("options");
         let (tokens, _) = tokenize("test", input.as_bytes(), &options);
-        parse(
-            &options,
-            &mut multipeek_put_back_n(tokens.into_iter()),
-            false,
-            false,
-        )
+        parse(&options, &mut multipeek_put_back_n(tokens), &[], false)
     }
 
     /// Strip all spans from an AST tree so we can compare structure only.
@@ -539,6 +753,17 @@ mod tests_ast {
         }
     }
 
+    fn unterminated(op: &str, content: Vec<Ast>) -> Ast {
+        Ast::Delimited {
+            op: StandardToken {
+                ty: StandardTokenType::Symbol(op.to_string()),
+                span: Span { lo: 0, hi: 0 },
+            },
+            cp: None,
+            content,
+        }
+    }
+
     fn ident(s: &str) -> Ast {
         tok(StandardTokenType::Identifier(s.to_string()))
     }
@@ -591,6 +816,66 @@ mod tests_ast {
         );
     }
 
+    #[test]
+    fn parse_mismatched_blocks() {
+        let ast = parse_str("{ a(b } c] }", "js");
+        assert_eq!(
+            strip_spans(&ast),
+            vec![
+                delim(
+                    "{",
+                    vec![ident("a"), unterminated("(", vec![ident("b")])],
+                    "}"
+                ),
+                ident("c"),
+                sym("]"),
+                sym("}"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_indent_blocks() {
+        let ast = parse_str("def f():\n    if a:\n        b\n\n    c\nd", "py");
+        let indent = |content| Ast::Delimited {
+            op: StandardToken {
+                ty: StandardTokenType::Indent,
+                span: Span { lo: 0, hi: 0 },
+            },
+            cp: Some(StandardToken {
+                ty: StandardTokenType::Dedent,
+                span: Span { lo: 0, hi: 0 },
+            }),
+            content,
+        };
+        assert_eq!(
+            strip_spans(&ast),
+            vec![
+                ident("def"),
+                ident("f"),
+                delim("(", vec![], ")"),
+                sym(":"),
+                indent(vec![
+                    ident("if"),
+                    ident("a"),
+                    sym(":"),
+                    indent(vec![ident("b")]),
+                    ident("c"),
+                ]),
+                ident("d"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_keyword_blocks() {
+        let ast = parse_str("sub A\n  exit sub\nEND SUB", "vb");
+        assert_eq!(
+            strip_spans(&ast),
+            vec![delim("Sub", vec![ident("A"), sym("Exit Sub")], "End Sub")]
+        );
+    }
+
     #[test]
     fn complex_type_params() {
         let ast = parse_str("a: Bar<Foo<T>>; b: Foo<Bar<{foo: string[]}>>", "js");
@@ -641,14 +926,9 @@ mod tests_query {
     use super::*;
 
     fn parse_str(input: &str, ext: &str) -> Vec<ParsedAstMatcher> {
-        let options = Options::new(ext.as_ref(), &["syns", "query", "file"]);
-        let (tokens, _) = tokenize_query(input.as_bytes(), &options);
-        parse_query_ast(
-            &options,
-            &mut multipeek_put_back_n(tokens.into_iter()),
-            false,
-            false,
-        )
+        let options = Options::new(ext.as_ref(), &["syns", "query", "file"]).expect("options");
+        let (tokens, _) = tokenize_query(input.as_bytes(), &options).expect("query");
+        parse_query_ast(&options, &mut multipeek_put_back_n(tokens), &[], false).expect("query")
     }
 
     /// Strip all spans from an AST tree so we can compare structure only.
@@ -686,7 +966,21 @@ mod tests_query {
                 ParsedAstMatcher::Or(Box::new(strip_span(left)), Box::new(strip_span(right)))
             }
             ParsedAstMatcher::Nested(content) => ParsedAstMatcher::Nested(strip_spans(content)),
-            ParsedAstMatcher::Regex(regex) => ParsedAstMatcher::Regex(regex.clone()),
+            ParsedAstMatcher::Regex(prefix, regex) => {
+                ParsedAstMatcher::Regex(prefix.clone(), regex.clone())
+            }
+            ParsedAstMatcher::Comment(regex) => ParsedAstMatcher::Comment(regex.clone()),
+            ParsedAstMatcher::Class(class, regex) => ParsedAstMatcher::Class(*class, regex.clone()),
+            ParsedAstMatcher::Number(bounds) => ParsedAstMatcher::Number(bounds.clone()),
+            ParsedAstMatcher::Capture(name) => ParsedAstMatcher::Capture(name.clone()),
+            ParsedAstMatcher::Not(content) => ParsedAstMatcher::Not(Box::new(strip_span(content))),
+            ParsedAstMatcher::Contains { op, content } => ParsedAstMatcher::Contains {
+                op: op.as_ref().map(|t| StandardToken {
+                    ty: t.ty.clone(),
+                    span: blank,
+                }),
+                content: strip_spans(content),
+            },
         }
     }
 
@@ -725,6 +1019,19 @@ mod tests_query {
         tok(StandardTokenType::Symbol(s.to_string()))
     }
 
+    #[test]
+    fn invalid_regex() {
+        let options = Options::new("js".as_ref(), &["syns", "query", "file"]).expect("options");
+        let error = parse_query(r#"f(\i"a(")"#.as_bytes(), &options).expect_err("invalid regex");
+        assert_eq!(
+            error,
+            SynsError::InvalidRegex {
+                message: "unclosed group".to_string(),
+                span: Span { lo: 4, hi: 7 },
+            }
+        );
+    }
+
     #[test]
     fn parse_type_parameters() {
         let ast = parse_str("const a: Foo<T>; if(a < b) { foo<T>(); }", "js");
@@ -769,4 +1076,61 @@ mod tests_query {
             ]
         );
     }
+
+    #[test]
+    fn parse_negation() {
+        let ast = parse_str("\\!foo bar \\!\\!baz", "js");
+        assert_eq!(
+            strip_spans(&ast),
+            vec![
+                ParsedAstMatcher::Not(Box::new(ident("foo"))),
+                ident("bar"),
+                ParsedAstMatcher::Not(Box::new(ParsedAstMatcher::Not(Box::new(ident("baz"))))),
+            ]
+        );
+
+        let ast = parse_str("\\!\\(a b\\)\\*", "js");
+        assert_eq!(
+            strip_spans(&ast),
+            vec![ParsedAstMatcher::Star(Box::new(ParsedAstMatcher::Not(
+                Box::new(ParsedAstMatcher::Nested(vec![ident("a"), ident("b")]))
+            )))]
+        );
+    }
+
+    #[test]
+    fn parse_contains() {
+        let ast = parse_str("\\has(free()) \\has{a b} \\has x", "js");
+        assert_eq!(
+            strip_spans(&ast),
+            vec![
+                ParsedAstMatcher::Contains {
+                    op: None,
+                    content: vec![ident("free"), delim("(", vec![], ")")],
+                },
+                ParsedAstMatcher::Contains {
+                    op: Some(StandardToken {
+                        ty: StandardTokenType::Symbol("{".to_string()),
+                        span: Span { lo: 0, hi: 0 },
+                    }),
+                    content: vec![ident("a"), ident("b")],
+                },
+                ParsedAstMatcher::Contains {
+                    op: None,
+                    content: vec![ident("x")],
+                },
+            ]
+        );
+
+        let ast = parse_str("\\!\\has(x)", "js");
+        assert_eq!(
+            strip_spans(&ast),
+            vec![ParsedAstMatcher::Not(Box::new(
+                ParsedAstMatcher::Contains {
+                    op: None,
+                    content: vec![ident("x")],
+                }
+            ))]
+        );
+    }
 }
@@ -74,6 +74,9 @@ impl Clone for OwnedCharIndices {
     }
 }
 
+/// A line of source text along with its line number, starting from 1.
+pub type NumberedLine = (usize, String);
+
 /// An iterator over strings, keeping track of origins for each substring.
 ///
 /// # Examples
@@ -243,6 +246,11 @@ impl PeekableStringIterator {
         self.iter.rest_str(|s| s.chars().take(n).collect())
     }
 
+    /// Call `f` with the rest of the current file without consuming it.
+    pub fn with_rest<R>(&self, f: impl FnOnce(&str) -> R) -> R {
+        self.iter.rest_str(f)
+    }
+
     /// Returns whether the current iterator position starts with `s`.
     pub fn starts_with(&self, s: &str) -> bool {
         self.iter.rest_str(|iter_s| iter_s.starts_with(s))
@@ -253,6 +261,21 @@ impl PeekableStringIterator {
         self.current_span
     }
 
+    /// Get the entire content of the file.
+    pub fn content(&self) -> &str {
+        self.iter.content()
+    }
+
+    /// Get the exclusive ending byte index of the span, accounting for multi-byte characters.
+    pub fn get_span_end(&self, span: Span) -> usize {
+        let content = self.iter.content();
+        content
+            .get(span.hi..)
+            .and_then(|s| s.chars().next())
+            .map(|c| span.hi + c.len_utf8())
+            .unwrap_or(content.len())
+    }
+
     /// Get characters contained in the span.
     pub fn get_content_between(&self, span: Span) -> String {
         String::from_utf8_lossy(
@@ -305,6 +328,56 @@ impl PeekableStringIterator {
         )
     }
 
+    /// Get the column numbers for the match, counted in characters starting from 1. Returns
+    /// (first_column, end_column), where end_column is the column after the last character.
+    pub fn get_column_information(&self, span: Span) -> (usize, usize) {
+        let (start_index, end_index) = self.get_line_starts(span);
+        let content = self.iter.content();
+        let column = |line_start: usize, offset: usize| {
+            content
+                .get(line_start..offset)
+                .map(|s| s.chars().count())
+                .unwrap_or(0)
+                + 1
+        };
+        (
+            column(start_index, span.lo),
+            column(end_index, self.get_span_end(span)),
+        )
+    }
+
+    /// Get up to `before` lines preceding and `after` lines following the lines of `span`, along
+    /// with their line numbers.
+    pub fn get_context_lines(
+        &self,
+        span: Span,
+        before: usize,
+        after: usize,
+    ) -> (Vec<NumberedLine>, Vec<NumberedLine>) {
+        let (start_index, end_index) = self.get_span_indices(span);
+        let content = self.iter.content();
+        let line = |(start, (end, number)): (&usize, &(usize, usize))| {
+            let text = content.get(*start..*end).unwrap_or("");
+            let text = text.strip_suffix('\n').unwrap_or(text);
+            (*number, text.strip_suffix('\r').unwrap_or(text).to_string())
+        };
+        let mut head = self
+            .line_numbers
+            .range(..start_index)
+            .rev()
+            .take(before)
+            .map(line)
+            .collect::<Vec<_>>();
+        head.reverse();
+        let tail = self
+            .line_numbers
+            .range(end_index..)
+            .take(after)
+            .map(line)
+            .collect();
+        (head, tail)
+    }
+
     /// Get line contents for the two matches.
     pub fn get_lines_including(&self, span: Span) -> (String, Vec<String>, String) {
         let (start_index, end_index) = self.get_span_indices(span);
@@ -422,6 +495,13 @@ mod tests {
         assert_eq!(iter.get_content_between(Span { lo: 4, hi: 4 }), "b");
     }
 
+    #[test]
+    fn get_columns() {
+        let iter = PeekableStringIterator::new("foo.h".to_string(), "a äö b\nc".to_string());
+        assert_eq!(iter.get_column_information(Span { lo: 2, hi: 4 }), (3, 5));
+        assert_eq!(iter.get_column_information(Span { lo: 7, hi: 7 }), (6, 7));
+    }
+
     #[test]
     fn get_lines() {
         let mut iter =
@@ -443,6 +523,9 @@ mod tests {
         assert_eq!(iter.get_line_information(sp1.merge(&sp3)), (1, 3));
         assert_eq!(iter.get_line_information(sp2.merge(&sp3)), (2, 3));
 
+        assert_eq!(iter.get_column_information(sp1), (1, 4));
+        assert_eq!(iter.get_column_information(sp2.merge(&sp3)), (1, 4));
+
         assert_eq!(iter.get_lines_including(sp1).1, vec!["foo"]);
         assert_eq!(iter.get_lines_including(sp2).1, vec!["bar"]);
         assert_eq!(iter.get_lines_including(sp3).1, vec!["baz"]);
@@ -459,4 +542,29 @@ mod tests {
             vec!["bar", "baz"]
         );
     }
+
+    #[test]
+    fn get_context_lines() {
+        let mut iter =
+            PeekableStringIterator::new("foo.h".to_string(), "a\r\nb\nc\nd\ne".to_string());
+        while iter.next().is_some() {}
+        let line = |n: usize, s: &str| (n, s.to_string());
+        let c = Span { lo: 5, hi: 5 };
+        assert_eq!(
+            iter.get_context_lines(c, 1, 1),
+            (vec![line(2, "b")], vec![line(4, "d")])
+        );
+        assert_eq!(
+            iter.get_context_lines(c, 5, 5),
+            (
+                vec![line(1, "a"), line(2, "b")],
+                vec![line(4, "d"), line(5, "e")]
+            )
+        );
+        assert_eq!(iter.get_context_lines(c, 0, 0), (vec![], vec![]));
+        assert_eq!(
+            iter.get_context_lines(Span { lo: 3, hi: 7 }, 1, 1),
+            (vec![line(1, "a")], vec![line(5, "e")])
+        );
+    }
 }
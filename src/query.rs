@@ -1,19 +1,24 @@
 //! Query handling and matching.
 
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 
 use log::debug;
+use memchr::memmem::Finder;
 
 use crate::compiler::{compile_query, Machine, Matcher};
+use crate::error::SynsError;
 use crate::options::Options;
 use crate::parser::{parse_query, Ast};
-use crate::tokenizer::StandardTokenType;
+use crate::tokenizer::{StandardToken, StandardTokenType, TokenClass};
+use crate::wrappers::ByAddress;
 
 /// Compiled query.
 #[derive(Debug)]
 pub struct Query {
     /// The state machine for the query.
     pub machine: Machine,
+    /// Literals which must be present in the source text for the query to match.
+    literals: Vec<Finder<'static>>,
 }
 
 /// Successful match.
@@ -21,63 +26,175 @@ pub struct Query {
 pub struct Match {
     /// Matched tokens.
     pub t: Vec<Ast>,
+    /// Trees bound to named metavariables.
+    pub captures: BTreeMap<String, Ast>,
 }
 
+/// Metavariable bindings collected while matching.
+type Bindings<'a> = BTreeMap<&'a str, ByAddress<'a, Ast>>;
+
 impl Query {
     /// Compile a query.
-    pub fn new(options: &Options) -> Query {
-        debug!("Query string: {}", options.query);
-        let (query, _) = parse_query(&mut options.query.as_bytes(), options);
-        let machine = compile_query(query);
+    pub fn new(options: &Options) -> Result<Query, SynsError> {
+        Query::from_pattern(&options.query, options)
+    }
+
+    /// Compile `pattern` using the language settings in `options`.
+    ///
+    /// ```
+    /// use syns::error::SynsError;
+    /// use syns::options::Options;
+    /// use syns::query::Query;
+    /// let options = Options::new("js".as_ref(), &["syns", "foo", "-"]).unwrap();
+    /// let error = Query::from_pattern("foo(\\x)", &options).unwrap_err();
+    /// assert!(matches!(error, SynsError::UnknownCommand { .. }));
+    /// ```
+    pub fn from_pattern(pattern: &str, options: &Options) -> Result<Query, SynsError> {
+        debug!("Query string: {}", pattern);
+        let (query, _) = parse_query(&mut pattern.as_bytes(), options)?;
+        let mut machine = compile_query(query)?;
+        machine.ignore_case = options.case_insensitive_identifiers;
         debug!("Query AST: {:#?}", machine);
-        Query { machine }
+        let literals = machine
+            .required_literals()
+            .into_iter()
+            .map(|literal| Finder::new(&literal).into_owned())
+            .collect();
+        Ok(Query { machine, literals })
     }
 
-    fn ast_match<'a>(&self, left: &'a [Ast], initials: &[usize]) -> Option<&'a [Ast]> {
+    /// Can this query match anything in the raw source text `content`? Used to skip
+    /// tokenizing files which are missing a literal that every match contains.
+    ///
+    /// ```
+    /// use syns::options::Options;
+    /// use syns::query::Query;
+    /// let options = Options::new("js".as_ref(), &["syns", "sprintf()", "-"]).unwrap();
+    /// let query = Query::new(&options).unwrap();
+    /// assert!(query.may_match(b"x = sprintf(\"%d\", 1);"));
+    /// assert!(!query.may_match(b"x = printf(\"%d\", 1);"));
+    /// ```
+    pub fn may_match(&self, content: &[u8]) -> bool {
+        self.literals
+            .iter()
+            .all(|literal| literal.find(content).is_some())
+    }
+
+    /// Run the NFA over `left`, returning the length and metavariable bindings of every
+    /// accepted prefix. Comments are skipped unless a comment matcher matches them, except at the
+    /// start of `left` if `skip_leading` is false.
+    fn ast_match<'a>(
+        &'a self,
+        left: &'a [Ast],
+        initials: &[usize],
+        bindings: &Bindings<'a>,
+        skip_leading: bool,
+    ) -> Vec<(usize, Bindings<'a>)> {
         let mut current_states = initials
             .iter()
-            .map(|state| (0, *state))
+            .map(|state| (0, *state, bindings.clone()))
             .collect::<BTreeSet<_>>();
-        let mut longest_match: Option<&'a [Ast]> = None;
+        let mut accepted = Vec::new();
+        // Zero-width transitions may loop back to an already visited state
+        let mut visited = current_states.clone();
         while !current_states.is_empty() {
             let mut next_states = BTreeSet::new();
-            for (left_pos, state) in current_states {
+            for (left_pos, state, bindings) in current_states {
+                let comments = if left_pos > 0 || skip_leading {
+                    left[left_pos.min(left.len())..]
+                        .iter()
+                        .take_while(|ast| ast.is_comment())
+                        .count()
+                } else {
+                    0
+                };
+                // Position of the next token which isn't a skipped comment
+                let pos = left_pos + comments;
+                let next = left.get(pos).filter(|ast| !ast.is_comment());
                 for (matcher, next_state) in &self.machine.states[&state].transitions {
-                    match (left.get(left_pos), matcher) {
+                    match (next, matcher) {
                         (_, Matcher::Accept) => {
-                            longest_match = if longest_match.is_none()
-                                || longest_match.map(|p| p.len()) < Some(left_pos)
-                            {
-                                Some(&left[0..left_pos.min(left.len())])
-                            } else {
-                                longest_match
-                            };
+                            accepted.push((left_pos.min(left.len()), bindings.clone()));
                             continue;
                         }
                         (None, Matcher::Any)
                         | (None, Matcher::Token(..))
-                        | (None, Matcher::Delimited { .. }) => {}
+                        | (None, Matcher::Delimited { .. })
+                        | (None, Matcher::Contains { .. })
+                        | (None, Matcher::Capture(..)) => {}
                         (Some(_), Matcher::Any) => {
-                            next_states.insert((left_pos + 1, *next_state));
+                            next_states.insert((pos + 1, *next_state, bindings.clone()));
                         }
-                        (Some(_), Matcher::End) => {}
-                        (None, Matcher::End) => {
-                            next_states.insert((left_pos + 1, *next_state));
+                        (_, Matcher::End) => {
+                            if pos >= left.len() {
+                                next_states.insert((pos + 1, *next_state, bindings.clone()));
+                            }
                         }
                         (_, Matcher::Epsilon) => {
-                            next_states.insert((left_pos, *next_state));
+                            next_states.insert((left_pos, *next_state, bindings.clone()));
+                        }
+                        (Some(Ast::Token(t1)), Matcher::Regex(prefix, re)) => {
+                            if let StandardTokenType::StringLiteral { prefix: p, content } = &t1.ty
+                            {
+                                if prefix.as_ref().is_none_or(|prefix| prefix == p)
+                                    && re.is_match(content)
+                                {
+                                    next_states.insert((pos + 1, *next_state, bindings.clone()));
+                                }
+                            }
+                        }
+                        (_, Matcher::Regex(..)) => {}
+                        (Some(Ast::Token(t1)), Matcher::Class(class, re)) => {
+                            if let (TokenClass::Identifier, StandardTokenType::Identifier(s))
+                            | (TokenClass::Symbol, StandardTokenType::Symbol(s)) =
+                                (class, &t1.ty)
+                            {
+                                if re.as_ref().is_none_or(|re| re.is_match(s)) {
+                                    next_states.insert((pos + 1, *next_state, bindings.clone()));
+                                }
+                            }
                         }
-                        (Some(Ast::Token(t1)), Matcher::Regex(re)) => {
-                            if let StandardTokenType::StringLiteral(c) = &t1.ty {
-                                if re.is_match(c) {
-                                    next_states.insert((left_pos + 1, *next_state));
+                        (_, Matcher::Class(..)) => {}
+                        (Some(Ast::Token(t1)), Matcher::Number(bounds)) => {
+                            let num = match &t1.ty {
+                                StandardTokenType::Integer(i) => Some(*i as f64),
+                                StandardTokenType::Float(f) => Some(**f),
+                                _ => None,
+                            };
+                            if num.is_some_and(|num| bounds.iter().all(|b| b.contains(num))) {
+                                next_states.insert((pos + 1, *next_state, bindings.clone()));
+                            }
+                        }
+                        (_, Matcher::Number(..)) => {}
+                        (_, Matcher::Comment(re)) => {
+                            // Any of the comments before the next token may match
+                            let end = if comments == 0 { left_pos + 1 } else { pos };
+                            for (i, ast) in left.iter().enumerate().take(end).skip(left_pos) {
+                                if let Ast::Token(StandardToken {
+                                    ty: StandardTokenType::Comment(content),
+                                    ..
+                                }) = ast
+                                {
+                                    if re.is_match(content) {
+                                        next_states.insert((i + 1, *next_state, bindings.clone()));
+                                    }
                                 }
                             }
                         }
-                        (_, Matcher::Regex(_)) => {}
+                        (Some(ast), Matcher::Capture(name)) => match bindings.get(name.as_str()) {
+                            Some(bound) if bound.structurally_eq(ast, self.machine.ignore_case) => {
+                                next_states.insert((pos + 1, *next_state, bindings.clone()));
+                            }
+                            Some(_) => {}
+                            None => {
+                                let mut bindings = bindings.clone();
+                                bindings.insert(name, ByAddress(ast));
+                                next_states.insert((pos + 1, *next_state, bindings));
+                            }
+                        },
                         (Some(Ast::Token(t1)), Matcher::Token(t2)) => {
-                            if &t1.ty == t2 {
-                                next_states.insert((left_pos + 1, *next_state));
+                            if t1.ty.eq_ignoring_case(t2, self.machine.ignore_case) {
+                                next_states.insert((pos + 1, *next_state, bindings.clone()));
                             }
                         }
                         (
@@ -88,8 +205,35 @@ impl Query {
                             }),
                             Matcher::Delimited { start, op: op1, .. },
                         ) => {
-                            if &op.ty == op1 && self.ast_match(content1, &[*start]).is_some() {
-                                next_states.insert((left_pos + 1, *next_state));
+                            if op.ty.eq_ignoring_case(op1, self.machine.ignore_case) {
+                                for (_, inner) in
+                                    self.ast_match(content1, &[*start], &bindings, true)
+                                {
+                                    next_states.insert((pos + 1, *next_state, inner));
+                                }
+                            }
+                        }
+                        (
+                            Some(Ast::Delimited { content, op, .. }),
+                            Matcher::Contains { op: op1, start },
+                        ) => {
+                            if op1.as_ref().is_none_or(|op1| {
+                                op.ty.eq_ignoring_case(op1, self.machine.ignore_case)
+                            }) {
+                                for sub in Query::potential_matches(content) {
+                                    for (_, inner) in
+                                        self.ast_match(sub, &[*start], &bindings, false)
+                                    {
+                                        next_states.insert((pos + 1, *next_state, inner));
+                                    }
+                                }
+                            }
+                        }
+                        (Some(Ast::Token { .. }), Matcher::Contains { .. }) => {}
+                        (_, Matcher::Not { start }) => {
+                            let rest = left.get(left_pos..).unwrap_or(&[]);
+                            if self.ast_match(rest, &[*start], &bindings, true).is_empty() {
+                                next_states.insert((left_pos, *next_state, bindings.clone()));
                             }
                         }
                         (Some(Ast::Token { .. }), Matcher::Delimited { .. }) => {}
@@ -97,9 +241,24 @@ impl Query {
                     }
                 }
             }
+            next_states.retain(|s| !visited.contains(s));
+            visited.extend(next_states.iter().cloned());
             current_states = next_states;
         }
-        longest_match
+        accepted
+    }
+
+    /// Find the longest match at the start of `left`.
+    fn longest_match<'a>(&'a self, left: &'a [Ast]) -> Option<(&'a [Ast], Bindings<'a>)> {
+        let mut longest: Option<(usize, Bindings<'a>)> = None;
+        for (len, bindings) in
+            self.ast_match(left, &[self.machine.initial], &Bindings::new(), false)
+        {
+            if longest.as_ref().is_none_or(|(l, _)| *l < len) {
+                longest = Some((len, bindings));
+            }
+        }
+        longest.map(|(len, bindings)| (&left[0..len], bindings))
     }
 
     fn potential_matches<'a>(input: &'a [Ast]) -> Box<dyn Iterator<Item = &'a [Ast]> + 'a> {
@@ -122,7 +281,13 @@ impl Query {
     /// Get all matches for this query from input.
     pub fn matches<'a>(&'a self, input: &'a [Ast]) -> impl Iterator<Item = Match> + 'a {
         Query::potential_matches(input)
-            .flat_map(move |tts| self.ast_match(tts, &[self.machine.initial]))
-            .map(move |tts| Match { t: tts.to_vec() })
+            .flat_map(move |tts| self.longest_match(tts))
+            .map(move |(tts, bindings)| Match {
+                t: tts.to_vec(),
+                captures: bindings
+                    .into_iter()
+                    .map(|(name, ast)| (name.to_string(), ast.0.clone()))
+                    .collect(),
+            })
     }
 }
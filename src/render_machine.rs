@@ -4,6 +4,7 @@ use itertools::Itertools;
 use std::collections::{HashMap, HashSet};
 
 use crate::compiler::{Machine, Matcher};
+use crate::tokenizer::TokenClass;
 
 fn to_dot_condition(matcher: &Matcher) -> String {
     (match matcher {
@@ -11,7 +12,27 @@ fn to_dot_condition(matcher: &Matcher) -> String {
         Matcher::Delimited { op, .. } => format!("delim {:?}", op),
         Matcher::Any => "*".to_string(),
         Matcher::End => "$".to_string(),
-        Matcher::Regex(r) => format!("r\"{}\"", r.as_str()),
+        Matcher::Regex(prefix, r) => {
+            format!("{}r\"{}\"", prefix.as_deref().unwrap_or(""), r.as_str())
+        }
+        Matcher::Comment(r) => format!("#\"{}\"", r.as_str()),
+        Matcher::Class(class, r) => format!(
+            "{}{}",
+            match class {
+                TokenClass::Identifier => "i",
+                TokenClass::Symbol => "sym",
+            },
+            r.as_ref()
+                .map(|r| format!("\"{}\"", r.as_str()))
+                .unwrap_or_default()
+        ),
+        Matcher::Number(bounds) => bounds.iter().fold("n".to_string(), |res, bound| {
+            format!("{}{}{}", res, bound.op.as_str(), *bound.value)
+        }),
+        Matcher::Capture(name) => format!(":{}", name),
+        Matcher::Contains { op: Some(op), .. } => format!("has {:?}", op),
+        Matcher::Contains { op: None, .. } => "has".to_string(),
+        Matcher::Not { .. } => "not".to_string(),
         Matcher::Epsilon => "e".to_string(),
         Matcher::Accept => "accept".to_string(),
     })
@@ -89,7 +110,9 @@ impl<'a> DotGraph<'a> {
         let mut out_ids = vec![];
         for (matcher, target_id) in &transitions {
             match matcher {
-                Matcher::Delimited { start, .. } => {
+                Matcher::Delimited { start, .. }
+                | Matcher::Contains { start, .. }
+                | Matcher::Not { start } => {
                     let new_prefix = format!("{}{}_", prefix, id);
                     self.edges.push((
                         format!("{}{}", prefix, id),
@@ -223,9 +246,9 @@ mod tests {
     use crate::parser::parse_query;
 
     fn compile(query: &str) -> Machine {
-        let options = Options::new("js".as_ref(), &["syns", query, "-"]);
-        let (parsed, _) = parse_query(query.as_bytes(), &options);
-        compile_query(parsed)
+        let options = Options::new("js".as_ref(), &["syns", query, "-"]).expect("options");
+        let (parsed, _) = parse_query(query.as_bytes(), &options).expect("query");
+        compile_query(parsed).expect("query")
     }
 
     #[test]
@@ -0,0 +1,532 @@
+//! Search-and-replace support.
+
+use log::{debug, warn};
+use std::io::{Read, Write};
+use std::path::Path;
+use termcolor::{Color, ColorSpec, WriteColor};
+
+use crate::options::Options;
+use crate::parser::{parse_file, Ast};
+use crate::psi::PeekableStringIterator;
+use crate::query::{Match, Query};
+
+/// Number of unchanged lines printed around each change in a diff.
+const DIFF_CONTEXT: usize = 3;
+
+/// A single replacement of the bytes `lo..hi` with `text`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Replacement {
+    /// Starting byte index of the replaced range.
+    pub lo: usize,
+    /// Exclusive ending byte index of the replaced range.
+    pub hi: usize,
+    /// Replacement text.
+    pub text: String,
+}
+
+/// Expand `template` for match `m`, replacing each `\:name` with the text captured by `name`.
+/// `\\` produces a single backslash.
+///
+/// ```
+/// # use syns::options::Options;
+/// # use syns::parser::parse_file;
+/// # use syns::query::Query;
+/// use syns::rewrite::expand_template;
+///
+/// let options = Options::new("js".as_ref(), &["syns", "foo(\\:x)", "-"]).unwrap();
+/// let query = Query::new(&options).unwrap();
+/// let (file, iter) = parse_file("foo(bar)".as_bytes(), &options);
+/// let m = query.matches(&file).next().unwrap();
+/// assert_eq!(expand_template("baz(\\:x, \\:x)", &m, &iter), "baz(bar, bar)");
+/// ```
+pub fn expand_template(template: &str, m: &Match, iter: &PeekableStringIterator) -> String {
+    let mut res = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some('\\')) => {
+                chars.next();
+                res.push('\\');
+            }
+            ('\\', Some(':')) => {
+                chars.next();
+                let mut name = String::new();
+                while let Some(&c) = chars.peek() {
+                    if !c.is_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    name.push(c);
+                    chars.next();
+                }
+                match m.captures.get(&name) {
+                    Some(ast) => {
+                        let span = ast.span();
+                        res.push_str(&iter.content()[span.lo..iter.get_span_end(span)]);
+                    }
+                    None => {
+                        warn!("Template refers to unknown metavariable \\:{}", name);
+                        res.push_str("\\:");
+                        res.push_str(&name);
+                    }
+                }
+            }
+            (c, _) => res.push(c),
+        }
+    }
+    res
+}
+
+/// Compute the replacements for all matches of `query` in the parsed file. Overlapping matches
+/// are resolved in favor of the one starting first.
+pub fn replacements(
+    query: &Query,
+    file: &[Ast],
+    iter: &PeekableStringIterator,
+    template: &str,
+) -> Vec<Replacement> {
+    let mut res = query
+        .matches(file)
+        .filter(|m| !m.t.is_empty())
+        .map(|m| {
+            let span = m.t[0].span().merge(&m.t.last().unwrap_or(&m.t[0]).span());
+            Replacement {
+                lo: span.lo,
+                hi: iter.get_span_end(span),
+                text: expand_template(template, &m, iter),
+            }
+        })
+        .collect::<Vec<_>>();
+    res.sort_by(|a, b| a.lo.cmp(&b.lo).then(b.hi.cmp(&a.hi)));
+
+    let mut end = 0;
+    res.retain(|r| {
+        if r.lo < end {
+            debug!("Skipping overlapping replacement at {}..{}", r.lo, r.hi);
+            false
+        } else {
+            end = r.hi;
+            true
+        }
+    });
+    res
+}
+
+/// Apply non-overlapping, sorted `replacements` to `content`. Bytes outside of the replaced
+/// ranges are kept as-is.
+///
+/// ```
+/// use syns::rewrite::{apply, Replacement};
+///
+/// let replacements = [Replacement { lo: 4, hi: 7, text: "qux".to_string() }];
+/// assert_eq!(apply("foo bar baz", &replacements), "foo qux baz");
+/// ```
+pub fn apply(content: &str, replacements: &[Replacement]) -> String {
+    let mut res = String::with_capacity(content.len());
+    let mut pos = 0;
+    for r in replacements {
+        res.push_str(&content[pos..r.lo]);
+        res.push_str(&r.text);
+        pos = r.hi;
+    }
+    res.push_str(&content[pos..]);
+    res
+}
+
+/// A contiguous run of changed lines.
+struct Change {
+    /// Index of the first replaced line.
+    first: usize,
+    /// Index of the last replaced line.
+    last: usize,
+    /// Lines replacing `first..=last`.
+    new_lines: Vec<String>,
+}
+
+fn line_index(line_starts: &[usize], offset: usize) -> usize {
+    match line_starts.binary_search(&offset) {
+        Ok(i) => i,
+        Err(i) => i - 1,
+    }
+}
+
+fn changes(content: &str, lines: &[&str], replacements: &[Replacement]) -> Vec<Change> {
+    let mut line_starts = Vec::with_capacity(lines.len());
+    let mut pos = 0;
+    for line in lines {
+        line_starts.push(pos);
+        pos += line.len();
+    }
+    let line_end = |i: usize| line_starts[i] + lines[i].len();
+
+    let mut res: Vec<(usize, usize, Vec<&Replacement>)> = Vec::new();
+    for r in replacements {
+        let first = line_index(&line_starts, r.lo);
+        let last = line_index(&line_starts, r.hi.max(r.lo + 1) - 1).max(first);
+        match res.last_mut() {
+            Some((_, prev_last, group)) if first <= *prev_last => {
+                *prev_last = last.max(*prev_last);
+                group.push(r);
+            }
+            _ => res.push((first, last, vec![r])),
+        }
+    }
+
+    res.into_iter()
+        .map(|(first, last, group)| {
+            let mut new_text = String::new();
+            let mut pos = line_starts[first];
+            for r in group {
+                new_text.push_str(&content[pos..r.lo]);
+                new_text.push_str(&r.text);
+                pos = r.hi;
+            }
+            new_text.push_str(&content[pos..line_end(last)]);
+            Change {
+                first,
+                last,
+                new_lines: new_text
+                    .split_inclusive('\n')
+                    .map(|s| s.to_string())
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+fn hunk_range(start: usize, count: usize) -> String {
+    if count == 0 {
+        format!("{},0", start)
+    } else if count == 1 {
+        format!("{}", start + 1)
+    } else {
+        format!("{},{}", start + 1, count)
+    }
+}
+
+fn push_line(out: &mut Vec<(char, String)>, prefix: char, line: &str) {
+    out.push((prefix, line.strip_suffix('\n').unwrap_or(line).to_string()));
+    if !line.ends_with('\n') {
+        out.push(('\\', " No newline at end of file".to_string()));
+    }
+}
+
+/// Render the `replacements` of `content` as unified diff lines, each prefixed with ' ', '-',
+/// '+', '\\' or '@'. File headers are not included.
+///
+/// ```
+/// use syns::rewrite::{diff, Replacement};
+///
+/// let replacements = [Replacement { lo: 4, hi: 7, text: "qux".to_string() }];
+/// let lines = diff("foo\nbar\nbaz\n", &replacements);
+/// assert_eq!(
+///     lines,
+///     vec![
+///         ('@', "@@ -1,3 +1,3 @@".to_string()),
+///         (' ', "foo".to_string()),
+///         ('-', "bar".to_string()),
+///         ('+', "qux".to_string()),
+///         (' ', "baz".to_string()),
+///     ]
+/// );
+/// ```
+pub fn diff(content: &str, replacements: &[Replacement]) -> Vec<(char, String)> {
+    let lines = content.split_inclusive('\n').collect::<Vec<_>>();
+    let changes = changes(content, &lines, replacements);
+
+    let mut hunks: Vec<Vec<Change>> = Vec::new();
+    for change in changes {
+        match hunks.last_mut() {
+            Some(hunk)
+                if hunk
+                    .last()
+                    .is_some_and(|prev| change.first <= prev.last + 1 + 2 * DIFF_CONTEXT) =>
+            {
+                hunk.push(change)
+            }
+            _ => hunks.push(vec![change]),
+        }
+    }
+
+    let mut out = Vec::new();
+    let mut offset: isize = 0;
+    for hunk in hunks {
+        let (Some(first), Some(last)) = (hunk.first(), hunk.last()) else {
+            continue;
+        };
+        let start = first.first.saturating_sub(DIFF_CONTEXT);
+        let end = (last.last + DIFF_CONTEXT).min(lines.len() - 1);
+
+        let mut body = Vec::new();
+        let mut old_count = 0;
+        let mut new_count = 0;
+        let mut line = start;
+        for change in &hunk {
+            for l in &lines[line..change.first] {
+                push_line(&mut body, ' ', l);
+            }
+            for l in &lines[change.first..=change.last] {
+                push_line(&mut body, '-', l);
+            }
+            for l in &change.new_lines {
+                push_line(&mut body, '+', l);
+            }
+            old_count += change.first - line + change.last - change.first + 1;
+            new_count += change.first - line + change.new_lines.len();
+            line = change.last + 1;
+        }
+        if line <= end {
+            for l in &lines[line..=end] {
+                push_line(&mut body, ' ', l);
+            }
+            old_count += end - line + 1;
+            new_count += end - line + 1;
+        }
+
+        let new_start = (start as isize + offset) as usize;
+        offset += new_count as isize - old_count as isize;
+        out.push((
+            '@',
+            format!(
+                "@@ -{} +{} @@",
+                hunk_range(start, old_count),
+                hunk_range(new_start, new_count)
+            ),
+        ));
+        out.append(&mut body);
+    }
+    out
+}
+
+/// Rewrite all matches of `query` in `file` with `options.replace` and write the whole result to
+/// `out`, for input such as stdin which can't be modified in place. Content which isn't valid
+/// UTF-8 is written unchanged. Returns whether anything matched.
+///
+/// ```
+/// use syns::options::Options;
+/// use syns::query::Query;
+/// use syns::rewrite::rewrite_to;
+///
+/// let options = Options::new("c".as_ref(), &["syns", "--replace", "puts()", "printf()", "-"]).unwrap();
+/// let mut out = Vec::new();
+/// let changed = rewrite_to(&Query::new(&options).unwrap(), &options, &b"printf(x);"[..], &mut out);
+/// assert!(changed.unwrap());
+/// assert_eq!(out, b"puts();");
+/// ```
+pub fn rewrite_to<R: Read, W: Write>(
+    query: &Query,
+    options: &Options,
+    mut file: R,
+    out: &mut W,
+) -> std::io::Result<bool> {
+    let template = options.replace.as_deref().unwrap_or("");
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+    if std::str::from_utf8(&buf).is_err() {
+        warn!("Not rewriting input as it is not valid UTF-8");
+        out.write_all(&buf)?;
+        return Ok(false);
+    }
+
+    let (ast, iter) = parse_file(&buf[..], options);
+    let replacements = replacements(query, &ast, &iter, template);
+    out.write_all(apply(iter.content(), &replacements).as_bytes())?;
+    Ok(!replacements.is_empty())
+}
+
+#[cfg(not(tarpaulin_include))]
+/// Rewrite all matches of `query` in `file` with `options.replace`. Writes the result back to
+/// `filename`, or writes a unified diff to `out` if `options.dry_run` is set. Returns whether anything
+/// matched.
+pub fn rewrite_cached<R: Read, W: WriteColor>(
+    query: &Query,
+    options: &Options,
+    filename: &Path,
+    mut file: R,
+    out: &mut W,
+) -> std::io::Result<bool> {
+    let template = options.replace.as_deref().unwrap_or("");
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)?;
+    if std::str::from_utf8(&buf).is_err() {
+        warn!(
+            "Not rewriting {} as it is not valid UTF-8",
+            filename.to_string_lossy()
+        );
+        return Ok(false);
+    }
+
+    let (ast, iter) = parse_file(&buf[..], options);
+    let replacements = replacements(query, &ast, &iter, template);
+    if replacements.is_empty() {
+        return Ok(false);
+    }
+
+    if !options.dry_run {
+        debug!(
+            "Writing {} replacements to {}",
+            replacements.len(),
+            filename.to_string_lossy()
+        );
+        std::fs::write(filename, apply(iter.content(), &replacements))?;
+        return Ok(true);
+    }
+
+    let mut header_spec = ColorSpec::new();
+    header_spec.set_bold(true);
+    let mut hunk_spec = ColorSpec::new();
+    hunk_spec.set_fg(Some(Color::Cyan));
+    let mut removed_spec = ColorSpec::new();
+    removed_spec.set_fg(Some(Color::Red));
+    let mut added_spec = ColorSpec::new();
+    added_spec.set_fg(Some(Color::Green));
+    let reset_spec = ColorSpec::new();
+
+    let name = filename.to_string_lossy();
+    out.set_color(&header_spec)?;
+    writeln!(out, "--- a/{}", name.trim_start_matches("./"))?;
+    writeln!(out, "+++ b/{}", name.trim_start_matches("./"))?;
+    for (prefix, line) in diff(iter.content(), &replacements) {
+        let spec = match prefix {
+            '@' => &hunk_spec,
+            '-' => &removed_spec,
+            '+' => &added_spec,
+            _ => &reset_spec,
+        };
+        out.set_color(spec)?;
+        if prefix == '@' {
+            writeln!(out, "{}", line)?;
+        } else {
+            writeln!(out, "{}{}", prefix, line)?;
+        }
+    }
+    out.set_color(&reset_spec)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewrite_str(query: &str, template: &str, file: &str) -> String {
+        let options = Options::new("js".as_ref(), &["syns", query, "-"]).expect("options");
+        let query = Query::new(&options).expect("query");
+        let (ast, iter) = parse_file(file.as_bytes(), &options);
+        apply(iter.content(), &replacements(&query, &ast, &iter, template))
+    }
+
+    fn diff_str(query: &str, template: &str, file: &str) -> Vec<String> {
+        let options = Options::new("js".as_ref(), &["syns", query, "-"]).expect("options");
+        let query = Query::new(&options).expect("query");
+        let (ast, iter) = parse_file(file.as_bytes(), &options);
+        diff(iter.content(), &replacements(&query, &ast, &iter, template))
+            .into_iter()
+            .map(|(prefix, line)| {
+                if prefix == '@' {
+                    line
+                } else {
+                    format!("{}{}", prefix, line)
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rewrite_simple() {
+        assert_eq!(rewrite_str("foo", "bar", "foo + foo"), "bar + bar");
+        assert_eq!(rewrite_str("baz", "bar", "foo + foo"), "foo + foo");
+    }
+
+    #[test]
+    fn rewrite_captures() {
+        assert_eq!(
+            rewrite_str(r"\:a = \:a + 1", r"\:a++", "x = x + 1;\ny = z + 1;"),
+            "x++;\ny = z + 1;"
+        );
+        assert_eq!(
+            rewrite_str(r"f(\:a, \:b)", r"f(\:b, \:a)", "f((1, 2), [3])"),
+            "f([3], (1, 2))"
+        );
+        assert_eq!(rewrite_str(r"f(\:a)", r"\\\:a\\", "f(x)"), r"\x\");
+    }
+
+    #[test]
+    fn rewrite_multiline_and_unicode() {
+        assert_eq!(
+            rewrite_str("foo()", "bar()", "let ä = foo(\n  1,\n  2\n);\n"),
+            "let ä = bar();\n"
+        );
+        assert_eq!(rewrite_str(r"\:a", "x", "öö"), "x");
+    }
+
+    #[test]
+    fn rewrite_overlapping() {
+        assert_eq!(rewrite_str(r"a \.", "X", "a a a b"), "X X");
+        assert_eq!(rewrite_str(r"f()", "g()", "f(f(1))"), "g()");
+    }
+
+    #[test]
+    fn diff_single_line() {
+        assert_eq!(
+            diff_str("foo", "bar", "a\nfoo\nb\n"),
+            vec!["@@ -1,3 +1,3 @@", " a", "-foo", "+bar", " b"]
+        );
+    }
+
+    #[test]
+    fn diff_multiline() {
+        assert_eq!(
+            diff_str("f()", "g()", "1\n2\n3\n4\nf(\n  x\n)\n5\n6\n7\n8\n"),
+            vec![
+                "@@ -2,9 +2,7 @@",
+                " 2",
+                " 3",
+                " 4",
+                "-f(",
+                "-  x",
+                "-)",
+                "+g()",
+                " 5",
+                " 6",
+                " 7",
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_separate_hunks() {
+        let file = "f\n1\n2\n3\n4\n5\n6\n7\n8\nf\n";
+        assert_eq!(
+            diff_str("f", "g\nh", file),
+            vec![
+                "@@ -1,4 +1,5 @@",
+                "-f",
+                "+g",
+                "+h",
+                " 1",
+                " 2",
+                " 3",
+                "@@ -7,4 +8,5 @@",
+                " 6",
+                " 7",
+                " 8",
+                "-f",
+                "+g",
+                "+h",
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_no_trailing_newline() {
+        assert_eq!(
+            diff_str("foo", "bar", "foo"),
+            vec![
+                "@@ -1 +1 @@",
+                "-foo",
+                "\\ No newline at end of file",
+                "+bar",
+                "\\ No newline at end of file",
+            ]
+        );
+    }
+}
@@ -0,0 +1,228 @@
+//! Rule files: many named queries run in a single pass.
+//!
+//! A rule file is a JSON object with a list of rules:
+//!
+//! ```json
+//! {
+//!   "rules": [
+//!     {
+//!       "id": "no-eval",
+//!       "pattern": "eval()",
+//!       "message": "Avoid eval",
+//!       "severity": "error",
+//!       "languages": ["javascript"],
+//!       "paths": ["src/**"]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Only `id` and `pattern` are required. `message` defaults to the pattern, `severity` to
+//! `warning`, and empty `languages` or `paths` lists place no restriction on the file.
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::options::Options;
+
+/// How serious a rule violation is.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Something that should be fixed.
+    Error,
+    /// Something that should be looked at.
+    #[default]
+    Warning,
+    /// Informational.
+    Note,
+}
+
+impl Severity {
+    /// Name of the severity, which is also its SARIF `level`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    rules: Vec<RuleDefinition>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleDefinition {
+    id: String,
+    pattern: String,
+    message: Option<String>,
+    #[serde(default)]
+    severity: Severity,
+    #[serde(default)]
+    languages: Vec<String>,
+    #[serde(default)]
+    paths: Vec<String>,
+}
+
+/// A named query loaded from a rule file.
+#[derive(Debug)]
+pub struct Rule {
+    /// Unique identifier of the rule.
+    pub id: String,
+    /// Query string.
+    pub pattern: String,
+    /// Message shown for each match.
+    pub message: String,
+    /// Severity of each match.
+    pub severity: Severity,
+    /// Only run the rule on files of these languages, or on all files if empty.
+    pub languages: Vec<String>,
+    /// Only run the rule on paths matching these globs, or on all paths if `None`.
+    paths: Option<GlobSet>,
+}
+
+impl Rule {
+    /// Should this rule be run on files whose defaults come from `language`?
+    pub fn applies_to_language(&self, language: Option<&str>) -> bool {
+        self.languages.is_empty()
+            || language.is_some_and(|lang| self.languages.iter().any(|l| l == lang))
+    }
+
+    /// Should this rule be run on `path`, whose defaults come from `language`?
+    pub fn applies_to(&self, path: &Path, language: Option<&str>) -> bool {
+        if !self.applies_to_language(language) {
+            return false;
+        }
+        match &self.paths {
+            Some(globs) => globs.is_match(path.strip_prefix("./").unwrap_or(path)),
+            None => true,
+        }
+    }
+}
+
+/// Parse the rule file contents in `json`, whose languages must be known to `options`.
+///
+/// ```
+/// use syns::options::Options;
+/// use syns::rules::{parse_rules, Severity};
+/// let options = Options::default();
+/// let rules = parse_rules(r#"{"rules": [{"id": "a", "pattern": "foo()"}]}"#, &options).unwrap();
+/// assert_eq!(rules[0].id, "a");
+/// assert_eq!(rules[0].message, "foo()");
+/// assert_eq!(rules[0].severity, Severity::Warning);
+/// ```
+pub fn parse_rules(json: &str, options: &Options) -> Result<Vec<Rule>, String> {
+    let file: RuleFile = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let mut seen = HashSet::new();
+    let mut rules = Vec::new();
+    for def in file.rules {
+        if !seen.insert(def.id.clone()) {
+            return Err(format!("Duplicate rule id '{}'", def.id));
+        }
+        for lang in &def.languages {
+            if !options.is_known_language(lang).map_err(|e| e.to_string())? {
+                return Err(format!("Unknown language '{}' in rule '{}'", lang, def.id));
+            }
+        }
+        let paths = if def.paths.is_empty() {
+            None
+        } else {
+            let mut builder = GlobSetBuilder::new();
+            for glob in &def.paths {
+                builder.add(
+                    Glob::new(glob)
+                        .map_err(|e| format!("Invalid path glob in rule '{}': {}", def.id, e))?,
+                );
+            }
+            Some(
+                builder
+                    .build()
+                    .map_err(|e| format!("Invalid path glob in rule '{}': {}", def.id, e))?,
+            )
+        };
+        rules.push(Rule {
+            message: def.message.unwrap_or_else(|| def.pattern.clone()),
+            id: def.id,
+            pattern: def.pattern,
+            severity: def.severity,
+            languages: def.languages,
+            paths,
+        });
+    }
+    Ok(rules)
+}
+
+#[cfg(not(tarpaulin_include))]
+/// Read and parse the rule file at `path`.
+pub fn load_rules(path: &Path, options: &Options) -> Result<Vec<Rule>, String> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read rule file {}: {}", path.to_string_lossy(), e))?;
+    parse_rules(&json, options)
+        .map_err(|e| format!("Invalid rule file {}: {}", path.to_string_lossy(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> Result<Vec<Rule>, String> {
+        parse_rules(json, &Options::default())
+    }
+
+    #[test]
+    fn full_rule() {
+        let rules = parse(
+            r#"{"rules": [{
+                "id": "no-eval",
+                "pattern": "eval()",
+                "message": "Avoid eval",
+                "severity": "error",
+                "languages": ["javascript"],
+                "paths": ["src/**"]
+            }]}"#,
+        )
+        .expect("Failed to parse rules");
+        let rule = &rules[0];
+        assert_eq!(rule.id, "no-eval");
+        assert_eq!(rule.pattern, "eval()");
+        assert_eq!(rule.message, "Avoid eval");
+        assert_eq!(rule.severity, Severity::Error);
+        assert_eq!(rule.severity.as_str(), "error");
+
+        assert!(rule.applies_to("src/a.js".as_ref(), Some("javascript")));
+        assert!(rule.applies_to("./src/lib/a.js".as_ref(), Some("javascript")));
+        assert!(!rule.applies_to("test/a.js".as_ref(), Some("javascript")));
+        assert!(!rule.applies_to("src/a.py".as_ref(), Some("python")));
+        assert!(!rule.applies_to("src/a.txt".as_ref(), None));
+    }
+
+    #[test]
+    fn unrestricted_rule() {
+        let rules =
+            parse(r#"{"rules": [{"id": "a", "pattern": "foo"}]}"#).expect("Failed to parse rules");
+        assert!(rules[0].applies_to("anything".as_ref(), None));
+        assert!(rules[0].applies_to("x/y.py".as_ref(), Some("python")));
+    }
+
+    #[test]
+    fn invalid_rules() {
+        assert!(parse(r#"{"rules": [{"id": "a"}]}"#).is_err());
+        assert!(parse(r#"{"rules": [{"id": "a", "pattern": "x", "extra": 1}]}"#).is_err());
+        assert!(
+            parse(r#"{"rules": [{"id": "a", "pattern": "x"}, {"id": "a", "pattern": "y"}]}"#)
+                .is_err()
+        );
+        assert!(
+            parse(r#"{"rules": [{"id": "a", "pattern": "x", "languages": ["klingon"]}]}"#).is_err()
+        );
+        assert!(parse(r#"{"rules": [{"id": "a", "pattern": "x", "paths": ["a/[b"]}]}"#).is_err());
+        assert!(parse(r#"{"rules": [{"id": "a", "pattern": "x", "severity": "fatal"}]}"#).is_err());
+    }
+}
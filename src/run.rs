@@ -1,13 +1,17 @@
 //! Main entry point for the program.
 
-use log::debug;
-use std::io::{Read, Write};
+use itertools::Itertools;
+use log::{debug, warn};
+use serde::Serialize;
+use std::io::Read;
 use std::path::Path;
-use termcolor::{Color, ColorSpec, StandardStream, WriteColor};
+use termcolor::{Color, ColorSpec, WriteColor};
 
 use crate::options::*;
 use crate::parser::*;
+use crate::psi::{NumberedLine, PeekableStringIterator};
 use crate::query::*;
+use crate::rules::Rule;
 
 macro_rules! write_colored {
     ($c:expr, $stdout:expr, $($arg:tt)*) => {let _ = {
@@ -22,9 +26,86 @@ macro_rules! writeln_colored {
     };}
 }
 
+/// A single match in the JSON Lines output.
+#[derive(Debug, Serialize)]
+struct JsonMatch<'a> {
+    /// Path of the matching file.
+    path: &'a str,
+    /// Id of the rule which produced the match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rule_id: Option<&'a str>,
+    /// Message of the rule which produced the match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<&'a str>,
+    /// Severity of the rule which produced the match.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    severity: Option<&'a str>,
+    /// First line of the match, starting from 1.
+    start_line: usize,
+    /// Last line of the match.
+    end_line: usize,
+    /// Column of the first character, starting from 1.
+    start_column: usize,
+    /// Column after the last character.
+    end_column: usize,
+    /// Byte offset of the first character.
+    start_byte: usize,
+    /// Byte offset after the last character.
+    end_byte: usize,
+    /// Matched text.
+    text: String,
+    /// Full lines containing the match.
+    lines: Vec<String>,
+}
+
+#[cfg(not(tarpaulin_include))]
+/// Parse `file` with `options` and print all matches to `out`.
+pub fn run_cached<R: Read, W: WriteColor>(
+    query: &Query,
+    options: &Options,
+    filename: &Path,
+    file: R,
+    out: &mut W,
+) -> bool {
+    debug!("Parsing file");
+    let (file, iter) = parse_file(file, options);
+    print_matches(query, options, filename, &file, &iter, None, out)
+}
+
+#[cfg(not(tarpaulin_include))]
+/// Print a single line of context around a match.
+fn print_context_line<W: WriteColor>(
+    options: &Options,
+    filename: &Path,
+    path_spec: &ColorSpec,
+    (line_number, text): &NumberedLine,
+    out: &mut W,
+) {
+    if !options.dont_print_filenames {
+        write_colored!(
+            path_spec,
+            out,
+            "[{}-{}]",
+            &filename.to_string_lossy(),
+            line_number
+        );
+        write_colored!(&ColorSpec::new(), out, " ");
+    }
+    writeln_colored!(&ColorSpec::new(), out, "{}", text);
+}
+
 #[cfg(not(tarpaulin_include))]
-/// Parse `file` with `options` and print all matches.
-pub fn run_cached<R: Read>(query: &Query, options: &Options, filename: &Path, file: R) -> bool {
+/// Print all matches of `query` in the already parsed `file` to `out`, labeled with `rule` if
+/// given.
+pub fn print_matches<W: WriteColor>(
+    query: &Query,
+    options: &Options,
+    filename: &Path,
+    file: &[Ast],
+    iter: &PeekableStringIterator,
+    rule: Option<&Rule>,
+    out: &mut W,
+) -> bool {
     /* Colors from ripgrep's printer crate */
     #[cfg(unix)]
     let path_style: Color = Color::Magenta;
@@ -32,6 +113,7 @@ pub fn run_cached<R: Read>(query: &Query, options: &Options, filename: &Path, fi
     let path_style: Color = Color::Cyan;
     let line_number_style: Color = Color::Green;
     let match_fg_color: Color = Color::Red;
+    let rule_style: Color = Color::Yellow;
 
     let reset_spec = ColorSpec::new();
     let mut path_spec = ColorSpec::new();
@@ -40,29 +122,113 @@ pub fn run_cached<R: Read>(query: &Query, options: &Options, filename: &Path, fi
     line_number_spec.set_fg(Some(line_number_style));
     let mut match_spec = ColorSpec::new();
     match_spec.set_fg(Some(match_fg_color)).set_bold(true);
+    let mut rule_spec = ColorSpec::new();
+    rule_spec.set_fg(Some(rule_style));
+
+    let context = (options.before_context > 0 || options.after_context > 0)
+        && !(options.json || options.only_matching || options.only_print_filenames);
+    // Last line printed so far, used to merge overlapping context
+    let mut last_line = 0;
+    // Context after the previous matches, printed once the next match is known
+    let mut pending_after: Vec<NumberedLine> = Vec::new();
 
-    let mut stdout = StandardStream::stdout(options.color);
-    debug!("Parsing file");
-    let (file, iter) = parse_file(file, options);
     debug!("Enumerating matches");
+    let matches: Box<dyn Iterator<Item = Match>> = if context {
+        // Merging context requires the matches to be in source order
+        Box::new(
+            query
+                .matches(file)
+                .sorted_by_key(|m| m.t.first().map(|t| t.span().lo)),
+        )
+    } else {
+        Box::new(query.matches(file))
+    };
     let mut found_match = false;
-    for m in query.matches(&file) {
+    for m in matches {
         debug!("Match: {:#?}", &m);
         if m.t.is_empty() {
             continue;
         }
         found_match = true;
         if options.only_print_filenames {
-            println!("{}", filename.to_string_lossy());
+            let _ = writeln!(out, "{}", filename.to_string_lossy());
             break;
         }
         let span = m.t[0].span().merge(&m.t.last().unwrap_or(&m.t[0]).span());
         let (start, end) = iter.get_line_information(span);
-        let ws_if_filenames = if options.dont_print_filenames {
+        if options.json {
+            let (start_column, end_column) = iter.get_column_information(span);
+            let (head, lines, tail) = iter.get_lines_including(span);
+            let record = JsonMatch {
+                path: &filename.to_string_lossy(),
+                rule_id: rule.map(|r| r.id.as_str()),
+                message: rule.map(|r| r.message.as_str()),
+                severity: rule.map(|r| r.severity.as_str()),
+                start_line: start,
+                end_line: end,
+                start_column,
+                end_column,
+                start_byte: span.lo,
+                end_byte: iter.get_span_end(span),
+                text: iter.get_content_between(span),
+                lines: format!("{}{}{}", head, lines.join("\n"), tail)
+                    .lines()
+                    .map(|s| s.to_string())
+                    .collect(),
+            };
+            match serde_json::to_string(&record) {
+                Ok(json) => {
+                    let _ = writeln!(out, "{}", json);
+                }
+                Err(e) => warn!("Failed to serialize match: {}", e),
+            }
+            continue;
+        }
+        let ws_if_filenames = if options.dont_print_filenames && rule.is_none() {
             ""
         } else {
             " "
         };
+        let rule_label = rule.map(|rule| {
+            if options.dont_print_filenames {
+                format!("[{}]", rule.id)
+            } else {
+                format!(" [{}]", rule.id)
+            }
+        });
+        if context {
+            let (before, after) =
+                iter.get_context_lines(span, options.before_context, options.after_context);
+            pending_after.retain(|line| {
+                if line.0 < start {
+                    print_context_line(options, filename, &path_spec, line, out);
+                    last_line = line.0;
+                    false
+                } else {
+                    true
+                }
+            });
+            let before = before
+                .into_iter()
+                .filter(|(n, _)| *n > last_line)
+                .collect::<Vec<_>>();
+            let first_line = before.first().map(|(n, _)| *n).unwrap_or(start);
+            if last_line > 0 && first_line > last_line + 1 {
+                writeln_colored!(&reset_spec, out, "--");
+            }
+            for line in &before {
+                print_context_line(options, filename, &path_spec, line, out);
+            }
+            last_line = last_line.max(end);
+            pending_after.retain(|(n, _)| *n > last_line);
+            let after = after
+                .into_iter()
+                .filter(|(n, _)| *n > last_line)
+                .collect::<Vec<_>>();
+            if after.len() > pending_after.len() {
+                pending_after = after;
+            }
+        }
         let line_number = if options.dont_print_filenames {
             "".to_string()
         } else if start == end {
@@ -72,11 +238,14 @@ pub fn run_cached<R: Read>(query: &Query, options: &Options, filename: &Path, fi
         };
         if options.only_matching {
             if !options.dont_print_filenames {
-                write_colored!(&path_spec, stdout, "{}", line_number);
+                write_colored!(&path_spec, out, "{}", line_number);
+            }
+            if let Some(label) = &rule_label {
+                write_colored!(&rule_spec, out, "{}", label);
             }
             writeln_colored!(
                 &match_spec,
-                stdout,
+                out,
                 "{}{}",
                 ws_if_filenames,
                 iter.get_content_between(span)
@@ -85,29 +254,44 @@ pub fn run_cached<R: Read>(query: &Query, options: &Options, filename: &Path, fi
             let (head, lines, tail) = iter.get_lines_including(span);
             if lines.len() == 1 {
                 if !options.dont_print_filenames {
-                    write_colored!(&path_spec, stdout, "{}", line_number);
+                    write_colored!(&path_spec, out, "{}", line_number);
                 }
-                write_colored!(&reset_spec, stdout, "{}{}", ws_if_filenames, head);
-                write_colored!(&match_spec, stdout, "{}", lines[0]);
-                writeln_colored!(&reset_spec, stdout, "{}", tail);
+                if let Some(label) = &rule_label {
+                    write_colored!(&rule_spec, out, "{}", label);
+                }
+                write_colored!(&reset_spec, out, "{}{}", ws_if_filenames, head);
+                write_colored!(&match_spec, out, "{}", lines[0]);
+                writeln_colored!(&reset_spec, out, "{}", tail);
             } else {
-                if !options.dont_print_filenames {
-                    writeln_colored!(&path_spec, stdout, "{}", line_number);
+                match &rule_label {
+                    Some(label) => {
+                        if !options.dont_print_filenames {
+                            write_colored!(&path_spec, out, "{}", line_number);
+                        }
+                        writeln_colored!(&rule_spec, out, "{}", label);
+                    }
+                    None if !options.dont_print_filenames => {
+                        writeln_colored!(&path_spec, out, "{}", line_number);
+                    }
+                    None => {}
                 }
-                write_colored!(&reset_spec, stdout, "{}", head);
+                write_colored!(&reset_spec, out, "{}", head);
                 let mut lines_peekable = lines.into_iter().peekable();
                 while let Some(line) = lines_peekable.next() {
-                    let _ = stdout.set_color(&match_spec);
+                    let _ = out.set_color(&match_spec);
                     if lines_peekable.peek().is_some() {
-                        writeln_colored!(&match_spec, stdout, "{}", line);
+                        writeln_colored!(&match_spec, out, "{}", line);
                     } else {
-                        write_colored!(&match_spec, stdout, "{}", line);
+                        write_colored!(&match_spec, out, "{}", line);
                     }
                 }
-                writeln_colored!(&reset_spec, stdout, "{}", tail);
+                writeln_colored!(&reset_spec, out, "{}", tail);
             }
         }
     }
+    for line in &pending_after {
+        print_context_line(options, filename, &path_spec, line, out);
+    }
     debug!("Done");
     found_match
 }
@@ -119,15 +303,19 @@ mod tests {
     use crate::tokenizer::*;
 
     fn run_all<R: Read>(options: Options, file: R) -> Vec<Match> {
-        let query = Query::new(&options);
+        let query = Query::new(&options).expect("query");
         let (file, _iter) = parse_file(file, &options);
         query.matches(&file).collect()
     }
 
     fn run_strs(query: &str, file: &str) -> Vec<String> {
-        let options = Options::new("js".as_ref(), &["syns", query, "-"]);
+        run_strs_ext("js", query, file)
+    }
+
+    fn run_strs_ext(ext: &str, query: &str, file: &str) -> Vec<String> {
+        let options = Options::new(ext.as_ref(), &["syns", query, "-"]).expect("options");
         let file = file.as_bytes();
-        let query = Query::new(&options);
+        let query = Query::new(&options).expect("query");
         let (file, iter) = parse_file(file, &options);
         query
             .matches(&file)
@@ -141,7 +329,7 @@ mod tests {
     #[test]
     fn test_empty() {
         let res = run_all(
-            Options::new("js".as_ref(), &["syns", "bar", "-"]),
+            Options::new("js".as_ref(), &["syns", "bar", "-"]).expect("options"),
             "foo".as_bytes(),
         );
         assert_eq!(res.len(), 0);
@@ -150,7 +338,7 @@ mod tests {
     #[test]
     fn test_one_match() {
         let res = run_all(
-            Options::new("js".as_ref(), &["syns", "foo", "-"]),
+            Options::new("js".as_ref(), &["syns", "foo", "-"]).expect("options"),
             "foo".as_bytes(),
         );
         assert_eq!(res.len(), 1);
@@ -167,7 +355,7 @@ mod tests {
     #[test]
     fn test_longest_match() {
         let res = run_all(
-            Options::new("js".as_ref(), &["syns", "\\.\\*", "-"]),
+            Options::new("js".as_ref(), &["syns", "\\.\\*", "-"]).expect("options"),
             "a a".as_bytes(),
         );
         assert_eq!(res.len(), 2);
@@ -225,7 +413,78 @@ mod tests {
 
     #[test]
     fn test_mismatched_parens_in_source() {
-        assert_eq!(run_strs("a ()", "a ([b)]"), vec!["a ([b)]"]);
+        // `)` closes the enclosing `(`, leaving `[` unterminated and `]` unmatched
+        assert_eq!(run_strs("a ()", "a ([b)]"), vec!["a ([b)"]);
+        assert_eq!(run_strs("a (b ]) c", "a (b]) c"), vec!["a (b]) c"]);
+    }
+
+    #[test]
+    fn test_keyword_blocks() {
+        let source = "if x do\n  fn -> y end\nelse\n  z\nend";
+        assert_eq!(
+            run_strs_ext("ex", "do fn \\. end else z end", source),
+            vec!["do\n  fn -> y end\nelse\n  z\nend"]
+        );
+        // Keyword arguments don't open a block
+        assert_eq!(
+            run_strs_ext("ex", "if \\. , do: \\.", "if x, do: y"),
+            vec!["if x, do: y"]
+        );
+    }
+
+    #[test]
+    fn test_multi_word_keyword_blocks() {
+        let source = "Sub A()\n  If x Then Exit Sub\n  B()\nEND  SUB";
+        assert_eq!(
+            run_strs_ext("vb", "sub A() \\.\\* B() \\$ end sub", source),
+            vec![source]
+        );
+        // `End Subway` isn't a closer
+        assert_eq!(
+            run_strs_ext("vb", "End Subway", "Sub A() End Subway"),
+            vec!["End Subway"]
+        );
+    }
+
+    #[test]
+    fn test_indent_blocks() {
+        let source =
+            "class A:\n    def f(self):\n        return 1\n\n    def g(self):\n        pass\n";
+        assert_eq!(
+            run_strs_ext("py", "def \\. () : \\has(return)", source),
+            vec!["def f(self):\n        return 1"]
+        );
+        assert_eq!(
+            run_strs_ext("py", "class \\. : \\.", source),
+            vec![source.trim_end()]
+        );
+    }
+
+    #[test]
+    fn test_shared_closers() {
+        let source = "BEGIN SELECT CASE WHEN a THEN 1 END; END";
+        assert_eq!(
+            run_strs_ext("sql", "begin SELECT case end; end", source),
+            vec![source]
+        );
+        assert_eq!(
+            run_strs_ext("sql", "case when a end", source),
+            vec!["CASE WHEN a THEN 1 END"]
+        );
+    }
+
+    #[test]
+    fn test_unterminated_keyword_blocks() {
+        // The unterminated `Sub` ends at the `End Interface` of the enclosing block
+        let source = "Interface I\n  Sub A()\nEnd Interface\nSub B()\nEnd Sub";
+        assert_eq!(
+            run_strs_ext("vb", "Interface I \\.\\* End Interface", source),
+            vec!["Interface I\n  Sub A()\nEnd Interface"]
+        );
+        assert_eq!(
+            run_strs_ext("vb", "Sub B() End Sub", source),
+            vec!["Sub B()\nEnd Sub"]
+        );
     }
 
     #[test]
@@ -240,7 +499,8 @@ mod tests {
             Options::new(
                 "js".as_ref(),
                 &["syns", "do foo end", "-b", "do", "end", "-"],
-            ),
+            )
+            .expect("options"),
             "do foo end".as_bytes(),
         );
         assert_eq!(res.len(), 1);
@@ -297,6 +557,70 @@ mod tests {
         assert_eq!(run_strs("\\\"foo.*\"", "+"), Vec::<String>::new());
     }
 
+    #[test]
+    fn test_comments() {
+        let source = "a(/* x */ 1) // nolint\nb(2) // TODO(alice)\n";
+        // Comments are skipped by other matchers
+        assert_eq!(run_strs("a(1)", source), vec!["a(/* x */ 1)"]);
+        assert_eq!(run_strs(r"a(\.\$)", source), vec!["a(/* x */ 1)"]);
+        assert_eq!(
+            run_strs(r"f\:x f\:x", "f(1) f(/* y */ 1)"),
+            vec!["f(1) f(/* y */ 1)"]
+        );
+        assert_eq!(
+            run_strs(r#"\.() \#"nolint""#, source),
+            vec!["a(/* x */ 1) // nolint"]
+        );
+        assert_eq!(
+            run_strs(r#"\#"TODO\(alice\)""#, source),
+            vec!["// TODO(alice)"]
+        );
+        assert_eq!(run_strs(r#"(\#"x" 1)"#, source), vec!["(/* x */ 1)"]);
+        assert_eq!(run_strs(r#"\#"y""#, source), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_typed_wildcards() {
+        let source = "get_a(1); set_b(2000); sleep(1500.5); sleep(10); a <= b";
+        assert_eq!(run_strs(r#"\i"^get_"()"#, source), vec!["get_a(1)"]);
+        assert_eq!(
+            run_strs(r"\i(\n)", source),
+            vec!["get_a(1)", "set_b(2000)", "sleep(1500.5)", "sleep(10)"]
+        );
+        assert_eq!(run_strs(r"sleep(\n>1000)", source), vec!["sleep(1500.5)"]);
+        // The sign is a separate symbol
+        assert_eq!(run_strs(r"f(-\n>2)", "f(-3); f(-1); f(3)"), vec!["f(-3)"]);
+        assert_eq!(run_strs(r"f(\n<0)", "f(-3)"), Vec::<String>::new());
+        assert_eq!(
+            run_strs(r"\.(\n>=10<=2000)", source),
+            vec!["set_b(2000)", "sleep(1500.5)", "sleep(10)"]
+        );
+        assert_eq!(run_strs(r#"\i \sym"[<>]=?" \i"#, source), vec!["a <= b"]);
+        assert_eq!(run_strs(r"\.(\s)", "f(1) g('x')"), vec!["g('x')"]);
+    }
+
+    #[test]
+    fn test_ignore_case() {
+        let source = "SELECT Name FROM users; select name from Users;";
+        assert_eq!(
+            run_strs_ext("sql", "select name from users", source),
+            vec!["SELECT Name FROM users", "select name from Users"]
+        );
+        assert_eq!(
+            run_strs_ext(
+                "sql",
+                r"select \:x from \:x",
+                "select a from A; select a from b"
+            ),
+            vec!["select a from A"]
+        );
+        assert_eq!(run_strs("select", source), vec!["select"]);
+        let options =
+            Options::new("js".as_ref(), &["syns", "--ignore-case", "foo()", "-"]).expect("options");
+        let res = run_all(options, "FOO(); Foo(); bar();".as_bytes());
+        assert_eq!(res.len(), 2);
+    }
+
     #[test]
     fn test_group_end() {
         assert_eq!(run_strs(r"foo(\.\$)", "foo(a)"), vec!["foo(a)"]);
@@ -333,8 +657,120 @@ mod tests {
         assert_eq!(run_strs("{}", "Foo"), Vec::<String>::new());
     }
 
+    #[test]
+    fn test_capture_backreference() {
+        assert_eq!(
+            run_strs(r"\:x == \:x", "if (a == a) if (a == b)"),
+            vec!["a == a"]
+        );
+        assert_eq!(
+            run_strs(r"\:x = \:x + 1", "y = y + 1; y = z + 1"),
+            vec!["y = y + 1"]
+        );
+        assert_eq!(
+            run_strs(r"\:a \:b \:a", "x y x y x"),
+            vec!["x y x", "y x y", "x y x"]
+        );
+    }
+
+    #[test]
+    fn test_capture_structural() {
+        assert_eq!(
+            run_strs(r"\:x == \:x", "(a [b]) == (a [b])"),
+            vec!["(a [b]) == (a [b])"]
+        );
+        assert_eq!(
+            run_strs(r"\:x == \:x", "(a [b]) == (a [c])"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_capture_across_blocks() {
+        assert_eq!(
+            run_strs(r"foo(\:x) \:x", "foo(a) b foo(a) a"),
+            vec!["foo(a) a"]
+        );
+        assert_eq!(
+            run_strs(r"\:x (\.\* \:x)", "a (b a) b (c)"),
+            vec!["a (b a)"]
+        );
+    }
+
+    #[test]
+    fn test_negation() {
+        assert_eq!(run_strs(r"\!foo \.", "foo bar"), vec!["bar"]);
+        assert_eq!(
+            run_strs(
+                r#"exec(\!\"" \.\*)"#,
+                r#"exec("ls"); exec(cmd, 1); exec();"#
+            ),
+            vec!["exec(cmd, 1)", "exec()"]
+        );
+        assert_eq!(
+            run_strs(
+                r"{\(\!unlock \.\)\* \$}",
+                "{ lock(); unlock(); } { lock(); }"
+            ),
+            vec!["{ lock(); }"]
+        );
+        assert_eq!(
+            run_strs(r"f(\!\(a b\) \.\+)", "f(a b) f(a c) f(b)"),
+            vec!["f(a c)", "f(b)"]
+        );
+    }
+
+    #[test]
+    fn test_contains() {
+        assert_eq!(
+            run_strs(
+                r"function \. \. \has{free()}",
+                "function foo(a) { if (a) { free(a); } } function bar() { malloc(); }"
+            ),
+            vec!["function foo(a) { if (a) { free(a); } }"]
+        );
+        assert_eq!(
+            run_strs(r"\has(unsafe)", "a(b, [c, unsafe]) d(e)"),
+            vec!["(b, [c, unsafe])", "[c, unsafe]"]
+        );
+        assert_eq!(run_strs(r"\has{x}", "(x) {y(x)}"), vec!["{y(x)}"]);
+        assert_eq!(
+            run_strs(
+                r"function \. \. \!\has{free()} \.",
+                "function foo() { free(a); } function bar() { malloc(); }"
+            ),
+            vec!["function bar() { malloc(); }"]
+        );
+    }
+
+    #[test]
+    fn test_contains_backreference() {
+        assert_eq!(
+            run_strs(
+                r"\:p = malloc(); \has{free(\:p)}",
+                "a = malloc(); { free(b); } c = malloc(); { if (c) { free(c); } }"
+            ),
+            vec!["c = malloc(); { if (c) { free(c); } }"]
+        );
+    }
+
+    #[test]
+    fn test_negation_backreference() {
+        assert_eq!(run_strs(r"\:x = \!\:x \.", "a = b; c = c;"), vec!["a = b"]);
+    }
+
+    #[test]
+    fn test_negation_zero_width_loop() {
+        assert_eq!(run_strs(r"\(\!a\)\* b", "a b"), vec!["b"]);
+        assert_eq!(run_strs(r"\(\!a\)\+ b", "a b"), vec!["b"]);
+    }
+
     #[test]
     fn test_unmatched_braces() {
-        assert_eq!(run_strs("()", "(()(}{{}}"), vec!["(()(}{{}}", "()", "(}"]);
+        // The unmatched `}` is a plain token inside the unterminated `(`
+        assert_eq!(
+            run_strs("()", "(()(}{{}}"),
+            vec!["(()(}{{}}", "()", "(}{{}}"]
+        );
     }
 }
@@ -0,0 +1,256 @@
+//! SARIF 2.1.0 output for code scanning tools.
+
+use serde::Serialize;
+use std::io::Read;
+use std::path::Path;
+
+use crate::options::Options;
+use crate::parser::{parse_file, Ast};
+use crate::psi::PeekableStringIterator;
+use crate::query::Query;
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const SARIF_VERSION: &str = "2.1.0";
+
+/// Rule id used for matches of the query given on the command line.
+pub const QUERY_RULE_ID: &str = "query";
+
+/// Top-level SARIF log.
+#[derive(Debug, Serialize)]
+struct Log {
+    #[serde(rename = "$schema")]
+    schema: &'static str,
+    version: &'static str,
+    runs: Vec<Run>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Run {
+    tool: Tool,
+    column_kind: &'static str,
+    results: Vec<SarifResult>,
+}
+
+#[derive(Debug, Serialize)]
+struct Tool {
+    driver: Driver,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Driver {
+    name: &'static str,
+    version: &'static str,
+    information_uri: &'static str,
+    rules: Vec<Rule>,
+}
+
+/// A rule which produced results.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Rule {
+    id: String,
+    short_description: Message,
+    default_configuration: Configuration,
+}
+
+impl Rule {
+    /// Create a new rule with `id`, described by `description`, whose results have `level`.
+    pub fn new(id: &str, description: &str, level: &str) -> Rule {
+        Rule {
+            id: id.to_string(),
+            short_description: Message {
+                text: description.to_string(),
+            },
+            default_configuration: Configuration {
+                level: level.to_string(),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct Configuration {
+    level: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct Message {
+    text: String,
+}
+
+/// A single match, ready to be serialized.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifResult {
+    rule_id: String,
+    level: String,
+    message: Message,
+    locations: Vec<Location>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Location {
+    physical_location: PhysicalLocation,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PhysicalLocation {
+    artifact_location: ArtifactLocation,
+    region: Region,
+}
+
+#[derive(Debug, Serialize)]
+struct ArtifactLocation {
+    uri: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Region {
+    start_line: usize,
+    start_column: usize,
+    end_line: usize,
+    end_column: usize,
+    snippet: Message,
+}
+
+/// Convert a path to a relative URI reference, percent-encoding reserved characters.
+///
+/// ```
+/// use syns::sarif::path_to_uri;
+/// assert_eq!(path_to_uri("./src/my file.c".as_ref()), "src/my%20file.c");
+/// ```
+pub fn path_to_uri(path: &Path) -> String {
+    let lossy = path.to_string_lossy().replace('\\', "/");
+    let mut uri = String::new();
+    for b in lossy.trim_start_matches("./").bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(b as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    uri
+}
+
+#[cfg(not(tarpaulin_include))]
+/// Parse `file` with `options` and collect all matches as SARIF results for rule `rule_id`.
+pub fn file_results<R: Read>(
+    query: &Query,
+    options: &Options,
+    rule_id: &str,
+    filename: &Path,
+    file: R,
+) -> Vec<SarifResult> {
+    let (file, iter) = parse_file(file, options);
+    let message = format!("Match for query '{}'", options.query);
+    match_results(query, rule_id, "warning", &message, filename, &file, &iter)
+}
+
+/// Collect all matches of `query` in the already parsed `file` as SARIF results for rule
+/// `rule_id`.
+pub fn match_results(
+    query: &Query,
+    rule_id: &str,
+    level: &str,
+    message: &str,
+    filename: &Path,
+    file: &[Ast],
+    iter: &PeekableStringIterator,
+) -> Vec<SarifResult> {
+    let uri = path_to_uri(filename);
+    query
+        .matches(file)
+        .filter(|m| !m.t.is_empty())
+        .map(|m| {
+            let span = m.t[0].span().merge(&m.t.last().unwrap_or(&m.t[0]).span());
+            let (start_line, end_line) = iter.get_line_information(span);
+            let (start_column, end_column) = iter.get_column_information(span);
+            SarifResult {
+                rule_id: rule_id.to_string(),
+                level: level.to_string(),
+                message: Message {
+                    text: message.to_string(),
+                },
+                locations: vec![Location {
+                    physical_location: PhysicalLocation {
+                        artifact_location: ArtifactLocation { uri: uri.clone() },
+                        region: Region {
+                            start_line,
+                            start_column,
+                            end_line,
+                            end_column,
+                            snippet: Message {
+                                text: iter.get_content_between(span),
+                            },
+                        },
+                    },
+                }],
+            }
+        })
+        .collect()
+}
+
+/// Wrap `results` into a SARIF log with a single run.
+pub fn to_log(rules: Vec<Rule>, results: Vec<SarifResult>) -> serde_json::Result<String> {
+    serde_json::to_string_pretty(&Log {
+        schema: SARIF_SCHEMA,
+        version: SARIF_VERSION,
+        runs: vec![Run {
+            tool: Tool {
+                driver: Driver {
+                    name: "syns",
+                    version: env!("CARGO_PKG_VERSION"),
+                    information_uri: "https://github.com/jgke/syntax-searcher",
+                    rules,
+                },
+            },
+            column_kind: "unicodeCodePoints",
+            results,
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sarif_log() {
+        let options = Options::new("js".as_ref(), &["syns", "foo()", "-"]).expect("options");
+        let query = Query::new(&options).expect("query");
+        let results = file_results(
+            &query,
+            &options,
+            QUERY_RULE_ID,
+            "dir/a b.js".as_ref(),
+            "bar\n  foo(1,\n  2)".as_bytes(),
+        );
+        let log = to_log(vec![Rule::new(QUERY_RULE_ID, "foo()", "warning")], results)
+            .expect("Failed to serialize SARIF log");
+        let json: serde_json::Value = serde_json::from_str(&log).expect("Invalid JSON");
+
+        assert_eq!(json["version"], "2.1.0");
+        let run = &json["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "syns");
+        assert_eq!(run["tool"]["driver"]["rules"][0]["id"], "query");
+        assert_eq!(run["results"].as_array().map(|r| r.len()), Some(1));
+
+        let result = &run["results"][0];
+        assert_eq!(result["ruleId"], "query");
+        assert_eq!(result["level"], "warning");
+        assert_eq!(result["message"]["text"], "Match for query 'foo()'");
+        let location = &result["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "dir/a%20b.js");
+        assert_eq!(location["region"]["startLine"], 2);
+        assert_eq!(location["region"]["startColumn"], 3);
+        assert_eq!(location["region"]["endLine"], 3);
+        assert_eq!(location["region"]["endColumn"], 5);
+        assert_eq!(location["region"]["snippet"]["text"], "foo(1,\n  2)");
+    }
+}
@@ -0,0 +1,175 @@
+//! Searching without printing, for using syns as a library.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::ops::{Range, RangeInclusive};
+use std::path::{Path, PathBuf};
+
+use crate::error::SynsError;
+use crate::options::Options;
+use crate::parser::parse_file;
+use crate::query::Query;
+
+/// A single match of a `Searcher`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchMatch {
+    /// Path of the matching file.
+    pub path: PathBuf,
+    /// Byte offsets of the match in the file.
+    pub span: Range<usize>,
+    /// First and last line of the match, starting from 1.
+    pub line_range: RangeInclusive<usize>,
+    /// Column of the first character on the first line and the column after the last character
+    /// on the last line, starting from 1.
+    pub column_range: (usize, usize),
+    /// Matched text.
+    pub text: String,
+    /// Text bound to each named metavariable.
+    pub captures: BTreeMap<String, String>,
+}
+
+/// Compiled query which can be run over files.
+///
+/// ```
+/// use syns::options::Options;
+/// use syns::search::Searcher;
+/// let options = Options::builder().language("javascript").build().unwrap();
+/// let searcher = Searcher::new("foo(\\.)", options).unwrap();
+/// let matches = searcher
+///     .search("a.js".as_ref(), "foo(1);\nfoo(2, 3);\nfoo(4);".as_bytes())
+///     .unwrap()
+///     .collect::<Vec<_>>();
+/// assert_eq!(matches.len(), 3);
+/// assert_eq!(matches[2].text, "foo(4)");
+/// assert_eq!(matches[2].line_range, 3..=3);
+/// ```
+#[derive(Debug)]
+pub struct Searcher {
+    query: Query,
+    options: Options,
+}
+
+impl Searcher {
+    /// Compile `query` using the language settings in `options`.
+    pub fn new(query: &str, options: Options) -> Result<Searcher, SynsError> {
+        Ok(Searcher {
+            query: Query::from_pattern(query, &options)?,
+            options,
+        })
+    }
+
+    /// Options used for parsing the searched files.
+    pub fn options(&self) -> &Options {
+        &self.options
+    }
+
+    /// Find all matches in `file`, reported as being in `path`, in source order.
+    pub fn search<R: Read>(
+        &self,
+        path: &Path,
+        mut file: R,
+    ) -> io::Result<impl Iterator<Item = SearchMatch>> {
+        let mut content = Vec::new();
+        file.read_to_end(&mut content)?;
+        if !self.query.may_match(&content) {
+            return Ok(Vec::new().into_iter());
+        }
+        let (ast, iter) = parse_file(content.as_slice(), &self.options);
+        let mut matches = self
+            .query
+            .matches(&ast)
+            .filter(|m| !m.t.is_empty())
+            .map(|m| {
+                let span = m.t[0].span().merge(&m.t.last().unwrap_or(&m.t[0]).span());
+                let (start_line, end_line) = iter.get_line_information(span);
+                let (start_column, end_column) = iter.get_column_information(span);
+                SearchMatch {
+                    path: path.to_path_buf(),
+                    span: span.lo..iter.get_span_end(span),
+                    line_range: start_line..=end_line,
+                    column_range: (start_column, end_column),
+                    text: iter.get_content_between(span),
+                    captures: m
+                        .captures
+                        .iter()
+                        .map(|(name, ast)| (name.clone(), iter.get_content_between(ast.span())))
+                        .collect(),
+                }
+            })
+            .collect::<Vec<_>>();
+        matches.sort_by_key(|m| (m.span.start, m.span.end));
+        Ok(matches.into_iter())
+    }
+
+    /// Find all matches in the file at `path`.
+    pub fn search_path(&self, path: &Path) -> io::Result<impl Iterator<Item = SearchMatch>> {
+        self.search(path, File::open(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(searcher: &Searcher, file: &str) -> Vec<SearchMatch> {
+        searcher
+            .search("test".as_ref(), file.as_bytes())
+            .expect("read")
+            .collect()
+    }
+
+    #[test]
+    fn structured_matches() {
+        let options = Options::builder()
+            .language("javascript")
+            .build()
+            .expect("options");
+        let searcher = Searcher::new(r"f(\:x, \.)", options).expect("query");
+        let file = "let a = f(1, 2);\nlet ü = f(\n  [3],\n  4);";
+        assert_eq!(
+            search(&searcher, file),
+            vec![
+                SearchMatch {
+                    path: "test".into(),
+                    span: 8..15,
+                    line_range: 1..=1,
+                    column_range: (9, 16),
+                    text: "f(1, 2)".to_string(),
+                    captures: collection!(("x".to_string(), "1".to_string())),
+                },
+                SearchMatch {
+                    path: "test".into(),
+                    span: 26..40,
+                    line_range: 2..=4,
+                    column_range: (9, 5),
+                    text: "f(\n  [3],\n  4)".to_string(),
+                    captures: collection!(("x".to_string(), "[3]".to_string())),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn builder_settings() {
+        let options = Options::builder()
+            .extension("sql")
+            .query("ignored")
+            .build()
+            .expect("options");
+        assert_eq!(options.language.as_deref(), Some("sql"));
+        assert_eq!(options.query, "ignored");
+        let searcher = Searcher::new("select \\.", options).expect("query");
+        assert_eq!(search(&searcher, "SELECT a FROM t").len(), 1);
+
+        let options = Options::builder()
+            .language("javascript")
+            .no_comment("//")
+            .comment("#")
+            .build()
+            .expect("options");
+        let searcher = Searcher::new("foo", options).expect("query");
+        assert_eq!(search(&searcher, "# foo\n// foo").len(), 1);
+        assert!(search(&searcher, "bar").is_empty());
+    }
+}
@@ -1,6 +1,7 @@
 //! Tokenizer for source files.
 
-use crate::options::Options;
+use crate::error::SynsError;
+use crate::options::{Options, RawString};
 use crate::psi::{PeekableStringIterator, Span};
 use crate::wrappers::Float;
 use std::convert::{TryFrom, TryInto};
@@ -22,10 +23,81 @@ pub enum SpecialTokenType {
     End,
     /// Match previous or next matcher
     Or,
-    /// Match string literals with regex.
-    Regex(String),
+    /// Match string literals with regex, and with the prefix if one is given.
+    Regex(Option<String>, String),
+    /// Match comments with regex, eg. `\#"nolint"`.
+    Comment(String),
+    /// Match identifiers or symbols, optionally with regex, eg. `\i"^get_"`.
+    Class(TokenClass, Option<String>),
+    /// Match numbers within the bounds, eg. `\n>1000`.
+    Number(Vec<NumberBound>),
     /// Grouped matchers.
     Nested(Vec<QueryToken>),
+    /// Named metavariable, eg. `\:name`.
+    Capture(String),
+    /// Match only if the next matcher doesn't match.
+    Not,
+    /// Match a block containing the next matcher at any depth.
+    Has,
+}
+
+/// Class of tokens matched by a typed wildcard.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TokenClass {
+    /// Identifiers, matched by `\i`.
+    Identifier,
+    /// Symbols, matched by `\sym`.
+    Symbol,
+}
+
+/// Comparison operator in a `NumberBound`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Comparison {
+    /// `<`
+    Less,
+    /// `<=`
+    LessOrEqual,
+    /// `>`
+    Greater,
+    /// `>=`
+    GreaterOrEqual,
+    /// `=`
+    Equal,
+}
+
+/// Constraint on the value of a number literal, eg. `>1000` in `\n>1000`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct NumberBound {
+    /// How the number is compared to `value`.
+    pub op: Comparison,
+    /// Value to compare to.
+    pub value: Float,
+}
+
+impl Comparison {
+    /// The operator as written in a query.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Comparison::Less => "<",
+            Comparison::LessOrEqual => "<=",
+            Comparison::Greater => ">",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::Equal => "=",
+        }
+    }
+}
+
+impl NumberBound {
+    /// Does `num` satisfy this bound?
+    pub fn contains(&self, num: f64) -> bool {
+        match self.op {
+            Comparison::Less => num < *self.value,
+            Comparison::LessOrEqual => num <= *self.value,
+            Comparison::Greater => num > *self.value,
+            Comparison::GreaterOrEqual => num >= *self.value,
+            Comparison::Equal => num == *self.value,
+        }
+    }
 }
 
 /// Stardard token types for source files.
@@ -38,12 +110,38 @@ pub enum StandardTokenType {
     Integer(i128),
     /// Floating point number, eg. 123.0
     Float(Float),
-    /// String literal, eg. "Hello"
-    StringLiteral(String),
+    /// String literal, eg. "Hello" or f"Hello"
+    StringLiteral {
+        /// Prefix of the literal, eg. "f", or the operator of a heredoc, eg. "<<".
+        prefix: String,
+        /// Content of the literal without delimiters.
+        content: String,
+    },
     /// Symbol, eg. +
     Symbol(String),
     /// Regex literal (without suffix flags), eg. /[a-z]/
     Regex(String),
+    /// Comment without its delimiters, eg. " TODO" for // TODO. Only matched by comment
+    /// matchers, other matchers skip comments.
+    Comment(String),
+    /// Start of a more indented line, see `Options::indent_blocks`.
+    Indent,
+    /// End of an indented block.
+    Dedent,
+}
+
+impl StandardTokenType {
+    /// Compare two tokens, ignoring the case of identifiers if `ignore_case` is set.
+    pub fn eq_ignoring_case(&self, other: &StandardTokenType, ignore_case: bool) -> bool {
+        match (self, other) {
+            (StandardTokenType::Identifier(a), StandardTokenType::Identifier(b)) if ignore_case => {
+                a.chars()
+                    .flat_map(char::to_lowercase)
+                    .eq(b.chars().flat_map(char::to_lowercase))
+            }
+            _ => self == other,
+        }
+    }
 }
 
 /// Query token type.
@@ -106,26 +204,119 @@ pub fn tokenize<R: Read>(
     }
     let buf = String::from_utf8_lossy(&file_buf).to_string();
     let mut iter = PeekableStringIterator::new(filename.to_string(), buf);
+    // Only query commands can fail, and there are none in source files
     let res = tokenize_recur(&mut iter, options, false, false)
+        .unwrap_or_default()
         .into_iter()
         .map(|t| t.try_into().expect("Unreachable"))
         .collect();
-    (res, iter)
+    if options.indent_blocks {
+        (indent_tokens(res, &iter, options), iter)
+    } else {
+        (res, iter)
+    }
+}
+
+/// Width of the indentation `s`, with tabs aligned to multiples of 8.
+fn indent_width(s: &str) -> usize {
+    s.chars().fold(0, |width, c| {
+        if c == '\t' {
+            width / 8 * 8 + 8
+        } else {
+            width + 1
+        }
+    })
+}
+
+/// Insert `Indent` and `Dedent` tokens around lines which are indented more than the line before
+/// them, like Python's INDENT and DEDENT. Lines inside blocks or continued with a backslash
+/// don't change the indentation level.
+fn indent_tokens(
+    tokens: Vec<StandardToken>,
+    iter: &PeekableStringIterator,
+    options: &Options,
+) -> Vec<StandardToken> {
+    let content = iter.content();
+    let mut res: Vec<StandardToken> = Vec::with_capacity(tokens.len());
+    let mut levels: Vec<usize> = Vec::new();
+    let mut depth = 0usize;
+    let mut last_span = None;
+    for token in tokens {
+        // Comments don't affect the indentation
+        if let StandardTokenType::Comment(_) = token.ty {
+            res.push(token);
+            continue;
+        }
+        let prev_end = last_span.map_or(0, |span| iter.get_span_end(span));
+        let starts_line = last_span.is_none() || content[prev_end..token.span.lo].contains('\n');
+        let continued = matches!(
+            res.last(),
+            Some(StandardToken { ty: StandardTokenType::Symbol(s), .. }) if s.ends_with('\\')
+        );
+        if starts_line && depth == 0 && !continued {
+            let line_start = content[..token.span.lo].rfind('\n').map_or(0, |i| i + 1);
+            let indent = indent_width(&content[line_start..token.span.lo]);
+            let dedent_span = last_span.map(|span: Span| Span {
+                lo: span.hi,
+                hi: span.hi,
+            });
+            while levels.len() > 1 && levels.last().is_some_and(|level| indent < *level) {
+                levels.pop();
+                res.push(StandardToken {
+                    ty: StandardTokenType::Dedent,
+                    span: dedent_span.unwrap_or(token.span),
+                });
+            }
+            if levels.last().is_none_or(|level| indent > *level) {
+                if !levels.is_empty() {
+                    res.push(StandardToken {
+                        ty: StandardTokenType::Indent,
+                        span: Span {
+                            lo: token.span.lo,
+                            hi: token.span.lo,
+                        },
+                    });
+                }
+                levels.push(indent);
+            }
+        }
+        if let StandardTokenType::Symbol(s) = &token.ty {
+            if options.is_open_paren(s) {
+                depth += 1;
+            } else if options.is_close_paren(s) {
+                depth = depth.saturating_sub(1);
+            }
+        }
+        last_span = Some(token.span);
+        res.push(token);
+    }
+    if let Some(span) = last_span {
+        for _ in 1..levels.len() {
+            res.push(StandardToken {
+                ty: StandardTokenType::Dedent,
+                span: Span {
+                    lo: span.hi,
+                    hi: span.hi,
+                },
+            });
+        }
+    }
+    res
 }
 
 /// Tokenize a query string.
 pub fn tokenize_query<R: Read>(
     mut content: R,
     options: &Options,
-) -> (Vec<QueryToken>, PeekableStringIterator) {
+) -> Result<(Vec<QueryToken>, PeekableStringIterator), SynsError> {
     let mut file_buf = vec![];
     content
         .read_to_end(&mut file_buf)
-        .expect("Failed to read file to memory");
+        .map_err(|e| SynsError::Io(e.to_string()))?;
     let buf = String::from_utf8_lossy(&file_buf).to_string();
     let mut iter = PeekableStringIterator::new("<query>".to_string(), buf);
-    let res = tokenize_recur(&mut iter, options, false, true);
-    (res, iter)
+    let res = tokenize_recur(&mut iter, options, false, true)?;
+    Ok((res, iter))
 }
 
 /// Given the token history, can we parse a regex literal?
@@ -135,7 +326,12 @@ pub fn tokenize_query<R: Read>(
 /// false. Almost everything looks like an expression in JavaScript, the regex parsing can only
 /// happen either opening parens, closed blocks, or operators.
 fn can_parse_regex(history: &[QueryToken]) -> bool {
-    let ty = match history.last() {
+    let ty = match history.iter().rev().find(|t| {
+        !matches!(
+            t.ty,
+            QueryTokenType::Standard(StandardTokenType::Comment(_))
+        )
+    }) {
         None => return true,
         Some(QueryToken {
             ty: QueryTokenType::Special(_),
@@ -156,96 +352,152 @@ fn can_parse_regex(history: &[QueryToken]) -> bool {
     sym != ")"
 }
 
-/// Generate tokens from a PeekableStringIterator.
+/// Generate tokens from a PeekableStringIterator. Fails only on invalid query commands.
 pub fn tokenize_recur(
     iter: &mut PeekableStringIterator,
     options: &Options,
     recur: bool,
     is_query: bool,
-) -> Vec<QueryToken> {
+) -> Result<Vec<QueryToken>, SynsError> {
     let mut res = Vec::new();
     let mut had_whitespace = false;
+    // Lengths of heredoc bodies starting on the next line
+    let mut heredocs: Vec<usize> = Vec::new();
     while let Some(c) = iter.peek() {
-        if options
+        if let Some(start) = options
             .single_line_comments
             .iter()
-            .any(|c| iter.starts_with(c))
+            .find(|c| iter.starts_with(c))
         {
-            flush_single_line(iter);
+            let comment = read_single_line_comment(iter, start);
+            if !is_query {
+                res.push(comment);
+            }
             had_whitespace = true;
             continue;
         }
-        if let Some((start, end)) = options
+        if let Some(pair) = options
             .multi_line_comments
             .iter()
             .find(|(start, _)| iter.starts_with(start))
         {
-            flush_multi_line_comment(iter, start, end);
+            let nested = options.nested_comments.contains(pair);
+            let comment = read_multi_line_comment(iter, &pair.0, &pair.1, nested);
+            if !is_query {
+                res.push(comment);
+            }
             had_whitespace = true;
             continue;
         }
+        if let Some(start) = string_start(iter, options, is_query) {
+            res.push(read_string(iter, start, &mut heredocs));
+            had_whitespace = false;
+            continue;
+        }
         let token = match c {
-            _ if options
-                .string_characters
-                .iter()
-                .any(|c| iter.starts_with(c)) =>
-            {
-                read_string(iter)
-            }
             '\\' if is_query => {
                 assert_eq!(iter.next_new_span(), Some('\\'));
                 if recur && iter.peek() == Some(')') {
                     break;
                 }
-                read_query_command(iter, options)
+                read_query_command(iter, options)?
             }
             _ if can_parse_regex(&res)
                 && options.regex_delimiters.iter().any(|c| iter.starts_with(c)) =>
             {
                 read_regex(iter)
             }
+            '\n' if !heredocs.is_empty() => {
+                iter.next();
+                for _ in 0..heredocs.drain(..).sum() {
+                    iter.next();
+                }
+                had_whitespace = true;
+                continue;
+            }
             ' ' | '\t' | '\n' => {
                 iter.next();
                 had_whitespace = true;
                 continue;
             }
             c if options.identifier_regex_start.is_match(&c.to_string()) => {
-                read_identifier(iter, options)
+                let token = read_identifier(iter, options);
+                if let QueryTokenType::Standard(StandardTokenType::Symbol(_)) = token.ty {
+                    // Block keywords are separate tokens, like parens
+                    res.push(token);
+                    had_whitespace = true;
+                    continue;
+                }
+                token
             }
             '0'..='9' => read_number(iter, options),
             c if options.is_open_paren(&c.to_string())
                 || options.is_close_paren(&c.to_string()) =>
             {
-                res.push(read_paren(iter));
+                res.push(read_paren(iter, c));
                 had_whitespace = true;
                 continue;
             }
-            _ => read_other(&mut res, had_whitespace, iter),
+            _ => read_other(&mut res, had_whitespace, iter, c),
         };
         had_whitespace = false;
         res.push(token);
     }
-    res
+    Ok(res)
 }
 
-fn flush_single_line(iter: &mut PeekableStringIterator) {
-    iter.collect_while(|x| x != '\n');
+fn comment_token(content: String, iter: &PeekableStringIterator) -> QueryToken {
+    QueryToken {
+        ty: QueryTokenType::Standard(StandardTokenType::Comment(content)),
+        span: iter.current_span(),
+    }
 }
 
-fn flush_multi_line_comment(iter: &mut PeekableStringIterator, start: &str, end: &str) {
-    for c in start.chars() {
-        assert_eq!(Some(c), iter.next());
+fn read_single_line_comment(iter: &mut PeekableStringIterator, start: &str) -> QueryToken {
+    skip_new_span(iter, start.chars().count());
+    let mut content = String::new();
+    while let Some(c) = iter.peek().filter(|c| *c != '\n') {
+        content.push(c);
+        iter.next();
     }
-    while !iter.starts_with(end) {
-        if iter.next().is_none() {
-            break;
+    comment_token(content, iter)
+}
+
+fn read_multi_line_comment(
+    iter: &mut PeekableStringIterator,
+    start: &str,
+    end: &str,
+    nested: bool,
+) -> QueryToken {
+    skip_new_span(iter, start.chars().count());
+    let mut content = String::new();
+    let mut depth = 1;
+    loop {
+        if iter.starts_with(end) {
+            depth -= 1;
+            if depth == 0 {
+                break;
+            }
+            content.push_str(end);
+            for _ in end.chars() {
+                iter.next();
+            }
+        } else if nested && iter.starts_with(start) {
+            depth += 1;
+            content.push_str(start);
+            for _ in start.chars() {
+                iter.next();
+            }
+        } else if let Some(c) = iter.next() {
+            content.push(c);
+        } else {
+            return comment_token(content, iter);
         }
     }
-    for c in end.chars() {
-        if let Some(other_c) = iter.next() {
-            assert_eq!(c, other_c);
-        }
+    for _ in end.chars() {
+        iter.next();
     }
+    comment_token(content, iter)
 }
 
 fn read_number(iter: &mut PeekableStringIterator, options: &Options) -> QueryToken {
@@ -344,10 +596,267 @@ fn read_string_content(iter: &mut PeekableStringIterator) -> String {
     content
 }
 
-fn read_string(iter: &mut PeekableStringIterator) -> QueryToken {
-    let content = read_string_content(iter);
+/// How a string literal at the current position starts.
+enum StringStart {
+    /// `prefix` followed by `delimiter`, which also ends the literal.
+    Delimited { prefix: String, delimiter: String },
+    /// Raw string literal with `prefix`.
+    Raw { prefix: String, style: RawString },
+    /// `prefix` followed by `\"regex"` in a query.
+    Regex { prefix: String },
+    /// Heredoc started by `operator`, see `heredoc_at`.
+    Heredoc {
+        operator: String,
+        content: String,
+        /// Length of the operator and terminator word in characters.
+        len: usize,
+        /// Length of the lines from the start of the next line up to the terminator.
+        body_len: usize,
+    },
+}
+
+/// Does a raw string with `style` start at `rest`, right after its prefix?
+fn starts_raw_string(style: RawString, rest: &str) -> bool {
+    match style {
+        RawString::Hashes => rest.trim_start_matches('#').starts_with('"'),
+        RawString::Parens => rest.strip_prefix('"').is_some_and(|rest| {
+            // The delimiter is at most 16 characters long
+            rest.find('(').is_some_and(|i| {
+                i <= 16
+                    && !rest[..i]
+                        .contains(|c: char| matches!(c, ')' | '\\' | '"') || c.is_whitespace())
+            })
+        }),
+        RawString::Doubled => rest.starts_with('"'),
+    }
+}
+
+/// Find the string literal starting at the current position, if any. The longest matching
+/// prefix and delimiter win.
+fn string_start(
+    iter: &PeekableStringIterator,
+    options: &Options,
+    is_query: bool,
+) -> Option<StringStart> {
+    iter.with_rest(|rest| {
+        if let Some(heredoc) = options
+            .heredocs
+            .iter()
+            .filter_map(|operator| heredoc_at(rest, operator, options.heredoc_spaces))
+            .max_by_key(|heredoc| heredoc.0.len())
+        {
+            let (operator, content, len, body_len) = heredoc;
+            return Some(StringStart::Heredoc {
+                operator: operator.to_string(),
+                content,
+                len,
+                body_len,
+            });
+        }
+        if let Some((prefix, style)) = options
+            .raw_strings
+            .iter()
+            .filter(|(prefix, style)| {
+                rest.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| starts_raw_string(**style, rest))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+        {
+            return Some(StringStart::Raw {
+                prefix: prefix.clone(),
+                style: *style,
+            });
+        }
+        std::iter::once("")
+            .chain(options.string_prefixes.iter().map(String::as_str))
+            .filter_map(|prefix| {
+                let after = rest.strip_prefix(prefix)?;
+                if is_query && !prefix.is_empty() && after.starts_with("\\\"") {
+                    return Some((prefix, None));
+                }
+                let delimiter = options
+                    .string_characters
+                    .iter()
+                    .filter(|delimiter| after.starts_with(delimiter.as_str()))
+                    .max_by_key(|delimiter| delimiter.len())?;
+                Some((prefix, Some(delimiter)))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(prefix, delimiter)| match delimiter {
+                Some(delimiter) => StringStart::Delimited {
+                    prefix: prefix.to_string(),
+                    delimiter: delimiter.clone(),
+                },
+                None => StringStart::Regex {
+                    prefix: prefix.to_string(),
+                },
+            })
+    })
+}
+
+/// Find a heredoc started by `operator` at the start of `rest`, eg. `<<EOF` or `<<'EOF'`,
+/// followed by the rest of the line and the lines of the body up to one starting with the
+/// terminator `EOF`. Spaces may separate the operator and the terminator word if `spaces` is
+/// set. The terminator may be indented unless `operator` is plain `<<`. Returns the operator,
+/// the body, the length of the operator and terminator and the length of the body including the
+/// terminator, in characters.
+fn heredoc_at<'a>(
+    rest: &str,
+    operator: &'a str,
+    spaces: bool,
+) -> Option<(&'a str, String, usize, usize)> {
+    let after = rest.strip_prefix(operator)?;
+    let after = if spaces {
+        after.trim_start_matches([' ', '\t'])
+    } else {
+        after
+    };
+    let (quote, word) = match after.chars().next()? {
+        quote @ ('\'' | '"') => (Some(quote), &after[1..]),
+        _ => (None, after),
+    };
+    if !word.starts_with(|c: char| c.is_alphabetic() || c == '_') {
+        return None;
+    }
+    let word = &word[..word
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(word.len())];
+    let mut end = rest.len() - after.len() + quote.map_or(0, |_| 1) + word.len();
+    if let Some(quote) = quote {
+        if !rest[end..].starts_with(quote) {
+            return None;
+        }
+        end += 1;
+    }
+    let body = &rest[end + rest[end..].find('\n')? + 1..];
+    let mut pos = 0;
+    for line in body.split_inclusive('\n') {
+        let indented = match operator {
+            "<<" => line,
+            _ => line.trim_start_matches([' ', '\t']),
+        };
+        if indented
+            .strip_prefix(word)
+            .is_some_and(|after| !after.starts_with(|c: char| c.is_alphanumeric() || c == '_'))
+        {
+            let content = body[..pos].strip_suffix('\n').unwrap_or(&body[..pos]);
+            let body_len = body[..pos].chars().count() + line.len() - indented.len();
+            return Some((
+                operator,
+                content.to_string(),
+                rest[..end].chars().count(),
+                body_len + word.chars().count(),
+            ));
+        }
+        pos += line.len();
+    }
+    None
+}
+
+/// Consume `n` characters, starting a new span at the first one.
+fn skip_new_span(iter: &mut PeekableStringIterator, n: usize) {
+    for i in 0..n {
+        if i == 0 {
+            iter.next_new_span();
+        } else {
+            iter.next();
+        }
+    }
+}
+
+/// Read characters up to `end`, which is consumed but not included.
+fn read_until(iter: &mut PeekableStringIterator, end: &str, escapes: bool) -> String {
+    let mut content = String::new();
+    while !iter.starts_with(end) {
+        match iter.next() {
+            Some('\\') if escapes => {
+                content.push('\\');
+                if let Some(c) = iter.next() {
+                    content.push(c);
+                }
+            }
+            Some(c) => content.push(c),
+            None => return content,
+        }
+    }
+    for _ in end.chars() {
+        iter.next();
+    }
+    content
+}
+
+fn read_string(
+    iter: &mut PeekableStringIterator,
+    start: StringStart,
+    heredocs: &mut Vec<usize>,
+) -> QueryToken {
+    let (prefix, content) = match start {
+        StringStart::Delimited { prefix, delimiter } => {
+            skip_new_span(iter, prefix.chars().count() + delimiter.chars().count());
+            let content = read_until(iter, &delimiter, true);
+            (prefix, content)
+        }
+        StringStart::Raw { prefix, style } => {
+            skip_new_span(iter, prefix.chars().count());
+            let content = match style {
+                RawString::Hashes => {
+                    let mut hashes = String::new();
+                    while let Some('#') = iter.next() {
+                        hashes.push('#');
+                    }
+                    read_until(iter, &format!("\"{}", hashes), false)
+                }
+                RawString::Parens => {
+                    iter.next();
+                    let mut delimiter = String::new();
+                    while let Some(c) = iter.next().filter(|c| *c != '(') {
+                        delimiter.push(c);
+                    }
+                    read_until(iter, &format!("){}\"", delimiter), false)
+                }
+                RawString::Doubled => {
+                    iter.next();
+                    let mut content = String::new();
+                    loop {
+                        match iter.next() {
+                            Some('"') if iter.peek() == Some('"') => {
+                                iter.next();
+                                content.push_str("\"\"");
+                            }
+                            Some('"') | None => break,
+                            Some(c) => content.push(c),
+                        }
+                    }
+                    content
+                }
+            };
+            (prefix, content)
+        }
+        StringStart::Regex { prefix } => {
+            skip_new_span(iter, prefix.chars().count() + 1);
+            let lo = iter.current_span().lo;
+            let content = read_string_content(iter);
+            return QueryToken {
+                ty: QueryTokenType::Special(SpecialTokenType::Regex(Some(prefix), content)),
+                span: Span {
+                    lo,
+                    hi: iter.current_span().hi,
+                },
+            };
+        }
+        StringStart::Heredoc {
+            operator,
+            content,
+            len,
+            body_len,
+        } => {
+            skip_new_span(iter, len);
+            heredocs.push(body_len);
+            (operator, content)
+        }
+    };
     QueryToken {
-        ty: QueryTokenType::Standard(StandardTokenType::StringLiteral(content)),
+        ty: QueryTokenType::Standard(StandardTokenType::StringLiteral { prefix, content }),
         span: iter.current_span(),
     }
 }
@@ -371,11 +880,11 @@ fn read_identifier(iter: &mut PeekableStringIterator, options: &Options) -> Quer
         }
     });
 
-    if options.is_open_paren(&content) || options.is_close_paren(&content) {
+    if let Some(keyword) = read_block_keyword(iter, options, &content) {
         /* handle eg. do / end -style "parens" */
         QueryToken {
-            ty: QueryTokenType::Standard(StandardTokenType::Symbol(content)),
-            span,
+            ty: QueryTokenType::Standard(StandardTokenType::Symbol(keyword)),
+            span: iter.current_span(),
         }
     } else {
         QueryToken {
@@ -385,98 +894,267 @@ fn read_identifier(iter: &mut PeekableStringIterator, options: &Options) -> Quer
     }
 }
 
-fn read_paren(iter: &mut PeekableStringIterator) -> QueryToken {
-    match iter.next_new_span() {
-        Some(c) => QueryToken {
-            ty: QueryTokenType::Standard(StandardTokenType::Symbol(c.to_string())),
-            span: iter.current_span(),
-        },
-        None => panic!("Unexpected end of file"),
+/// Read the rest of the longest block keyword starting with the identifier `word`, eg. " Sub"
+/// after "End". Returns the keyword as spelled in the options, or None if there isn't one.
+fn read_block_keyword(
+    iter: &mut PeekableStringIterator,
+    options: &Options,
+    word: &str,
+) -> Option<String> {
+    // Keyword arguments such as `do: x` in Elixir and Ruby don't open a block
+    if iter.peek() == Some(':') && !iter.starts_with("::") {
+        return None;
+    }
+    let (keyword, len) = options
+        .block_keywords()
+        .filter_map(|keyword| {
+            let mut words = keyword.split_whitespace();
+            if !options.block_keyword_eq(words.next()?, word) {
+                return None;
+            }
+            let len = iter.with_rest(|rest| match_keyword_words(options, rest, words))?;
+            Some((keyword, len))
+        })
+        .max_by_key(|(_, len)| *len)?;
+    for _ in 0..len {
+        iter.next();
     }
+    Some(keyword.clone())
 }
 
+/// Match `words`, each preceded by spaces or tabs, at the start of `rest`. Returns the number of
+/// characters matched.
+fn match_keyword_words<'a>(
+    options: &Options,
+    rest: &str,
+    words: impl Iterator<Item = &'a str>,
+) -> Option<usize> {
+    let mut pos = 0;
+    for word in words {
+        let after_space = rest[pos..].trim_start_matches([' ', '\t']);
+        if after_space.len() == rest[pos..].len() {
+            return None;
+        }
+        pos = rest.len() - after_space.len();
+        if !options.block_keyword_eq(after_space.get(..word.len())?, word) {
+            return None;
+        }
+        pos += word.len();
+    }
+    match rest[pos..].chars().next() {
+        Some(c) if options.identifier_regex_continue.is_match(&c.to_string()) => None,
+        _ => Some(rest[..pos].chars().count()),
+    }
+}
+
+/// Read the paren `c`, which is the next character of `iter`.
+fn read_paren(iter: &mut PeekableStringIterator, c: char) -> QueryToken {
+    iter.next_new_span();
+    QueryToken {
+        ty: QueryTokenType::Standard(StandardTokenType::Symbol(c.to_string())),
+        span: iter.current_span(),
+    }
+}
+
+/// Read the symbol `c`, which is the next character of `iter`, gluing it to the previous symbol
+/// unless there was whitespace between them.
 fn read_other(
     res: &mut Vec<QueryToken>,
     had_whitespace: bool,
     iter: &mut PeekableStringIterator,
+    c: char,
 ) -> QueryToken {
-    match iter.next_new_span() {
-        Some(c) => {
-            // We want to parse eg. ++ as a single token, but parse
-            // "Foo<T>;" as ["Foo", "<", "T", ">", ";"].
-            // In theory, this would require language-specific operator lists, but
-            // we can get quite far by just checking for comma and semicolon.
-            if !had_whitespace && c != ',' && c != ';' {
-                if let Some(QueryToken {
-                    ty: QueryTokenType::Standard(StandardTokenType::Symbol(old_c)),
-                    span,
-                }) = res.last()
-                {
-                    if old_c == "," || old_c == ";" {
-                        return QueryToken {
-                            ty: QueryTokenType::Standard(StandardTokenType::Symbol(c.to_string())),
-                            span: iter.current_span(),
-                        };
-                    }
-                    let new_symbol = format!("{}{}", old_c, c);
-                    let new_span = span.merge(&iter.current_span());
-                    res.pop();
-                    QueryToken {
-                        ty: QueryTokenType::Standard(StandardTokenType::Symbol(new_symbol)),
-                        span: new_span,
-                    }
-                } else {
-                    QueryToken {
-                        ty: QueryTokenType::Standard(StandardTokenType::Symbol(c.to_string())),
-                        span: iter.current_span(),
-                    }
-                }
-            } else {
-                QueryToken {
+    iter.next_new_span();
+    // We want to parse eg. ++ as a single token, but parse
+    // "Foo<T>;" as ["Foo", "<", "T", ">", ";"].
+    // In theory, this would require language-specific operator lists, but
+    // we can get quite far by just checking for comma and semicolon.
+    if !had_whitespace && c != ',' && c != ';' {
+        if let Some(QueryToken {
+            ty: QueryTokenType::Standard(StandardTokenType::Symbol(old_c)),
+            span,
+        }) = res.last()
+        {
+            if old_c == "," || old_c == ";" {
+                return QueryToken {
                     ty: QueryTokenType::Standard(StandardTokenType::Symbol(c.to_string())),
                     span: iter.current_span(),
-                }
+                };
             }
+            let new_symbol = format!("{}{}", old_c, c);
+            let new_span = span.merge(&iter.current_span());
+            res.pop();
+            QueryToken {
+                ty: QueryTokenType::Standard(StandardTokenType::Symbol(new_symbol)),
+                span: new_span,
+            }
+        } else {
+            QueryToken {
+                ty: QueryTokenType::Standard(StandardTokenType::Symbol(c.to_string())),
+                span: iter.current_span(),
+            }
+        }
+    } else {
+        QueryToken {
+            ty: QueryTokenType::Standard(StandardTokenType::Symbol(c.to_string())),
+            span: iter.current_span(),
         }
-        None => panic!("Unexpected end of file"),
     }
 }
 
-fn read_query_command(iter: &mut PeekableStringIterator, options: &Options) -> QueryToken {
-    let t = match iter.peek().expect("Unexpected end of query string") {
+fn read_query_command(
+    iter: &mut PeekableStringIterator,
+    options: &Options,
+) -> Result<QueryToken, SynsError> {
+    let Some(c) = iter.peek() else {
+        return Err(SynsError::UnexpectedEnd {
+            span: iter.current_span(),
+        });
+    };
+    let t = match c {
         '.' => QueryTokenType::Special(SpecialTokenType::Any),
         '*' => QueryTokenType::Special(SpecialTokenType::Star),
         '+' => QueryTokenType::Special(SpecialTokenType::Plus),
         '?' => QueryTokenType::Special(SpecialTokenType::QuestionMark),
         '|' => QueryTokenType::Special(SpecialTokenType::Or),
         '$' => QueryTokenType::Special(SpecialTokenType::End),
+        '!' => QueryTokenType::Special(SpecialTokenType::Not),
         '"' => {
-            let ty = QueryTokenType::Special(SpecialTokenType::Regex(read_string_content(iter)));
-            return QueryToken {
+            let ty =
+                QueryTokenType::Special(SpecialTokenType::Regex(None, read_string_content(iter)));
+            return Ok(QueryToken {
                 ty,
                 span: iter.current_span(),
-            };
+            });
+        }
+        '#' if iter.peek_n(2) == "#\"" => {
+            iter.next();
+            let ty = QueryTokenType::Special(SpecialTokenType::Comment(read_string_content(iter)));
+            return Ok(QueryToken {
+                ty,
+                span: iter.current_span(),
+            });
         }
         '(' => {
             assert_eq!(iter.next(), Some('('));
             let tts = QueryTokenType::Special(SpecialTokenType::Nested(tokenize_recur(
                 iter, options, true, true,
-            )));
+            )?));
             if iter.peek() == Some(')') {
                 iter.next();
             }
-            return QueryToken {
+            return Ok(QueryToken {
                 ty: tts,
                 span: iter.current_span(),
+            });
+        }
+        ':' => {
+            assert_eq!(iter.next(), Some(':'));
+            let mut name = String::new();
+            while let Some(c) = iter.peek() {
+                if !c.is_alphanumeric() && c != '_' {
+                    break;
+                }
+                name.push(c);
+                iter.next();
+            }
+            if name.is_empty() {
+                return Err(SynsError::MissingCaptureName {
+                    span: iter.current_span(),
+                });
+            }
+            return Ok(QueryToken {
+                ty: QueryTokenType::Special(SpecialTokenType::Capture(name)),
+                span: iter.current_span(),
+            });
+        }
+        'a'..='z' => {
+            let mut name = String::new();
+            while let Some(c @ 'a'..='z') = iter.peek() {
+                name.push(c);
+                iter.next();
+            }
+            let mut regex = || (iter.peek() == Some('"')).then(|| read_string_content(iter));
+            let ty = match name.as_str() {
+                "has" => QueryTokenType::Special(SpecialTokenType::Has),
+                "i" => QueryTokenType::Special(SpecialTokenType::Class(
+                    TokenClass::Identifier,
+                    regex(),
+                )),
+                "sym" => {
+                    QueryTokenType::Special(SpecialTokenType::Class(TokenClass::Symbol, regex()))
+                }
+                "s" => QueryTokenType::Special(SpecialTokenType::Regex(
+                    None,
+                    regex().unwrap_or_default(),
+                )),
+                "n" => QueryTokenType::Special(SpecialTokenType::Number(read_number_bounds(iter)?)),
+                _ => {
+                    return Err(SynsError::UnknownCommand {
+                        name,
+                        span: iter.current_span(),
+                    })
+                }
             };
+            return Ok(QueryToken {
+                ty,
+                span: iter.current_span(),
+            });
+        }
+        c => {
+            iter.next();
+            return Err(SynsError::UnknownCommand {
+                name: c.to_string(),
+                span: iter.current_span(),
+            });
         }
-        c => panic!("Unimplemented query command: {}", c),
     };
     iter.next();
-    QueryToken {
+    Ok(QueryToken {
         ty: t,
         span: iter.current_span(),
+    })
+}
+
+/// Read the bounds after `\n`, eg. `>=1<10`.
+fn read_number_bounds(iter: &mut PeekableStringIterator) -> Result<Vec<NumberBound>, SynsError> {
+    let mut bounds = Vec::new();
+    while let Some(c @ ('<' | '>' | '=')) = iter.peek() {
+        iter.next();
+        let op = match (c, iter.peek()) {
+            ('<', Some('=')) => Comparison::LessOrEqual,
+            ('>', Some('=')) => Comparison::GreaterOrEqual,
+            ('<', _) => Comparison::Less,
+            ('>', _) => Comparison::Greater,
+            _ => Comparison::Equal,
+        };
+        if matches!(op, Comparison::LessOrEqual | Comparison::GreaterOrEqual) {
+            iter.next();
+        }
+        let mut value = String::new();
+        while let Some(c) = iter
+            .peek()
+            .filter(|c| c.is_ascii_digit() || ".-_e".contains(*c))
+        {
+            if c != '_' {
+                value.push(c);
+            }
+            iter.next();
+        }
+        match f64::from_str(&value) {
+            Ok(value) => bounds.push(NumberBound {
+                op,
+                value: value.into(),
+            }),
+            Err(_) => {
+                return Err(SynsError::InvalidNumber {
+                    value,
+                    span: iter.current_span(),
+                })
+            }
+        }
     }
+    Ok(bounds)
 }
 
 #[cfg(test)]
@@ -489,6 +1167,12 @@ mod tests {
             span: Span { lo, hi },
         }
     }
+    fn string(content: &str) -> StandardTokenType {
+        StandardTokenType::StringLiteral {
+            prefix: String::new(),
+            content: content.to_string(),
+        }
+    }
     fn q(ty: QueryTokenType, lo: usize, hi: usize) -> QueryToken {
         QueryToken {
             ty,
@@ -509,7 +1193,7 @@ mod tests {
     }
 
     fn test_query(input: &str, expected: Vec<QueryToken>, options: Options) {
-        let (tokens, _) = tokenize_query(input.as_bytes(), &options);
+        let (tokens, _) = tokenize_query(input.as_bytes(), &options).expect("query");
         assert_eq!(
             tokens.iter().map(|t| &t.ty).collect::<Vec<_>>(),
             expected.iter().map(|t| &t.ty).collect::<Vec<_>>()
@@ -524,10 +1208,38 @@ mod tests {
         test_file(
             input,
             expected,
-            Options::new("js".as_ref(), &["syns", "foo", "foo"]),
+            Options::new("js".as_ref(), &["syns", "foo", "foo"]).expect("options"),
         )
     }
 
+    #[test]
+    fn indent_tokens() {
+        test_file(
+            "if a:\n    b(\n  c)\n    d\ne",
+            vec![
+                t(StandardTokenType::Identifier("if".to_string()), 0, 1),
+                t(StandardTokenType::Identifier("a".to_string()), 3, 3),
+                t(StandardTokenType::Symbol(":".to_string()), 4, 4),
+                t(StandardTokenType::Indent, 10, 10),
+                t(StandardTokenType::Identifier("b".to_string()), 10, 10),
+                t(StandardTokenType::Symbol("(".to_string()), 11, 11),
+                t(StandardTokenType::Identifier("c".to_string()), 15, 15),
+                t(StandardTokenType::Symbol(")".to_string()), 16, 16),
+                t(StandardTokenType::Identifier("d".to_string()), 22, 22),
+                t(StandardTokenType::Dedent, 22, 22),
+                t(StandardTokenType::Identifier("e".to_string()), 24, 24),
+            ],
+            Options::new("py".as_ref(), &["syns", "foo", "foo"]).expect("options"),
+        );
+    }
+
+    #[test]
+    fn indent_width() {
+        assert_eq!(super::indent_width("    "), 4);
+        assert_eq!(super::indent_width("\t"), 8);
+        assert_eq!(super::indent_width("  \t "), 9);
+    }
+
     #[test]
     fn simple_tokens() {
         test(
@@ -535,7 +1247,7 @@ mod tests {
             vec![
                 t(StandardTokenType::Identifier("foo".to_string()), 0, 2),
                 t(StandardTokenType::Integer(123), 4, 6),
-                t(StandardTokenType::StringLiteral("bar".to_string()), 8, 12),
+                t(string("bar"), 8, 12),
             ],
         );
     }
@@ -546,12 +1258,70 @@ mod tests {
             "foo /* bar */ baz\ngux //baz",
             vec![
                 t(StandardTokenType::Identifier("foo".to_string()), 0, 2),
+                t(StandardTokenType::Comment(" bar ".to_string()), 4, 12),
                 t(StandardTokenType::Identifier("baz".to_string()), 14, 16),
                 t(StandardTokenType::Identifier("gux".to_string()), 18, 20),
+                t(StandardTokenType::Comment("baz".to_string()), 22, 26),
             ],
         );
     }
 
+    #[test]
+    fn nested_comments() {
+        let input = "a /* b /* c */ d */ e";
+        test_file(
+            input,
+            vec![
+                t(StandardTokenType::Identifier("a".to_string()), 0, 0),
+                t(StandardTokenType::Comment(" b /* c ".to_string()), 2, 13),
+                t(StandardTokenType::Identifier("d".to_string()), 15, 15),
+                t(StandardTokenType::Symbol("*/".to_string()), 17, 18),
+                t(StandardTokenType::Identifier("e".to_string()), 20, 20),
+            ],
+            Options::new("c".as_ref(), &["syns", "foo", "foo"]).expect("options"),
+        );
+        test_file(
+            input,
+            vec![
+                t(StandardTokenType::Identifier("a".to_string()), 0, 0),
+                t(
+                    StandardTokenType::Comment(" b /* c */ d ".to_string()),
+                    2,
+                    18,
+                ),
+                t(StandardTokenType::Identifier("e".to_string()), 20, 20),
+            ],
+            Options::new("rs".as_ref(), &["syns", "foo", "foo"]).expect("options"),
+        );
+        test_file(
+            "(* a (* b *) *) c",
+            vec![
+                t(StandardTokenType::Comment(" a (* b *) ".to_string()), 0, 14),
+                t(StandardTokenType::Identifier("c".to_string()), 16, 16),
+            ],
+            Options::new("ml".as_ref(), &["syns", "foo", "foo"]).expect("options"),
+        );
+        // D only nests /+ +/
+        test_file(
+            "int a; /* x /* y */\nint b; /+ c /+ d +/ +/",
+            vec![
+                t(StandardTokenType::Identifier("int".to_string()), 0, 2),
+                t(StandardTokenType::Identifier("a".to_string()), 4, 4),
+                t(StandardTokenType::Symbol(";".to_string()), 5, 5),
+                t(StandardTokenType::Comment(" x /* y ".to_string()), 7, 18),
+                t(StandardTokenType::Identifier("int".to_string()), 20, 22),
+                t(StandardTokenType::Identifier("b".to_string()), 24, 24),
+                t(StandardTokenType::Symbol(";".to_string()), 25, 25),
+                t(
+                    StandardTokenType::Comment(" c /+ d +/ ".to_string()),
+                    27,
+                    41,
+                ),
+            ],
+            Options::new("d".as_ref(), &["syns", "foo", "foo"]).expect("options"),
+        );
+    }
+
     #[test]
     fn numbers() {
         test(
@@ -606,46 +1376,113 @@ mod tests {
         test(
             r#""foo" "bar\"" 'baz\''"#,
             vec![
-                t(StandardTokenType::StringLiteral("foo".to_string()), 0, 4),
-                t(
-                    StandardTokenType::StringLiteral("bar\\\"".to_string()),
-                    6,
-                    12,
-                ),
-                t(
-                    StandardTokenType::StringLiteral("baz\\'".to_string()),
-                    14,
-                    20,
-                ),
+                t(string("foo"), 0, 4),
+                t(string("bar\\\""), 6, 12),
+                t(string("baz\\'"), 14, 20),
             ],
         );
 
-        test(
-            "'foo'",
-            vec![t(StandardTokenType::StringLiteral("foo".to_string()), 0, 4)],
-        );
+        test("'foo'", vec![t(string("foo"), 0, 4)]);
 
-        test(
-            "\"bar\"",
-            vec![t(StandardTokenType::StringLiteral("bar".to_string()), 0, 4)],
-        );
+        test("\"bar\"", vec![t(string("bar"), 0, 4)]);
 
-        test(
-            "\"baz'nt\"",
-            vec![t(
-                StandardTokenType::StringLiteral("baz'nt".to_string()),
-                0,
-                7,
-            )],
+        test("\"baz'nt\"", vec![t(string("baz'nt"), 0, 7)]);
+
+        test("'qux\"d'", vec![t(string("qux\"d"), 0, 6)]);
+    }
+
+    fn prefixed(prefix: &str, content: &str) -> StandardTokenType {
+        StandardTokenType::StringLiteral {
+            prefix: prefix.to_string(),
+            content: content.to_string(),
+        }
+    }
+
+    #[test]
+    fn prefixed_strings() {
+        let lang =
+            |ext: &str| Options::new(ext.as_ref(), &["syns", "foo", "foo"]).expect("options");
+        test_file(
+            r#"f"{a}" rb'\d' """x"y""" if"#,
+            vec![
+                t(prefixed("f", "{a}"), 0, 5),
+                t(prefixed("rb", "\\d"), 7, 12),
+                t(string("x\"y"), 14, 22),
+                t(StandardTokenType::Identifier("if".to_string()), 24, 25),
+            ],
+            lang("py"),
+        );
+        test_file(
+            r##"r#"a "b" c"# br"\" b"x" r"##,
+            vec![
+                t(prefixed("r", "a \"b\" c"), 0, 11),
+                t(prefixed("br", "\\"), 13, 17),
+                t(prefixed("b", "x"), 19, 22),
+                t(StandardTokenType::Identifier("r".to_string()), 24, 24),
+            ],
+            lang("rs"),
+        );
+        test_file(
+            r#"R"x()")x" u8"y""#,
+            vec![
+                t(prefixed("R", ")\""), 0, 8),
+                t(prefixed("u8", "y"), 10, 14),
+            ],
+            lang("cpp"),
+        );
+        test_file(
+            r#"@"a""b\" $"{c}""#,
+            vec![
+                t(prefixed("@", "a\"\"b\\"), 0, 7),
+                t(prefixed("$", "{c}"), 9, 14),
+            ],
+            lang("cs"),
         );
+    }
 
-        test(
-            "'qux\"d'",
-            vec![t(
-                StandardTokenType::StringLiteral("qux\"d".to_string()),
-                0,
-                6,
-            )],
+    #[test]
+    fn heredocs() {
+        test_file(
+            "cat <<EOF | grep x\nfoo(\n  EOF bar\nEOF\ny",
+            vec![
+                t(StandardTokenType::Identifier("cat".to_string()), 0, 2),
+                t(prefixed("<<", "foo(\n  EOF bar"), 4, 8),
+                t(StandardTokenType::Symbol("|".to_string()), 10, 10),
+                t(StandardTokenType::Identifier("grep".to_string()), 12, 15),
+                t(StandardTokenType::Identifier("x".to_string()), 17, 17),
+                t(StandardTokenType::Identifier("y".to_string()), 38, 38),
+            ],
+            Options::new("sh".as_ref(), &["syns", "foo", "foo"]).expect("options"),
+        );
+        test_file(
+            "x = <<~'END'\n  a\n  END\n",
+            vec![
+                t(StandardTokenType::Identifier("x".to_string()), 0, 0),
+                t(StandardTokenType::Symbol("=".to_string()), 2, 2),
+                t(prefixed("<<~", "  a"), 4, 11),
+            ],
+            Options::new("rb".as_ref(), &["syns", "foo", "foo"]).expect("options"),
+        );
+        test_file(
+            "cat << 'EOF'\na\nEOF",
+            vec![
+                t(StandardTokenType::Identifier("cat".to_string()), 0, 2),
+                t(prefixed("<<", "a"), 4, 11),
+            ],
+            Options::new("sh".as_ref(), &["syns", "foo", "foo"]).expect("options"),
+        );
+        // Appending in Ruby, `<<` must be directly followed by the terminator
+        test_file(
+            "arr << x\nx = 1",
+            vec![
+                t(StandardTokenType::Identifier("arr".to_string()), 0, 2),
+                t(StandardTokenType::Symbol("<<".to_string()), 4, 5),
+                t(StandardTokenType::Identifier("x".to_string()), 7, 7),
+                t(StandardTokenType::Identifier("x".to_string()), 9, 9),
+                t(StandardTokenType::Symbol("=".to_string()), 11, 11),
+                t(StandardTokenType::Integer(1), 13, 13),
+            ],
+            Options::new("rb".as_ref(), &["syns", "foo", "foo"]).expect("options"),
         );
     }
 
@@ -699,23 +1536,19 @@ mod tests {
             r#"\.\+\*\"foo.*bar""#,
             vec![
                 t(StandardTokenType::Symbol("\\.\\+\\*\\".to_string()), 0, 6),
-                t(
-                    StandardTokenType::StringLiteral("foo.*bar".to_string()),
-                    7,
-                    16,
-                ),
+                t(string("foo.*bar"), 7, 16),
             ],
         );
 
-        let opts = Options::new("js".as_ref(), &["syns", "foo", "foo"]);
+        let opts = Options::new("js".as_ref(), &["syns", "foo", "foo"]).expect("options");
         test_query(
-            r#"\.\+\*\"foo.*bar"\$\|\?"#,
+            r#"\.\+\*\"foo.*bar"\$\|\?\!"#,
             vec![
                 q(QueryTokenType::Special(SpecialTokenType::Any), 0, 1),
                 q(QueryTokenType::Special(SpecialTokenType::Plus), 2, 3),
                 q(QueryTokenType::Special(SpecialTokenType::Star), 4, 5),
                 q(
-                    QueryTokenType::Special(SpecialTokenType::Regex("foo.*bar".to_string())),
+                    QueryTokenType::Special(SpecialTokenType::Regex(None, "foo.*bar".to_string())),
                     7,
                     16,
                 ),
@@ -726,14 +1559,189 @@ mod tests {
                     21,
                     22,
                 ),
+                q(QueryTokenType::Special(SpecialTokenType::Not), 23, 24),
             ],
             opts,
         );
     }
 
+    #[test]
+    fn capture_tokens() {
+        let opts = Options::new("js".as_ref(), &["syns", "foo", "foo"]).expect("options");
+
+        test_query(
+            r#"\:x == \:foo_1"#,
+            vec![
+                q(
+                    QueryTokenType::Special(SpecialTokenType::Capture("x".to_string())),
+                    0,
+                    2,
+                ),
+                q(
+                    QueryTokenType::Standard(StandardTokenType::Symbol("==".to_string())),
+                    4,
+                    5,
+                ),
+                q(
+                    QueryTokenType::Special(SpecialTokenType::Capture("foo_1".to_string())),
+                    7,
+                    13,
+                ),
+            ],
+            opts,
+        );
+    }
+
+    #[test]
+    fn comment_tokens() {
+        test_query(
+            r#"a // b
+\#"c.*" /* d */"#,
+            vec![
+                q(
+                    QueryTokenType::Standard(StandardTokenType::Identifier("a".to_string())),
+                    0,
+                    0,
+                ),
+                q(
+                    QueryTokenType::Special(SpecialTokenType::Comment("c.*".to_string())),
+                    9,
+                    13,
+                ),
+            ],
+            Options::new("js".as_ref(), &["syns", "foo", "foo"]).expect("options"),
+        );
+    }
+
+    #[test]
+    fn typed_wildcard_tokens() {
+        let special = |ty: SpecialTokenType| QueryTokenType::Special(ty);
+        let bound = |op: Comparison, value: f64| NumberBound {
+            op,
+            value: value.into(),
+        };
+        test_query(
+            r#"\i"^get_" \sym \s \n>=1<1e3 \n=-2.5"#,
+            vec![
+                q(
+                    special(SpecialTokenType::Class(
+                        TokenClass::Identifier,
+                        Some("^get_".to_string()),
+                    )),
+                    2,
+                    8,
+                ),
+                q(
+                    special(SpecialTokenType::Class(TokenClass::Symbol, None)),
+                    10,
+                    13,
+                ),
+                q(
+                    special(SpecialTokenType::Regex(None, String::new())),
+                    15,
+                    16,
+                ),
+                q(
+                    special(SpecialTokenType::Number(vec![
+                        bound(Comparison::GreaterOrEqual, 1.0),
+                        bound(Comparison::Less, 1000.0),
+                    ])),
+                    18,
+                    26,
+                ),
+                q(
+                    special(SpecialTokenType::Number(vec![bound(
+                        Comparison::Equal,
+                        -2.5,
+                    )])),
+                    28,
+                    34,
+                ),
+            ],
+            Options::new("js".as_ref(), &["syns", "foo", "foo"]).expect("options"),
+        );
+    }
+
+    fn query_error(input: &str) -> SynsError {
+        tokenize_query(input.as_bytes(), &Options::default()).expect_err("query error")
+    }
+
+    #[test]
+    fn invalid_number_bound() {
+        assert_eq!(
+            query_error(r"f(\n>x)"),
+            SynsError::InvalidNumber {
+                value: "".to_string(),
+                span: Span { lo: 2, hi: 4 },
+            }
+        );
+    }
+
+    #[test]
+    fn has_token() {
+        let opts = Options::new("js".as_ref(), &["syns", "foo", "foo"]).expect("options");
+
+        test_query(
+            r#"\has(x)"#,
+            vec![
+                q(QueryTokenType::Special(SpecialTokenType::Has), 0, 3),
+                q(
+                    QueryTokenType::Standard(StandardTokenType::Symbol("(".to_string())),
+                    4,
+                    4,
+                ),
+                q(
+                    QueryTokenType::Standard(StandardTokenType::Identifier("x".to_string())),
+                    5,
+                    5,
+                ),
+                q(
+                    QueryTokenType::Standard(StandardTokenType::Symbol(")".to_string())),
+                    6,
+                    6,
+                ),
+            ],
+            opts,
+        );
+    }
+
+    #[test]
+    fn unknown_word_command() {
+        assert_eq!(
+            query_error(r"foo \hax"),
+            SynsError::UnknownCommand {
+                name: "hax".to_string(),
+                span: Span { lo: 4, hi: 7 },
+            }
+        );
+        assert_eq!(
+            query_error(r"\(a \x\)"),
+            SynsError::UnknownCommand {
+                name: "x".to_string(),
+                span: Span { lo: 4, hi: 5 },
+            }
+        );
+    }
+
+    #[test]
+    fn incomplete_commands() {
+        assert_eq!(
+            query_error(r"a \:"),
+            SynsError::MissingCaptureName {
+                span: Span { lo: 2, hi: 3 },
+            }
+        );
+        assert_eq!(
+            query_error(r"a \"),
+            SynsError::UnexpectedEnd {
+                span: Span { lo: 2, hi: 2 },
+            }
+        );
+    }
+
     #[test]
     fn user_forgot_to_close_group() {
-        let opts = Options::new("js".as_ref(), &["syns", "foo", "foo"]);
+        let opts = Options::new("js".as_ref(), &["syns", "foo", "foo"]).expect("options");
 
         // \() should behave like \()\)
         test_query(
@@ -766,13 +1774,13 @@ mod tests {
 
     #[test]
     fn plus_after_regex() {
-        let opts = Options::new("js".as_ref(), &["syns", "foo", "foo"]);
+        let opts = Options::new("js".as_ref(), &["syns", "foo", "foo"]).expect("options");
 
         test_query(
             r#"\"INSERT .*" +"#,
             vec![
                 q(
-                    QueryTokenType::Special(SpecialTokenType::Regex("INSERT .*".to_string())),
+                    QueryTokenType::Special(SpecialTokenType::Regex(None, "INSERT .*".to_string())),
                     1,
                     11,
                 ),
@@ -788,7 +1796,7 @@ mod tests {
 
     #[test]
     fn binary_file_skipped_by_default() {
-        let opts = Options::new("js".as_ref(), &["syns", "foo", "foo"]);
+        let opts = Options::new("js".as_ref(), &["syns", "foo", "foo"]).expect("options");
         let input: &[u8] = b"foo \x00 bar";
         let (tokens, _) = tokenize("bin", input, &opts);
         assert!(tokens.is_empty());
@@ -796,7 +1804,7 @@ mod tests {
 
     #[test]
     fn binary_file_searched_with_flag() {
-        let mut opts = Options::new("js".as_ref(), &["syns", "foo", "foo"]);
+        let mut opts = Options::new("js".as_ref(), &["syns", "foo", "foo"]).expect("options");
         opts.search_binary = true;
         let input: &[u8] = b"foo \x00 bar";
         let (tokens, _) = tokenize("bin", input, &opts);
@@ -28,6 +28,44 @@ impl std::ops::Deref for RegexEq {
     }
 }
 
+/// Reference wrapper with `Ord` + `PartialEq` based on the address of the referenced value.
+#[derive(Debug)]
+pub struct ByAddress<'a, T>(pub &'a T);
+
+impl<T> Clone for ByAddress<'_, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T> Copy for ByAddress<'_, T> {}
+
+impl<T> PartialEq for ByAddress<'_, T> {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0, other.0)
+    }
+}
+impl<T> Eq for ByAddress<'_, T> {}
+
+impl<T> PartialOrd for ByAddress<'_, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> Ord for ByAddress<'_, T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.0 as *const T).cmp(&(other.0 as *const T))
+    }
+}
+
+impl<T> std::ops::Deref for ByAddress<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
 /// `f64` wrapper with `Hash` + `PartialEq` based on `to_bits`.
 #[derive(Clone, Debug)]
 pub struct Float(pub f64);
@@ -1,13 +1,19 @@
 [0m[35m[test-files/hello/elixir.ex:1][0m [0m[1m[31mdefmodule[0m Foo do
 [0m[35m[test-files/hello/elixir.ex:1][0m defmodule [0m[1m[31mFoo[0m do
-[0m[35m[test-files/hello/elixir.ex:1][0m defmodule Foo [0m[1m[31mdo[0m
+[0m[35m[test-files/hello/elixir.ex:1-5]
+[0mdefmodule Foo [0m[1m[31m[0m[1m[31mdo
+[0m[1m[31m[0m[1m[31m  def bar() do
+[0m[1m[31m[0m[1m[31m    IO.puts "Hello world!"
+[0m[1m[31m[0m[1m[31m  end
+[0m[1m[31m[0m[1m[31mend[0m
 [0m[35m[test-files/hello/elixir.ex:2][0m   [0m[1m[31mdef[0m bar() do
 [0m[35m[test-files/hello/elixir.ex:2][0m   def [0m[1m[31mbar[0m() do
 [0m[35m[test-files/hello/elixir.ex:2][0m   def bar[0m[1m[31m()[0m do
-[0m[35m[test-files/hello/elixir.ex:2][0m   def bar() [0m[1m[31mdo[0m
+[0m[35m[test-files/hello/elixir.ex:2-4]
+[0m  def bar() [0m[1m[31m[0m[1m[31mdo
+[0m[1m[31m[0m[1m[31m    IO.puts "Hello world!"
+[0m[1m[31m[0m[1m[31m  end[0m
 [0m[35m[test-files/hello/elixir.ex:3][0m     [0m[1m[31mIO[0m.puts "Hello world!"
 [0m[35m[test-files/hello/elixir.ex:3][0m     IO[0m[1m[31m.[0mputs "Hello world!"
 [0m[35m[test-files/hello/elixir.ex:3][0m     IO.[0m[1m[31mputs[0m "Hello world!"
 [0m[35m[test-files/hello/elixir.ex:3][0m     IO.puts [0m[1m[31m"Hello world!"[0m
-[0m[35m[test-files/hello/elixir.ex:4][0m   [0m[1m[31mend[0m
-[0m[35m[test-files/hello/elixir.ex:5][0m [0m[1m[31mend[0m
@@ -2,13 +2,15 @@
 [0m[35m[test-files/hello/python.py:1][0m def [0m[1m[31mhello_fn[0m():
 [0m[35m[test-files/hello/python.py:1][0m def hello_fn[0m[1m[31m()[0m:
 [0m[35m[test-files/hello/python.py:1][0m def hello_fn()[0m[1m[31m:[0m
-[0m[35m[test-files/hello/python.py:2][0m     [0m[1m[31mprint[0m("Hello world!")
-[0m[35m[test-files/hello/python.py:2][0m     print[0m[1m[31m("Hello world!")[0m
+[0m[35m[test-files/hello/python.py:2][0m     [0m[1m[31mprint("Hello world!")[0m
 [0m[35m[test-files/hello/python.py:4][0m [0m[1m[31mif[0m __name__ == "__main__":
 [0m[35m[test-files/hello/python.py:4][0m if [0m[1m[31m__name__[0m == "__main__":
 [0m[35m[test-files/hello/python.py:4][0m if __name__ [0m[1m[31m==[0m "__main__":
 [0m[35m[test-files/hello/python.py:4][0m if __name__ == [0m[1m[31m"__main__"[0m:
 [0m[35m[test-files/hello/python.py:4][0m if __name__ == "__main__"[0m[1m[31m:[0m
+[0m[35m[test-files/hello/python.py:5][0m     [0m[1m[31mhello_fn()[0m
+[0m[35m[test-files/hello/python.py:2][0m     [0m[1m[31mprint[0m("Hello world!")
+[0m[35m[test-files/hello/python.py:2][0m     print[0m[1m[31m("Hello world!")[0m
+[0m[35m[test-files/hello/python.py:2][0m     print([0m[1m[31m"Hello world!"[0m)
 [0m[35m[test-files/hello/python.py:5][0m     [0m[1m[31mhello_fn[0m()
 [0m[35m[test-files/hello/python.py:5][0m     hello_fn[0m[1m[31m()[0m
-[0m[35m[test-files/hello/python.py:2][0m     print([0m[1m[31m"Hello world!"[0m)
@@ -1,13 +1,17 @@
 [test-files/hello/elixir.ex:1] defmodule
 [test-files/hello/elixir.ex:1] Foo
-[test-files/hello/elixir.ex:1] do
+[test-files/hello/elixir.ex:1-5] do
+  def bar() do
+    IO.puts "Hello world!"
+  end
+end
 [test-files/hello/elixir.ex:2] def
 [test-files/hello/elixir.ex:2] bar
 [test-files/hello/elixir.ex:2] ()
-[test-files/hello/elixir.ex:2] do
+[test-files/hello/elixir.ex:2-4] do
+    IO.puts "Hello world!"
+  end
 [test-files/hello/elixir.ex:3] IO
 [test-files/hello/elixir.ex:3] .
 [test-files/hello/elixir.ex:3] puts
 [test-files/hello/elixir.ex:3] "Hello world!"
-[test-files/hello/elixir.ex:4] end
-[test-files/hello/elixir.ex:5] end
@@ -2,13 +2,15 @@
 [test-files/hello/python.py:1] hello_fn
 [test-files/hello/python.py:1] ()
 [test-files/hello/python.py:1] :
-[test-files/hello/python.py:2] print
-[test-files/hello/python.py:2] ("Hello world!")
+[test-files/hello/python.py:2] print("Hello world!")
 [test-files/hello/python.py:4] if
 [test-files/hello/python.py:4] __name__
 [test-files/hello/python.py:4] ==
 [test-files/hello/python.py:4] "__main__"
 [test-files/hello/python.py:4] :
+[test-files/hello/python.py:5] hello_fn()
+[test-files/hello/python.py:2] print
+[test-files/hello/python.py:2] ("Hello world!")
+[test-files/hello/python.py:2] "Hello world!"
 [test-files/hello/python.py:5] hello_fn
 [test-files/hello/python.py:5] ()
-[test-files/hello/python.py:2] "Hello world!"
@@ -1,4 +1,5 @@
 //! Tests for CLI output.
+#![allow(clippy::unwrap_used)]
 
 use assert_cmd::cargo;
 use assert_cmd::prelude::*; // Add methods on commands
@@ -7,11 +8,19 @@ use regex::Regex;
 use std::path::PathBuf;
 use std::process::Command; // Run programs
 
+/// The syns binary, without the configuration of whoever runs the tests.
+fn syns() -> Command {
+    let home = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("no-config");
+    let mut cmd = Command::new(cargo::cargo_bin!());
+    cmd.env("XDG_CONFIG_HOME", &home).env("HOME", &home);
+    cmd
+}
+
 fn run(path: &str, query: &str) -> Command {
     let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     d.push(path);
 
-    let mut cmd = Command::new(cargo::cargo_bin!());
+    let mut cmd = syns();
     cmd.arg("--no-color").arg(query).arg(d);
     cmd
 }
@@ -272,3 +281,625 @@ fn test_dump_machine() {
 "#,
     );
 }
+
+#[test]
+fn test_replace_dry_run() {
+    let mut cmd = run("test-files/main.c", "printf(\\\"\" , \\:y)");
+    cmd.arg("--replace").arg("puts(\\:y)").arg("--dry-run");
+
+    cmd.assert().code(0).stdout(
+        predicate::str::is_match(
+            "^--- a/.*test-files/main.c
+\\+\\+\\+ b/.*test-files/main.c
+@@ -1,6 \\+1,6 @@
+ #include <stdio.h>
+ 
+ int main\\(\\) \\{
+-    printf\\(\"Hello %s!\\\\n\", \"world\"\\);
+\\+    puts\\(\"world\"\\);
+     return 0;
+ }
+$",
+        )
+        .unwrap(),
+    );
+}
+
+#[test]
+fn test_replace_in_place() {
+    let mut path = std::env::temp_dir();
+    path.push(format!("syns-replace-{}.c", std::process::id()));
+    std::fs::copy(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test-files/main.c"),
+        &path,
+    )
+    .unwrap();
+
+    let mut cmd = syns();
+    cmd.arg("--replace")
+        .arg("puts(\\:y)")
+        .arg("printf(\\\"\" , \\:y)")
+        .arg(&path);
+    cmd.assert().code(0).stdout("");
+
+    let content = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(
+        content,
+        "#include <stdio.h>\n\nint main() {\n    puts(\"world\");\n    return 0;\n}\n"
+    );
+}
+
+#[test]
+fn test_json_output() {
+    let mut cmd = run("test-files/main.c", "printf()");
+    cmd.arg("--json");
+
+    cmd.assert().code(0).stdout(
+        predicate::str::is_match(
+            r#"^\{"path":".*test-files/main.c","start_line":4,"end_line":4,"start_column":5,"end_column":35,"start_byte":37,"end_byte":67,"text":"printf\(\\"Hello %s!\\\\n\\", \\"world\\"\)","lines":\["    printf\(\\"Hello %s!\\\\n\\", \\"world\\"\);"\]\}
+$"#,
+        )
+        .unwrap(),
+    );
+}
+
+#[test]
+fn test_sarif_output() {
+    let mut cmd = run("test-files", "printf()");
+    cmd.arg("--sarif");
+
+    let value = cmd.assert().code(0).get_output().clone();
+    let log: serde_json::Value = serde_json::from_slice(&value.stdout).unwrap();
+
+    assert_eq!(log["version"], "2.1.0");
+    assert_eq!(log["runs"][0]["tool"]["driver"]["rules"][0]["id"], "query");
+    let results = log["runs"][0]["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    let uris = results
+        .iter()
+        .map(|r| {
+            r["locations"][0]["physicalLocation"]["artifactLocation"]["uri"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        })
+        .collect::<Vec<String>>();
+    assert!(uris.iter().any(|uri| uri.ends_with("test-files/main.c")));
+    assert!(uris.iter().any(|uri| uri.ends_with("test-files/hello/c.c")));
+}
+
+#[test]
+fn test_sarif_no_matches() {
+    let mut cmd = run("test-files/main.c", "no match");
+    cmd.arg("--sarif");
+
+    let value = cmd.assert().code(1).get_output().clone();
+    let log: serde_json::Value = serde_json::from_slice(&value.stdout).unwrap();
+    assert_eq!(
+        log["runs"][0]["results"].as_array().map(|r| r.len()),
+        Some(0)
+    );
+}
+
+fn write_rules(name: &str, json: &str) -> PathBuf {
+    let mut path = std::env::temp_dir();
+    path.push(format!("syns-rules-{}-{}.json", name, std::process::id()));
+    std::fs::write(&path, json).unwrap();
+    path
+}
+
+const RULES: &str = r#"{"rules": [
+    {"id": "c-printf", "pattern": "printf()", "message": "Use puts", "languages": ["c"]},
+    {"id": "any-return", "pattern": "return 0", "severity": "note", "paths": ["**/hello/**"]},
+    {"id": "py-print", "pattern": "print()", "severity": "error"}
+]}"#;
+
+#[test]
+fn test_rules() {
+    let rules = write_rules("text", RULES);
+    let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    d.push("test-files");
+
+    let mut cmd = syns();
+    cmd.arg("--no-color").arg("--rules").arg(&rules).arg(d);
+    let value = cmd.assert().code(0).get_output().clone();
+    std::fs::remove_file(&rules).unwrap();
+
+    let stdout = String::from_utf8(value.stdout).unwrap();
+    let mut lines = stdout.lines().collect::<Vec<_>>();
+    lines.sort();
+    assert_eq!(lines.len(), 5, "{}", stdout);
+    assert!(Regex::new(
+        r#"^\[.*test-files/hello/c.c:6\] \[c-printf\]   printf\("Hello world!\\n"\);$"#
+    )
+    .unwrap()
+    .is_match(lines[0]));
+    assert!(
+        Regex::new(r"^\[.*test-files/hello/c.c:7\] \[any-return\]   return 0;$")
+            .unwrap()
+            .is_match(lines[1])
+    );
+    assert!(
+        Regex::new(r"^\[.*test-files/hello/python.py:2\] \[py-print\] ")
+            .unwrap()
+            .is_match(lines[2])
+    );
+    assert!(
+        Regex::new(r#"^\[.*test-files/main.c:4\] \[c-printf\]     printf\("#)
+            .unwrap()
+            .is_match(lines[3])
+    );
+    assert!(
+        Regex::new(r"^\[.*test-files/main.py:3\] \[py-print\]     print\('Hello world!'\)$")
+            .unwrap()
+            .is_match(lines[4])
+    );
+}
+
+#[test]
+fn test_rules_json() {
+    let rules = write_rules("json", RULES);
+    let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    d.push("test-files/main.py");
+
+    let mut cmd = syns();
+    cmd.arg("--json").arg("--rules").arg(&rules).arg(d);
+    let value = cmd.assert().code(0).get_output().clone();
+    std::fs::remove_file(&rules).unwrap();
+
+    let record: serde_json::Value = serde_json::from_slice(&value.stdout).unwrap();
+    assert_eq!(record["rule_id"], "py-print");
+    assert_eq!(record["message"], "print()");
+    assert_eq!(record["severity"], "error");
+    assert_eq!(record["start_line"], 3);
+}
+
+#[test]
+fn test_rules_sarif() {
+    let rules = write_rules("sarif", RULES);
+    let mut d = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    d.push("test-files/main.c");
+
+    let mut cmd = syns();
+    cmd.arg("--sarif").arg("--rules").arg(&rules).arg(d);
+    let value = cmd.assert().code(0).get_output().clone();
+    std::fs::remove_file(&rules).unwrap();
+
+    let log: serde_json::Value = serde_json::from_slice(&value.stdout).unwrap();
+    let driver_rules = log["runs"][0]["tool"]["driver"]["rules"]
+        .as_array()
+        .unwrap();
+    assert_eq!(driver_rules.len(), 3);
+    assert_eq!(driver_rules[2]["defaultConfiguration"]["level"], "error");
+    let results = log["runs"][0]["results"].as_array().unwrap();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0]["ruleId"], "c-printf");
+    assert_eq!(results[0]["message"]["text"], "Use puts");
+}
+
+#[test]
+fn test_rules_invalid_file() {
+    let rules = write_rules("invalid", r#"{"rules": [{"id": "a"}]}"#);
+
+    let mut cmd = syns();
+    cmd.arg("--rules").arg(&rules).arg("test-files");
+    let assert = cmd.assert();
+    std::fs::remove_file(&rules).unwrap();
+    assert
+        .code(2)
+        .stderr(predicate::str::contains("missing field `pattern`"));
+}
+
+#[test]
+fn test_invalid_query() {
+    let mut cmd = run("test-files/main.c", r"printf(\x)");
+
+    cmd.assert().code(2).stderr(predicate::str::diff(
+        "Err: Unknown query command \\x\n  |\n1 | printf(\\x)\n  |        ^^\n",
+    ));
+}
+
+#[test]
+fn test_backslash_in_query_string() {
+    // Only a command outside of strings in the language of the file searched
+    let mut path = std::env::temp_dir();
+    path.push(format!("syns-backslash-{}.py", std::process::id()));
+    std::fs::write(&path, "import re\nr = re.compile(\"\\d+\")\n").unwrap();
+    let mut cmd = syns();
+    cmd.arg("--no-color").arg(r#"re.compile("\d+")"#).arg(&path);
+    let query = cmd.assert();
+
+    // Rules are only compiled for the languages they run on
+    let rules = write_rules(
+        "backslash",
+        r#"{"rules": [{"id": "py-digits", "pattern": "re.compile(\"\\d+\")", "languages": ["python"]}]}"#,
+    );
+    let mut cmd = syns();
+    cmd.arg("--no-color")
+        .arg("--rules")
+        .arg(&rules)
+        .arg(&path)
+        .arg(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test-files"));
+    let rule = cmd.assert();
+    std::fs::remove_file(&rules).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    query
+        .code(0)
+        .stdout(predicate::str::ends_with(":2] r = re.compile(\"\\d+\")\n"));
+    rule.code(0).stderr("").stdout(predicate::str::ends_with(
+        ":2] [py-digits] r = re.compile(\"\\d+\")\n",
+    ));
+
+    run_stdin(
+        &["--stdin-filename", "e.c", r#"printf("\x41")"#],
+        "printf(\"\\x41\");\n",
+    )
+    .assert()
+    .code(0)
+    .stdout("[e.c:1] printf(\"\\x41\");\n");
+}
+
+#[test]
+fn test_explain() {
+    let mut cmd = syns();
+    cmd.arg("--explain")
+        .arg(r"printf(\.\$) \| \+")
+        .arg("test-files/main.c");
+
+    let explanation = "either (identifier `printf`, then a `(...)` block whose contents are: any tree, then end of block) or one or more of any tree\n\
+         Warning: Nothing before \\+ to repeat, so it repeats \\.\n  |\n1 | printf(\\.\\$) \\| \\+\n  |                 ^^\n";
+    cmd.assert()
+        .code(0)
+        .stdout(predicate::str::diff(explanation));
+
+    // Explained once even without any files to search
+    let dir = std::env::temp_dir().join("syns-explain-empty");
+    std::fs::create_dir_all(&dir).unwrap();
+    syns()
+        .arg("--explain")
+        .arg(r"printf(\.\$) \| \+")
+        .arg(&dir)
+        .assert()
+        .code(0)
+        .stdout(predicate::str::diff(explanation));
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    // Strings and comments come from the language of the file, or the general defaults
+    let strings =
+        "identifier `printf`, then a `(...)` block whose contents start with: string `\"hi\"`\n";
+    syns()
+        .arg("--explain")
+        .arg(r#"printf("hi") /* x */"#)
+        .arg("test-files/main.c")
+        .assert()
+        .code(0)
+        .stdout(predicate::str::diff(strings));
+    run_stdin(
+        &[
+            "--explain",
+            "--stdin-filename",
+            "x.py",
+            r#"printf("hi") # x"#,
+        ],
+        "",
+    )
+    .assert()
+    .code(0)
+    .stdout(predicate::str::diff(strings));
+    run_stdin(&["--explain", r#"printf("hi") // x"#], "")
+        .assert()
+        .code(0)
+        .stdout(predicate::str::diff(strings));
+}
+
+#[test]
+fn test_query_warning() {
+    let mut cmd = run("test-files/main.c", r"printf(\+)");
+
+    cmd.assert().stderr(predicate::str::diff(
+        "Warning: Nothing before \\+ to repeat, so it repeats \\.\n  |\n1 | printf(\\+)\n  |        ^^\n",
+    ));
+}
+
+#[test]
+fn test_rules_invalid_pattern() {
+    let rules = write_rules(
+        "invalid-pattern",
+        r#"{"rules": [{"id": "bad", "pattern": "\\i\"[\""}]}"#,
+    );
+
+    let mut cmd = syns();
+    cmd.arg("--rules").arg(&rules).arg("test-files");
+    let assert = cmd.assert();
+    std::fs::remove_file(&rules).unwrap();
+    assert.code(2).stderr(predicate::str::diff(
+        "Err: Invalid pattern in rule 'bad': Invalid regex: unclosed character class\n  |\n1 | \\i\"[\"\n  |   ^^^\n",
+    ));
+}
+
+#[test]
+fn test_threads() {
+    let output = |threads: &str| {
+        let mut cmd = run("test-files", "main()");
+        cmd.arg("-j").arg(threads);
+        let value = cmd.assert().code(0).get_output().clone();
+        let stdout = String::from_utf8(value.stdout).unwrap();
+        // Each match starts with a filename header, keep multi-line matches together
+        let mut matches = format!("\n{}", stdout.trim_end())
+            .split("\n[")
+            .skip(1)
+            .map(|m| m.to_string())
+            .collect::<Vec<String>>();
+        matches.sort();
+        matches
+    };
+
+    let serial = output("1");
+    assert!(serial.len() > 2);
+    assert_eq!(output("8"), serial);
+}
+
+#[test]
+fn test_context() {
+    let mut cmd = run("test-files/main.c", "printf()");
+    cmd.arg("-I").arg("-C").arg("1");
+    cmd.assert()
+        .code(0)
+        .stdout("int main() {\n    printf(\"Hello %s!\\n\", \"world\");\n    return 0;\n");
+
+    let mut path = std::env::temp_dir();
+    path.push(format!("syns-context-{}.js", std::process::id()));
+    std::fs::write(&path, "a\nfoo(1)\nb\nc\nd\ne\nf\nfoo(2)\ng\nfoo(3)\nh\n").unwrap();
+    let mut cmd = syns();
+    cmd.arg("--no-color")
+        .arg("-C")
+        .arg("1")
+        .arg("foo()")
+        .arg(&path);
+    let assert = cmd.assert();
+    std::fs::remove_file(&path).unwrap();
+    let p = path.to_string_lossy();
+    assert.code(0).stdout(format!(
+        "[{p}-1] a\n[{p}:2] foo(1)\n[{p}-3] b\n--\n[{p}-7] f\n[{p}:8] foo(2)\n[{p}-9] g\n\
+         [{p}:10] foo(3)\n[{p}-11] h\n"
+    ));
+}
+
+fn run_stdin(args: &[&str], stdin: &str) -> assert_cmd::Command {
+    let mut cmd = assert_cmd::Command::from_std(syns());
+    cmd.arg("--no-color").args(args).write_stdin(stdin);
+    cmd
+}
+
+#[test]
+fn test_interactive() {
+    run_stdin(
+        &["--interactive", "test-files/main.c"],
+        "printf()\nreturn \\n>0\n:tokens 5\n",
+    )
+    .assert()
+    .code(0)
+    .stdout(
+        "syns> [test-files/main.c:4]     printf(\"Hello %s!\\n\", \"world\");\n\
+         syns> No matches\n\
+         syns> 5:5-5:11 [73..79] Identifier return\n\
+         5:12-5:13 [80..81] Integer 0\n\
+         5:13-5:14 [81..82] Symbol ;\n\
+         syns> ",
+    );
+}
+
+#[test]
+fn test_dump_tokens_and_ast() {
+    run_stdin(
+        &["--lang", "javascript", "--dump-tokens", "--dump-ast", "-"],
+        "f<T>(1)",
+    )
+    .assert()
+    .code(0)
+    .stdout(
+        "<stdin>:\n\
+         1:1-1:2 [0..1] Identifier f\n\
+         1:2-1:3 [1..2] Symbol <\n\
+         1:3-1:4 [2..3] Identifier T\n\
+         1:4-1:5 [3..4] Symbol >\n\
+         1:5-1:6 [4..5] Symbol (\n\
+         1:6-1:7 [5..6] Integer 1\n\
+         1:7-1:8 [6..7] Symbol )\n\
+         1:1-1:2 [0..1] Identifier f\n\
+         1:2-1:5 [1..4] Block Symbol < ... Symbol >\n\
+         \x20 1:3-1:4 [2..3] Identifier T\n\
+         1:5-1:8 [4..7] Block Symbol ( ... Symbol )\n\
+         \x20 1:6-1:7 [5..6] Integer 1\n",
+    );
+
+    run_stdin(
+        &["--lang", "c", "--dump-tokens", "--lines", "2", "-"],
+        "a\nb\nc\n",
+    )
+    .assert()
+    .code(0)
+    .stdout("<stdin>:\n2:1-2:2 [2..3] Identifier b\n");
+
+    // Tokens are dumped before the parser splits `>>` into two closing symbols
+    run_stdin(
+        &["--lang", "javascript", "--dump-tokens", "-"],
+        "Vec<Vec<u8>>",
+    )
+    .assert()
+    .code(0)
+    .stdout(
+        "<stdin>:\n\
+         1:1-1:4 [0..3] Identifier Vec\n\
+         1:4-1:5 [3..4] Symbol <\n\
+         1:5-1:8 [4..7] Identifier Vec\n\
+         1:8-1:9 [7..8] Symbol <\n\
+         1:9-1:11 [8..10] Identifier u8\n\
+         1:11-1:13 [10..12] Symbol >>\n",
+    );
+
+    run_stdin(&["--lines", "2", "a", "-"], "a\nb\n")
+        .assert()
+        .code(1)
+        .stdout(predicate::str::starts_with(
+            "Invalid argument --lines without --dump-tokens or --dump-ast\n",
+        ));
+}
+
+#[test]
+fn test_stdin() {
+    run_stdin(
+        &["--lang", "c", "memcpy()", "-"],
+        "int f() {\n  memcpy(a, b, 1);\n}\n",
+    )
+    .assert()
+    .code(0)
+    .stdout("[<stdin>:2]   memcpy(a, b, 1);\n");
+
+    run_stdin(&["--lang", "pyhton", "memcpy()", "-"], "memcpy(a)\n")
+        .assert()
+        .code(2)
+        .stderr("Err: Unknown language 'pyhton', see --lang for the list\n");
+
+    // Without a path a piped stdin is searched instead of the current directory
+    run_stdin(&["-I", "memcpy()"], "# memcpy(a)\n")
+        .assert()
+        .code(0)
+        .stdout("# memcpy(a)\n");
+}
+
+#[test]
+fn test_stdin_filename() {
+    run_stdin(
+        &["--stdin-filename", "src/x.py", "memcpy()"],
+        "x = memcpy(a)\n# memcpy(b)\n",
+    )
+    .assert()
+    .code(0)
+    .stdout("[src/x.py:1] x = memcpy(a)\n");
+}
+
+#[test]
+fn test_stdin_replace() {
+    run_stdin(
+        &["--replace", "puts()", "printf()", "-"],
+        "printf(1);\nfoo();\n",
+    )
+    .assert()
+    .code(0)
+    .stdout("puts();\nfoo();\n");
+
+    run_stdin(
+        &["--replace", "puts()", "--dry-run", "printf()", "-"],
+        "printf(1);\n",
+    )
+    .assert()
+    .code(0)
+    .stdout("--- a/<stdin>\n+++ b/<stdin>\n@@ -1 +1 @@\n-printf(1);\n+puts();\n");
+}
+
+#[test]
+fn test_language_detection() {
+    let mut dir = std::env::temp_dir();
+    dir.push(format!("syns-detect-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("bin")).unwrap();
+    // Comments are only skipped if the language is detected
+    std::fs::write(
+        dir.join("bin/deploy"),
+        "#!/usr/bin/env python3\n# foo(1)\nfoo(2)\n",
+    )
+    .unwrap();
+    std::fs::write(dir.join("Makefile"), "# foo(3)\nall:\n\tfoo(4)\n").unwrap();
+
+    let mut cmd = syns();
+    cmd.arg("--no-color")
+        .arg("-I")
+        .arg("-j")
+        .arg("1")
+        .arg("foo()")
+        .arg(&dir);
+    let assert = cmd.assert();
+    std::fs::remove_dir_all(&dir).unwrap();
+    let value = assert.code(0).get_output().clone();
+    let mut lines = String::from_utf8(value.stdout)
+        .unwrap()
+        .lines()
+        .map(|l| l.to_string())
+        .collect::<Vec<_>>();
+    lines.sort();
+    assert_eq!(lines, vec!["\tfoo(4)", "foo(2)"]);
+
+    run_stdin(
+        &["-I", "--stdin-filename", "deploy", "foo()"],
+        "#!/bin/sh\n# foo(1)\n",
+    )
+    .assert()
+    .code(1);
+}
+
+#[test]
+fn test_config_files() {
+    let mut dir = std::env::temp_dir();
+    dir.push(format!("syns-config-{}", std::process::id()));
+    let project = dir.join("project");
+    std::fs::create_dir_all(project.join("src")).unwrap();
+    std::fs::create_dir_all(dir.join("home/syns")).unwrap();
+    std::fs::write(project.join("src/main.tf"), "# foo(1)\n// foo(2)\nfoo(3)\n").unwrap();
+    let search = |args: &[&str]| {
+        let mut cmd = syns();
+        cmd.current_dir(project.join("src"))
+            .env("XDG_CONFIG_HOME", dir.join("home"))
+            .arg("--no-color")
+            .arg("-I")
+            .args(args)
+            .arg("foo()")
+            .arg("main.tf");
+        cmd
+    };
+
+    // User configuration
+    std::fs::write(
+        dir.join("home/syns/config.json"),
+        r##"{"hcl": {"extensions": ["tf"], "single_comments": ["#", "//"]}}"##,
+    )
+    .unwrap();
+    search(&[]).assert().code(0).stdout("foo(3)\n");
+
+    // The project configuration is found in a parent directory and takes precedence
+    std::fs::write(
+        project.join(".syns.json"),
+        r##"{"hcl": {"single_comments": ["#"]}}"##,
+    )
+    .unwrap();
+    search(&[]).assert().code(0).stdout("// foo(2)\nfoo(3)\n");
+
+    // --config takes precedence over both
+    let config = dir.join("extra.json");
+    std::fs::write(&config, r#"{"hcl": {"single_comments": []}}"#).unwrap();
+    search(&["--config", &config.to_string_lossy()])
+        .assert()
+        .code(0)
+        .stdout("# foo(1)\n// foo(2)\nfoo(3)\n");
+
+    std::fs::write(&config, r#"{"hcl": {"comments": []}}"#).unwrap();
+    search(&["--config", &config.to_string_lossy()])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains("Invalid configuration file"));
+    search(&["--config", "doesnt-exist.json"])
+        .assert()
+        .code(2)
+        .stderr(predicate::str::contains(
+            "Failed to read configuration file",
+        ));
+
+    // The search for a project configuration stops at the root of a repository
+    std::fs::rename(project.join(".syns.json"), dir.join(".syns.json")).unwrap();
+    search(&[]).assert().code(0).stdout("// foo(2)\nfoo(3)\n");
+    std::fs::create_dir(project.join(".git")).unwrap();
+    search(&[]).assert().code(0).stdout("foo(3)\n");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -1,4 +1,5 @@
 //! Tests for various language defaults.
+#![allow(clippy::unwrap_used)]
 
 use assert_cmd::cargo;
 use assert_cmd::prelude::*;
@@ -6,10 +7,18 @@ use std::path::PathBuf;
 use std::process::Command;
 use test_generator::test_resources;
 
+/// The syns binary, without the configuration of whoever runs the tests.
+fn syns() -> Command {
+    let home = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("no-config");
+    let mut cmd = Command::new(cargo::cargo_bin!());
+    cmd.env("XDG_CONFIG_HOME", &home).env("HOME", &home);
+    cmd
+}
+
 // for f in test-files/hello/*; do syns --no-color -o '\.' $f > tests/.fixtures/nocolor/$(basename $f); done
 #[test_resources("test-files/hello/*")]
 fn hello_world_langs_nocolor(file: &str) {
-    let mut cmd = Command::new(cargo::cargo_bin!());
+    let mut cmd = syns();
     cmd.arg("--no-color").arg("-o").arg("\\.").arg(file);
 
     let filename = file.split('/').next_back().unwrap();
@@ -25,7 +34,7 @@ fn hello_world_langs_nocolor(file: &str) {
 // for f in test-files/hello/*; do syns --color '\.' $f > tests/.fixtures/color/$(basename $f); done
 #[test_resources("test-files/hello/*")]
 fn hello_world_langs_color(file: &str) {
-    let mut cmd = Command::new(cargo::cargo_bin!());
+    let mut cmd = syns();
     cmd.arg("--color").arg("\\.").arg(file);
 
     let filename = file.split('/').next_back().unwrap();